@@ -0,0 +1,75 @@
+// Map generation cost from 50x50 up to 1000x1000
+// cargo bench --bench generation
+
+#[macro_use]
+extern crate criterion;
+extern crate maps;
+extern crate rand;
+
+use criterion::{BenchmarkId, Criterion};
+use rand::Rng;
+use maps::voronoi::{nearest_region_exhaustive, DistanceMetric, RegionBuckets};
+use maps::{BiomeRegistry, Map, Tile};
+
+static MAP_SIZES: [i32; 5] = [50, 100, 250, 500, 1000];
+
+// Full new_biome for each size, Cave has no sparse trees, Forest runs every pass
+fn bench_new_biome(c: &mut Criterion) {
+    let biomes = BiomeRegistry::builtin();
+    for biome_name in ["Cave", "Forest"].iter() {
+        let biome = biomes.get_or_fallback(biome_name);
+        let mut group = c.benchmark_group(format!("new_biome/{}", biome_name));
+        group.sample_size(10);
+        for size in MAP_SIZES.iter() {
+            group.bench_with_input(BenchmarkId::from_parameter(format!("{}x{}", size, size)), size, |b, &size| {
+                b.iter(|| Map::new_biome(size, size, &biome, 101))
+            });
+        }
+        group.finish();
+    }
+}
+
+// Same number of regions new_biome would pick for the size
+fn random_regions(size: i32) -> Vec<Tile> {
+    let mut rng = Map::seeded_rng(101);
+    let number_of_regions = rng.gen_range(size+size, (size+size)*2);
+    (0..number_of_regions).map(|_| Tile::new(rng.gen_range(0, size), rng.gen_range(0, size), '.', Vec::new())).collect()
+}
+
+// Region assignment alone, the exhaustive search is only run on the smaller sizes (too slow past that)
+fn bench_voronoi(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tiles_to_voronoi");
+    group.sample_size(10);
+    for size in MAP_SIZES.iter() {
+        let regions = random_regions(*size);
+        group.bench_with_input(BenchmarkId::new("buckets", format!("{}x{}", size, size)), size, |b, &size| {
+            b.iter(|| {
+                let region_buckets = RegionBuckets::new(size, size, &regions, &[], DistanceMetric::Manhattan);
+                let mut closest = 0;
+                for y in 0..size {
+                    for x in 0..size {
+                        closest += region_buckets.nearest(x, y);
+                    }
+                }
+                closest
+            })
+        });
+        if *size <= 250 {
+            group.bench_with_input(BenchmarkId::new("exhaustive", format!("{}x{}", size, size)), size, |b, &size| {
+                b.iter(|| {
+                    let mut closest = 0;
+                    for y in 0..size {
+                        for x in 0..size {
+                            closest += nearest_region_exhaustive(&regions, &[], DistanceMetric::Manhattan, x, y);
+                        }
+                    }
+                    closest
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_new_biome, bench_voronoi);
+criterion_main!(benches);
@@ -0,0 +1,42 @@
+// Long distance queries on a 500x500 map, A* over every tile against HPA* over the cluster graph
+// cargo bench --bench pathfinding
+
+#[macro_use]
+extern crate criterion;
+extern crate maps;
+
+use criterion::Criterion;
+use maps::hpa::{ClusterGraph, DEFAULT_CLUSTER_SIZE};
+use maps::{BiomeRegistry, CostProfile, Map, Movement, PathMap};
+
+// Spawn to the far corner of a carved cave, so every walkable tile is reachable
+fn bench_long_path(c: &mut Criterion) {
+    let biomes = BiomeRegistry::builtin();
+    let map = Map::new_biome(500, 500, &biomes.get_or_fallback("Cave"), 7);
+    let tileset = &map.tileset;
+    let profile = CostProfile::walker();
+    let movement = Movement::NoCornerCutting;
+    let start = tileset.index(map.header.spawn_x, map.header.spawn_y);
+    let end = (0..tileset.len()).rev().find(|tile| profile.is_passable(tileset.tiles()[*tile])).expect("Cave has floor tiles");
+
+    let mut group = c.benchmark_group("path_500x500");
+    group.sample_size(10);
+    group.bench_function("astar", |b| {
+        let mut path_map = PathMap::new(tileset);
+        b.iter(|| path_map.find_path(start, end, tileset, &profile, movement))
+    });
+    group.bench_function("hpa_build", |b| {
+        b.iter(|| ClusterGraph::build(tileset, &profile, movement, DEFAULT_CLUSTER_SIZE))
+    });
+    let graph = ClusterGraph::build(tileset, &profile, movement, DEFAULT_CLUSTER_SIZE);
+    group.bench_function("hpa_abstract", |b| {
+        b.iter(|| graph.abstract_path(tileset, &profile, movement, start, end))
+    });
+    group.bench_function("hpa_refined", |b| {
+        b.iter(|| graph.find_path(tileset, &profile, movement, start, end))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_long_path);
+criterion_main!(benches);
@@ -1,53 +1,41 @@
 extends Node2D
 
 onready var gui = get_node("/root/Main/GUI")
-var floor_tile = load("res://scenes/Floor.tscn")
-var wall_tile = load("res://scenes/Wall.tscn")
-var water_tile = load("res://scenes/Water.tscn")
-var sand_tile = load("res://scenes/Sand.tscn")
-var tree_tile = load("res://scenes/Tree.tscn")
+var tileset = load("res://scenes/Tiles.tres") # floor, wall, water, sand and tree tiles for the map TileMaps
 var player_tile = load("res://scenes/Player.tscn")
 var selector_point = load("res://scenes/Selector.tscn")
 var star_point = load("res://scenes/Star.tscn")
 var canvas_mod = load("res://scenes/CanvasModulate.tscn")
-var map = {}
+var tiles = PoolByteArray() # tile ids, row-major (y * size_x + x), header["tile_chars"] has the char of each id
+var header = {} # map size, biome, seed, default tiles, spawn...
 var vd_map = {}
 var vd_tile_size = 8
 var tile_size = 32 # Sprite size
 var player
 var rust_map_name = "test101.map"
 
-var map_store = {} # map_store[map_name][ready(bool)/biome(string)]
-var thread_counter = 0
-var mutex
-var thread
-var semaphore
+var map_store = {} # map_store[map_name][job(int)/ready(bool)/biome(string)]
 var file_name
-var exit_thread
-var thread_busy = false
 var max_maps_to_generate = 5
+var maps_generated = 0
 
-# Timer to run thread ever 20 seconds
+# Timer to queue another map every 2 seconds
 var timer = 0.0
 var reset_timer = 2.0
 
 
 func _ready():
-  mutex = Mutex.new()
-  thread = Thread.new()
-  semaphore = Semaphore.new()
-  exit_thread = false
-  thread.start(self, "_thread_gen_map")
+  # Rust node generates maps on its own worker threads and signals when one is ready
+  var gen_map = get_node("/root/Main/Parent")
+  gen_map.connect("map_ready", self, "_on_map_ready")
+  gen_map.connect("job_cancelled", self, "_on_job_cancelled")
     
 func _process(delta):
   timer += delta
-  if timer > reset_timer and not get_thread_busy():
-    print("attempting to start thread")
+  if timer > reset_timer:
     timer = 0.0
-    if len(map_store) > max_maps_to_generate:
-      print("DEATH TO THREADS", len(map_store))
-      exit_thread = true
-    semaphore.post()
+    if maps_generated < max_maps_to_generate:
+      gen_map()
   update()
   if Input.is_action_just_released("ui_path"):
     test_path_find()
@@ -56,58 +44,29 @@ func _process(delta):
     $CanvasModulate.hide()
     clear_map()
     #get_tree().reload_current_scene() # only fast way to unload map?
-    map = {}
+    tiles = PoolByteArray()
+    header = {}
     vd_map = {}
     
 func _exit_tree():
-    # Set exit condition to true.
-    mutex.lock()
-    exit_thread = true # Protect with Mutex.
-    mutex.unlock()
-
-    # Unblock by posting.
-    semaphore.post()
-
-    # Wait until it exits.
-    thread.wait_to_finish()
-    print("remaining threads: ", get_counter())
- 
-func get_counter():
-    mutex.lock()
-    # Copy counter, protect with Mutex.
-    var counter_value = thread_counter
-    mutex.unlock()
-    return counter_value
+  # Jobs still running are cancelled when the Rust node is freed
+  var gen_map = get_node("/root/Main/Parent")
+  for key in map_store.keys():
+    if not map_store[key]["ready"]:
+      gen_map.godot_cancel_job(map_store[key]["job"])
 
-func get_thread_busy():
-    mutex.lock()
-    # Copy counter, protect with Mutex.
-    var busy = thread_busy
-    mutex.unlock()
-    return busy
+func _on_map_ready(job_id, biome):
+  for key in map_store.keys():
+    if map_store[key]["job"] == job_id:
+      map_store[key]["biome"] = biome
+      map_store[key]["ready"] = true
+      print("[gen_map]-> ", key, " ready: ", biome)
 
-func _thread_gen_map(userdata):
-  while true:
-    mutex.lock()
-    thread_busy = false
-    mutex.unlock()
-    semaphore.wait() # Wait until posted.
-    mutex.lock()
-    thread_busy = true
-    mutex.unlock()
-    mutex.lock()
-    var should_exit = exit_thread # Protect with Mutex.
-    mutex.unlock()
-    
-    if should_exit:
-      print("[THREAD_gen_map]-> exiting thread")
-      break
-    print("[THREAD_gen_map]-> starting thread")
-    mutex.lock()
-    thread_counter += 1
-    mutex.unlock()
-    gen_map()
-    print("[THREAD_gen_map]-> thread finished")
+func _on_job_cancelled(job_id):
+  for key in map_store.keys():
+    if map_store[key]["job"] == job_id:
+      map_store.erase(key)
+      return
   
 func test_path_find():
   var map_path = "resources/maps/"
@@ -116,7 +75,13 @@ func test_path_find():
   
   print("dying1")
   
-  var string_array = gen_map.godot_path_find(map_path + file_name + ".map", "5x5", "15x15")
+  # Keep the map in the Rust node, later path queries don't read the file again
+  if gen_map.godot_map_load(map_path + file_name + ".map") != 0:
+    print(gen_map.godot_last_error())
+    return
+  var string_array = gen_map.godot_map_path_find("5x5", "15x15", "walker", "no_corner_cutting")
+  if string_array.size() == 0:
+    print(gen_map.godot_last_error())
   print(string_array)
   
     
@@ -180,7 +145,10 @@ func play_tiles():
     print("[MAIN]-> exiting play_tiles failure")
     return
   gui.hide()
-  draw_map_tiles()
+  var node = Node2D.new()
+  node.name = file_name
+  add_child(node)
+  draw_map_tilemap(new_tilemap(node, "ground"), new_tilemap(node, "overlay"))
   add_player() 
   if biome == "Ocean" or biome == "Desert" or biome == "Forest": # Detect outside lighting
     $CanvasModulate.hide()
@@ -205,33 +173,23 @@ func detect_old_maps():
   # [IMPORTANT] Loop through files in directory and find which exist
   # Also use /tmp tmpfs if available
   
+# Queue a map on the Rust worker pool, _on_map_ready marks it ready
 func gen_map():
   var gen_map = get_node("/root/Main/Parent")
-  var map_count = len(map_store)
-  var map_path = "resources/maps/"
-  var map_name = "f" + str(map_count)
-  mutex.lock()
-  map_store[map_name] = {}
-  map_store[map_name]["ready"] = false 
-  mutex.unlock()
-  #gen_map.godot_new_map(map_path + map_name + ".map")
+  var map_name = "f" + str(maps_generated)
+  maps_generated += 1
   #var biome = "Forest"
-  #gen_map.godot_new_biome(map_path + map_name + ".map", biome)
-  var biome = gen_map.godot_random_biome(map_path + map_name + ".map")
-  mutex.lock()
-  map_store[map_name]["biome"] = biome 
-  map_store[map_name]["ready"] = true 
-  mutex.unlock()
-  print("[THREAD_gen_map]-> ", map_store)
-  #gen_map.godot_save_map(rust_map_name)
+  #var job = gen_map.godot_generate_async(biome, 50, 50, randi())
+  var job = gen_map.godot_generate_async("", 50, 50, randi()) # "" picks the biome from the seed
+  map_store[map_name] = {"job": job, "ready": false, "biome": ""}
+  print("[gen_map]-> queued ", map_name, " job ", job)
 
-# Loads the map in var map
+# Loads the map in var tiles and var header
 func load_map():
   # If there are no maps to load, fail to load
   if len(map_store) < 1:
     print("map store empty... please wait...")
     return "fail"
-  var f = File.new()
   #f.open("res://resources/maps/50vc11.map", f.READ)
   for key in map_store.keys():
     if map_store[key]["ready"]: #Fix123
@@ -245,30 +203,20 @@ func load_map():
   if not map_store[file_name]["ready"]: # Fix123
     print("map is not available")
     return "fail"
-  # Let thread know you are using it's dictionary
-  mutex.lock()
   map_store[file_name]["ready"] = false #Fix123
   var biome = map_store[file_name]["biome"]
-  mutex.unlock()
-  f.open("res://resources/maps/" + file_name + ".map", f.READ)
-  var json_string = f.get_as_text()
-  var json = JSON.parse(json_string)
-  if json.error == OK:
-    map = json.result
-    extract_vd_points() # extract voronoi regions from map
-  else:
-    print("error with json") 
+  # Finished job becomes the Rust node's resident map, it hands back tile ids and a metadata Dictionary (no json parsing here)
+  var gen_map = get_node("/root/Main/Parent")
+  if gen_map.godot_job_take_map(map_store[file_name]["job"]) != 0:
+    print(gen_map.godot_last_error())
+    return "fail"
+  # Saved so test_path_find can load it again
+  if gen_map.godot_map_save("resources/maps/" + file_name + ".map") != 0:
+    print(gen_map.godot_last_error())
+  header = gen_map.godot_map_metadata()
+  tiles = gen_map.godot_map_tiles()
   return biome
 
-# Extract vd_points
-func extract_vd_points():
-  var regex = RegEx.new()
-  regex.compile("v\\d+")
-  for key in map.keys():
-    var result = regex.search(key)
-    if result:
-      vd_map[key] = map[key]
-
 func draw_map_points():
   for key in vd_map.keys():
     var s = star_point.instance()
@@ -297,73 +245,27 @@ func draw_map_lines():
 # Try and avoid queue_free and just edit the same objects
 func update_map_tiles():
   load_map()
-  if not map:
+  if tiles.size() == 0:
     return
   
-# Draws the tiles from map
-func draw_map_tiles():
-  var node = Node2D.new()
-  #node.set_name(file_name)
-  node.name = file_name
-  add_child(node)
-  if not map:
-    return
-  # Add canvas light/shadows  
-#  var mod = canvas_mod.instance()
-#  mod.set_name("canvas_mod")
-#  node.add_child(mod)
-  for key in map.keys():
-    if map[key]['c'] == "#":
-      var t = wall_tile.instance()
-      #t.key = key
-      t.position = update_pos(map[key]['x'], map[key]['y'])
-      node.add_child(t)
-    elif map[key]['c'] == ".":
-      var t = floor_tile.instance()
-      #t.key = key
-      t.position = update_pos(map[key]['x'], map[key]['y'])
-      node.add_child(t)
-    elif map[key]['c'] == "~":
-      var t = water_tile.instance()
-      #t.key = key
-      t.position = update_pos(map[key]['x'], map[key]['y'])
-      node.add_child(t)
-    elif map[key]['c'] == ",":
-      var t = sand_tile.instance()
-      #t.key = key
-      t.position = update_pos(map[key]['x'], map[key]['y'])
-      node.add_child(t)
-    elif map[key]['c'] == "t":
-      # Add a ground tile under te tree
-      if map["default_floor"]['c'] == ',':
-        var t = sand_tile.instance()
-        t.position = update_pos(map[key]['x'], map[key]['y'])
-        node.add_child(t)
-      elif map["default_floor"]['c'] == '.':
-        var t = floor_tile.instance()
-        t.position = update_pos(map[key]['x'], map[key]['y'])
-        node.add_child(t)
-      var t = tree_tile.instance()
-      #t.key = key
-      t.position = update_pos(map[key]['x'], map[key]['y'])
-      node.add_child(t)
-  
+# TileSet index for each tile type, used by draw_map_tilemap
+var tilemap_indexes = {"floor": 0, "wall": 1, "water": 2, "sand": 3, "tree": 4, "road": 0, "exit": 0, "bridge": 0} # No road sprites yet
 
-# Draws the tiles from map
-func draw_map_tiles_old():
-  if not map:
-    return
-  for key in map.keys():
-    if map[key]['c'] == "#":
-      var t = wall_tile.instance()
-      t.key = key
-      t.update_pos(map[key]['x'], map[key]['y'])
-      add_child(t)
-    elif map[key]['c'] == ".":
-      var t = floor_tile.instance()
-      t.key = key
-      t.update_pos(map[key]['x'], map[key]['y'])
-      add_child(t)
+# Draws the resident map of the Rust node into two TileMaps (trees on overlay)
+func draw_map_tilemap(ground, overlay):
+  var gen_map = get_node("/root/Main/Parent")
+  if gen_map.godot_map_fill_tilemap(ground, overlay, tilemap_indexes) != 0:
+    print(gen_map.godot_last_error())
+
+# TileMap for one layer of the map, 64px tiles drawn at tile_size like the old tile sprites
+func new_tilemap(node, layer_name):
+  var tilemap = TileMap.new()
+  tilemap.name = layer_name
+  tilemap.tile_set = tileset
+  tilemap.cell_size = Vector2(64, 64)
+  tilemap.scale = Vector2(tile_size / 64.0, tile_size / 64.0)
+  node.add_child(tilemap)
+  return tilemap
 
 # Create the player, pass map information to player
 func add_player(vd_mode=false):
@@ -374,10 +276,11 @@ func add_player(vd_mode=false):
   else:
     player = player_tile.instance()
   player.key = "player"
-  player.map = map
+  player.tiles = tiles
+  player.header = header
   player.vd_map = vd_map
   player.vd_mode = vd_mode
-  player.update_pos(map["mapsize"]["x"] / 2, map["mapsize"]["y"] / 2)
+  player.update_pos(header["spawn_x"], header["spawn_y"])
   add_child(player)
   
   
@@ -4,7 +4,8 @@ var key
 var mapx
 var mapy
 var delay = 0.0
-var map
+var tiles # tile ids from the Rust node, row-major
+var header # map metadata, size_x/size_y and the tile_chars of each id
 var vd_mode = false
 var vd_num = 0
 var vd_map
@@ -27,11 +28,13 @@ func enable_light():
   $Light2D.enabled = true  
   
 func is_floor(x, y):
-  #if x > map["mapsize"]["x"] || map["mapsize"]["y"] / 2)
-  var key = str(x) + "x" + str(y)
-  if map[key]["c"] == "." or map[key]["c"] == ",":
-    # water walking or map[key]["c"] == "~":
-    return true
+  if x < 0 or y < 0 or x >= header["size_x"] or y >= header["size_y"]:
+    return false
+  var id = tiles[y * header["size_x"] + x]
+  if id >= header["tile_chars"].size():
+    return false
+  # Floor, sand, roads, bridges and exits, the Rust library decides what's walkable
+  return get_node("/root/Main/Parent").godot_is_walkable(header["tile_chars"][id])
   
 # This function needs to use voronoi delaunay triangulation
 func get_input_vd():
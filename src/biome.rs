@@ -0,0 +1,267 @@
+// Biome definitions, loaded from json/toml files so new biomes don't need a recompile
+// Built-in biomes live in resources/biomes and are baked into the library, extra directories add/replace biomes
+
+use rand::Rng;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::connectivity::Connectivity;
+use crate::voronoi::DistanceMetric;
+use crate::{Map, MapError, TILE_TYPE};
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)] // Unknown tile types are an error, missing ones are 0.0
+pub struct TileChance { // Used to control the biome tiles on map
+    pub floor: f32, // percentage of map floor
+    pub wall: f32,  // percentage of map wall
+    pub water: f32, // percentage of map water
+    pub sand: f32,
+    pub tree: f32
+}
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)] // Missing flags are off
+pub struct BiomeControl {
+    pub water_edges: bool, // Activate method add_water_edges(), makes floor around water
+    pub outer_wall: bool, // Activate method add_border_walls(), add wall around map
+    pub sparse_trees: bool,
+    pub roads: bool,
+    pub exit_roads: bool,
+    pub exits: bool,
+    pub distance_metric: DistanceMetric, // How tiles pick their closest voronoi region
+    pub region_weights: HashMap<String, [f64; 2]>, // Weighted/Power only: tile type -> [min, max] random weight of its regions (as written, see Biome::region_weights)
+    pub cellular_smoothing: bool, // Activate method smooth_tiles(), runs the smoothing rules after voronoi
+    pub smoothing: SmoothingRules,
+    pub connectivity: Connectivity // Activate method connect_walkable(), carve or prune pockets the spawn can't reach
+}
+// Cellular automata rules for smooth_tiles(), counts are solid tiles out of the 8 neighbors (outside the map counts as solid)
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SmoothingRules {
+    pub iterations: u32,
+    pub birth: Vec<u8>, // Neighbor counts that turn an open tile solid
+    pub survival: Vec<u8>, // Neighbor counts that keep a solid tile solid, other counts open it up
+    pub solid: Vec<String>, // Tile types counted as solid
+    pub tiles: Vec<String> // Tile types the pass is allowed to change, everything else is left alone (but still counted)
+}
+#[derive(Clone, Debug)]
+pub struct Biome { // Used to control advanced biome manipulation
+    pub biome_name: String,
+    pub tile_chance: TileChance,
+    pub biome_control: BiomeControl,
+    pub region_weights: HashMap<char, [f64; 2]>, // biome_control.region_weights checked and keyed by tile char
+    pub default_floor: char,
+    pub default_wall: char
+}
+
+// Layout of a biome definition file, default tiles can be a tile type name ("sand") or the tile char (",")
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BiomeDefinition {
+    name: String,
+    default_floor: String,
+    default_wall: String,
+    tile_chance: TileChance,
+    #[serde(default)]
+    biome_control: BiomeControl
+}
+
+// Definitions shipped with the library, also copied in resources/biomes as examples for designers
+static BUILTIN_BIOMES: [(&str, &str); 5] = [
+    ("cave.json", include_str!("../resources/biomes/cave.json")),
+    ("ocean.json", include_str!("../resources/biomes/ocean.json")),
+    ("underlake.json", include_str!("../resources/biomes/underlake.json")),
+    ("desert.json", include_str!("../resources/biomes/desert.json")),
+    ("forest.json", include_str!("../resources/biomes/forest.json")),
+];
+// Mixed into the seed for random_biome, any constant works as long as it never changes
+static BIOME_PICK_STREAM: u64 = 0x9e37_79b9_7f4a_7c15;
+
+// All biomes available to map generation, in load order (random_biome depends on the order)
+#[derive(Clone)]
+pub struct BiomeRegistry {
+    biomes: Vec<Biome>
+}
+
+impl Biome {
+    // Used for biome names that aren't in the registry
+    pub fn fallback(biome_name: String) -> Biome {
+        Biome {
+            biome_name: biome_name,
+            tile_chance: TileChance{floor: 0.33, wall: 0.33, water: 0.33, sand: 0.0, tree: 0.0},
+            biome_control: BiomeControl{outer_wall: true, water_edges: true, sparse_trees: false,
+                                        roads: false, exit_roads: false, exits: false,
+                                        distance_metric: DistanceMetric::Manhattan, region_weights: HashMap::new(),
+                                        cellular_smoothing: false, smoothing: SmoothingRules::default(),
+                                        connectivity: Connectivity::None},
+            region_weights: HashMap::new(),
+            default_floor: TILE_TYPE.floor,
+            default_wall: TILE_TYPE.wall
+        }
+    }
+    // Parse and validate a biome definition, file_name is only used for error messages and picking json/toml
+    pub fn from_definition(file_name: &str, text: &str) -> Result<Biome, MapError> {
+        let definition: BiomeDefinition = if file_name.ends_with(".toml") {
+            toml::from_str(text).map_err(|e| MapError::Biome(format!("{}: {}", file_name, e)))?
+        } else {
+            serde_json::from_str(text).map_err(|e| MapError::Biome(format!("{}: {}", file_name, e)))?
+        };
+        let chance = &definition.tile_chance;
+        let chances = [chance.floor, chance.wall, chance.water, chance.sand, chance.tree];
+        if chances.iter().any(|c| *c < 0.0) {
+            return Err(MapError::Biome(format!("{}: tile_chance can't be negative", file_name)));
+        }
+        let total: f32 = chances.iter().sum();
+        if (total - 1.0).abs() > 0.01 {
+            return Err(MapError::Biome(format!("{}: tile_chance adds up to {}, should be 1.0", file_name, total)));
+        }
+        let default_floor = match TILE_TYPE.from_name(&definition.default_floor) {
+            Some(c) => c,
+            None => return Err(MapError::Biome(format!("{}: unknown default_floor tile type '{}'", file_name, definition.default_floor))),
+        };
+        let default_wall = match TILE_TYPE.from_name(&definition.default_wall) {
+            Some(c) => c,
+            None => return Err(MapError::Biome(format!("{}: unknown default_wall tile type '{}'", file_name, definition.default_wall))),
+        };
+        let metric = definition.biome_control.distance_metric;
+        // Names and chars can both be used as keys, so "sand" and "," are the same tile type
+        let mut region_weights = HashMap::new();
+        for (tile_name, weight_range) in definition.biome_control.region_weights.iter() {
+            let tile_type = match TILE_TYPE.from_name(tile_name) {
+                Some(c) => c,
+                None => return Err(MapError::Biome(format!("{}: unknown region_weights tile type '{}'", file_name, tile_name))),
+            };
+            if weight_range[0] > weight_range[1] {
+                return Err(MapError::Biome(format!("{}: region_weights for '{}' has min bigger than max", file_name, tile_name)));
+            }
+            if metric == DistanceMetric::Weighted && weight_range[0] <= 0.0 {
+                return Err(MapError::Biome(format!("{}: region_weights for '{}' must be above 0.0 for the weighted metric", file_name, tile_name)));
+            }
+            if metric == DistanceMetric::Power && weight_range[0] < 0.0 {
+                return Err(MapError::Biome(format!("{}: region_weights for '{}' can't be negative for the power metric", file_name, tile_name)));
+            }
+            if region_weights.insert(tile_type, *weight_range).is_some() {
+                return Err(MapError::Biome(format!("{}: region_weights has tile type '{}' more than once", file_name, tile_type)));
+            }
+        }
+        let smoothing = &definition.biome_control.smoothing;
+        for tile_name in smoothing.solid.iter().chain(smoothing.tiles.iter()) {
+            if TILE_TYPE.from_name(tile_name).is_none() {
+                return Err(MapError::Biome(format!("{}: unknown smoothing tile type '{}'", file_name, tile_name)));
+            }
+        }
+        if smoothing.birth.iter().chain(smoothing.survival.iter()).any(|count| *count > 8) {
+            return Err(MapError::Biome(format!("{}: smoothing neighbor counts can't be more than 8", file_name)));
+        }
+        if smoothing.solid.is_empty() {
+            return Err(MapError::Biome(format!("{}: smoothing needs at least one solid tile type", file_name)));
+        }
+        Ok(Biome {
+            biome_name: definition.name,
+            tile_chance: definition.tile_chance,
+            biome_control: definition.biome_control,
+            region_weights: region_weights,
+            default_floor: default_floor,
+            default_wall: default_wall
+        })
+    }
+    // Random weight range for regions of a tile type, tile types missing from region_weights use the metric default
+    pub fn region_weight_range(&self, tile_type: char) -> (f64, f64) {
+        if let Some(weight_range) = self.region_weights.get(&tile_type) {
+            return (weight_range[0], weight_range[1]);
+        }
+        let default_weight = self.biome_control.distance_metric.default_weight();
+        (default_weight, default_weight)
+    }
+    pub fn default_floor(&self) -> char {
+        self.default_floor
+    }
+    pub fn default_wall(&self) -> char {
+        self.default_wall
+    }
+}
+
+// Classic cave rules (B5678/S45678), walls grow into open areas and lone walls disappear
+impl Default for SmoothingRules {
+    fn default() -> SmoothingRules {
+        SmoothingRules {
+            iterations: 4,
+            birth: vec![5, 6, 7, 8],
+            survival: vec![4, 5, 6, 7, 8],
+            solid: vec![String::from("wall")],
+            tiles: vec![String::from("floor"), String::from("wall")]
+        }
+    }
+}
+
+impl BiomeRegistry {
+    // Registry with only the biomes baked into the library
+    pub fn builtin() -> BiomeRegistry {
+        let mut registry = BiomeRegistry { biomes: Vec::new() };
+        for (file_name, text) in BUILTIN_BIOMES.iter() {
+            let biome = Biome::from_definition(file_name, text).expect("Built-in biome definition is invalid");
+            registry.insert(biome);
+        }
+        registry
+    }
+    // Add a biome, replaces any biome with the same name (keeps its place in the order)
+    pub fn insert(&mut self, biome: Biome) {
+        match self.biomes.iter().position(|b| b.biome_name == biome.biome_name) {
+            Some(index) => self.biomes[index] = biome,
+            None => self.biomes.push(biome),
+        }
+    }
+    // Load every .json/.toml definition in a directory (sorted by file name), returns number of biomes loaded
+    // Nothing is added if any file fails to load or validate
+    pub fn load_dir(&mut self, directory: &str) -> Result<usize, MapError> {
+        let entries = fs::read_dir(directory).map_err(|e| MapError::Io {path: directory.to_string(), source: e})?;
+        let mut paths = Vec::new();
+        for entry in entries {
+            let path = entry.map_err(|e| MapError::Io {path: directory.to_string(), source: e})?.path();
+            match path.extension().and_then(|e| e.to_str()) {
+                Some("json") | Some("toml") => paths.push(path),
+                _ => (),
+            }
+        }
+        paths.sort();
+        let mut loaded = Vec::new();
+        for path in paths {
+            loaded.push(BiomeRegistry::load_file(&path)?);
+        }
+        let number_loaded = loaded.len();
+        for biome in loaded {
+            self.insert(biome);
+        }
+        Ok(number_loaded)
+    }
+    // Read and validate a single definition file
+    pub fn load_file(path: &Path) -> Result<Biome, MapError> {
+        let file_name = path.to_string_lossy().to_string();
+        let text = fs::read_to_string(path).map_err(|e| MapError::Io {path: file_name.clone(), source: e})?;
+        Biome::from_definition(&file_name, &text)
+    }
+    pub fn get(&self, biome_name: &str) -> Option<&Biome> {
+        self.biomes.iter().find(|b| b.biome_name == biome_name)
+    }
+    // Unknown biome names get the fallback biome (old behaviour of Biome::new)
+    pub fn get_or_fallback(&self, biome_name: &str) -> Biome {
+        match self.get(biome_name) {
+            Some(biome) => biome.clone(),
+            None => Biome::fallback(biome_name.to_string()),
+        }
+    }
+    pub fn names(&self) -> Vec<String> {
+        self.biomes.iter().map(|b| b.biome_name.clone()).collect()
+    }
+    // Pick a biome name from the seed, same seed and registry always gives the same biome
+    pub fn random_biome(&self, seed: u64) -> String {
+        if self.biomes.is_empty() {
+            return Biome::fallback(String::from("Cave")).biome_name;
+        }
+        // Own stream, so the biome pick isn't tied to the first numbers new_biome draws from the same seed
+        let mut rng = Map::seeded_rng(seed ^ BIOME_PICK_STREAM);
+        let random_biome = rng.gen_range(0, self.biomes.len());
+        self.biomes[random_biome].biome_name.clone()
+    }
+}
@@ -0,0 +1,144 @@
+// World biome layout, continuous elevation/moisture/temperature fields sampled at each map's world position
+// Neighbor maps sit close together in the fields, so biomes come in regions (desert fades into forest, ocean has a coast)
+// Rules pick the biome from the fields and the depth below the surface, so underground levels get their own biomes
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::{MapError, World};
+
+// Default layout, also copied in resources as an example for designers
+static BUILTIN_CLIMATE: &str = include_str!("../resources/climate.json");
+
+// Field values at one world position, all 0.0 to 1.0
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Climate {
+    pub elevation: f64,
+    pub moisture: f64,
+    pub temperature: f64, // Colder the higher the elevation is above sea level
+    pub depth: i32 // Levels below the surface (z 0), levels above count as the surface
+}
+
+// Biome for every position inside all of its ranges (inclusive), missing ranges match anything
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BiomeRule {
+    pub biome: String,
+    #[serde(default = "BiomeRule::any")]
+    pub elevation: [f64; 2],
+    #[serde(default = "BiomeRule::any")]
+    pub moisture: [f64; 2],
+    #[serde(default = "BiomeRule::any")]
+    pub temperature: [f64; 2],
+    #[serde(default = "BiomeRule::surface")]
+    pub depth: [i32; 2] // Surface only unless set
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClimateModel {
+    pub scale: f64, // Maps across one noise feature, bigger makes bigger biome regions
+    pub octaves: u32, // Noise layers, each one half the size and strength of the last
+    pub sea_level: f64, // Elevation where temperature starts to drop
+    pub lapse_rate: f64, // Temperature lost per unit of elevation above sea level
+    pub fallback: String, // Biome for positions no rule matches
+    pub rules: Vec<BiomeRule> // First matching rule wins
+}
+
+impl BiomeRule {
+    fn any() -> [f64; 2] {
+        [0.0, 1.0]
+    }
+    fn surface() -> [i32; 2] {
+        [0, 0]
+    }
+    pub fn matches(&self, climate: &Climate) -> bool {
+        let within = |range: &[f64; 2], value: f64| value >= range[0] && value <= range[1];
+        within(&self.elevation, climate.elevation) && within(&self.moisture, climate.moisture) && within(&self.temperature, climate.temperature)
+            && climate.depth >= self.depth[0] && climate.depth <= self.depth[1]
+    }
+}
+
+impl ClimateModel {
+    // Layout baked into the library
+    pub fn builtin() -> ClimateModel {
+        ClimateModel::from_definition("climate.json", BUILTIN_CLIMATE).expect("Built-in climate definition is invalid")
+    }
+    // Parse and validate a layout, file_name is only used for error messages and picking json/toml
+    pub fn from_definition(file_name: &str, text: &str) -> Result<ClimateModel, MapError> {
+        let model: ClimateModel = if file_name.ends_with(".toml") {
+            toml::from_str(text).map_err(|e| MapError::Biome(format!("{}: {}", file_name, e)))?
+        } else {
+            serde_json::from_str(text).map_err(|e| MapError::Biome(format!("{}: {}", file_name, e)))?
+        };
+        if model.scale <= 0.0 {
+            return Err(MapError::Biome(format!("{}: scale must be above 0.0", file_name)));
+        }
+        if model.octaves == 0 || model.octaves > 16 {
+            return Err(MapError::Biome(format!("{}: octaves must be 1 to 16", file_name)));
+        }
+        for rule in model.rules.iter() {
+            let ranges = [rule.elevation, rule.moisture, rule.temperature];
+            if ranges.iter().any(|range| range[0] > range[1]) || rule.depth[0] > rule.depth[1] {
+                return Err(MapError::Biome(format!("{}: rule for '{}' has a min bigger than its max", file_name, rule.biome)));
+            }
+        }
+        Ok(model)
+    }
+    pub fn load_file(path: &Path) -> Result<ClimateModel, MapError> {
+        let file_name = path.to_string_lossy().to_string();
+        let text = fs::read_to_string(path).map_err(|e| MapError::Io {path: file_name.clone(), source: e})?;
+        ClimateModel::from_definition(&file_name, &text)
+    }
+
+    // Fields at a world position, the same seed always gives the same climate
+    // Every level samples the fields at its x/y, so e.g. wet ground sits over underground lakes
+    pub fn climate_at(&self, seed: u64, x: i32, y: i32, z: i32) -> Climate {
+        let (x, y) = (f64::from(x) / self.scale, f64::from(y) / self.scale);
+        let elevation = self.fractal_noise(seed, 0, x, y);
+        let moisture = self.fractal_noise(seed, 1, x, y);
+        let cooling = self.lapse_rate * (elevation - self.sea_level).max(0.0);
+        let temperature = (self.fractal_noise(seed, 2, x, y) - cooling).max(0.0);
+        Climate {elevation: elevation, moisture: moisture, temperature: temperature, depth: (-z).max(0)}
+    }
+    // Biome name of the first rule matching the climate, or the fallback
+    pub fn biome_for(&self, climate: &Climate) -> String {
+        match self.rules.iter().find(|rule| rule.matches(climate)) {
+            Some(rule) => rule.biome.clone(),
+            None => self.fallback.clone(),
+        }
+    }
+    pub fn biome_at(&self, seed: u64, x: i32, y: i32, z: i32) -> String {
+        self.biome_for(&self.climate_at(seed, x, y, z))
+    }
+
+    // Octaves of value noise added together, stretched back out to 0.0 to 1.0 (adding layers pulls values to the middle)
+    fn fractal_noise(&self, seed: u64, field: u8, x: f64, y: f64) -> f64 {
+        let (mut total, mut amplitude, mut frequency, mut spread) = (0.0, 1.0, 1.0, 0.0);
+        for octave in 0..self.octaves {
+            total += amplitude * (ClimateModel::value_noise(seed, field, octave, x * frequency, y * frequency) - 0.5);
+            spread += amplitude * amplitude;
+            amplitude *= 0.5;
+            frequency *= 2.0;
+        }
+        (0.5 + total / spread.sqrt()).clamp(0.0, 1.0)
+    }
+    // Random value on each lattice point, smoothly blended in between
+    fn value_noise(seed: u64, field: u8, octave: u32, x: f64, y: f64) -> f64 {
+        let (x0, y0) = (x.floor(), y.floor());
+        let smooth = |t: f64| t * t * (3.0 - 2.0 * t);
+        let (tx, ty) = (smooth(x - x0), smooth(y - y0));
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let corner = |cx: i64, cy: i64| ClimateModel::lattice_value(seed, field, octave, cx, cy);
+        let top = corner(x0, y0) + (corner(x0 + 1, y0) - corner(x0, y0)) * tx;
+        let bottom = corner(x0, y0 + 1) + (corner(x0 + 1, y0 + 1) - corner(x0, y0 + 1)) * tx;
+        top + (bottom - top) * ty
+    }
+    fn lattice_value(seed: u64, field: u8, octave: u32, x: i64, y: i64) -> f64 {
+        let bytes = seed.to_le_bytes().iter().cloned().chain(Some(field)).chain(octave.to_le_bytes().iter().cloned())
+            .chain(x.to_le_bytes().iter().cloned()).chain(y.to_le_bytes().iter().cloned()).collect::<Vec<u8>>();
+        let hash = World::fnv1a(bytes.into_iter());
+        (hash >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
@@ -0,0 +1,198 @@
+// Flood fill helpers for making sure the player can walk from spawn to the rest of the map
+// Connectivity is always checked with side neighbors only, so it holds for every pathfinding mode
+
+use serde::Deserialize;
+use std::collections::VecDeque;
+
+use crate::grid::{TileGrid, SIDE_OFFSETS};
+
+// What the connectivity pass does with walkable tiles the spawn can't reach, picked by the biome definition
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Connectivity {
+    None, // Leave the map alone (old behaviour)
+    Carve, // Dig the shortest tunnels from the spawn area to every pocket
+    Prune // Fill every pocket with wall
+}
+
+impl Default for Connectivity {
+    fn default() -> Connectivity {
+        Connectivity::None
+    }
+}
+
+// Label every group of side-connected tiles where is_member is true, 0 means not a member
+// Labels start at 1 and are numbered in row-major order of each group's first tile, sizes[label] is the group size
+pub fn label_components<F: Fn(char) -> bool>(tileset: &TileGrid, is_member: F) -> (Vec<u32>, Vec<usize>) {
+    let mut labels = vec![0; tileset.len()];
+    let mut sizes = vec![0];
+    let mut queue = VecDeque::new();
+    for start in 0..tileset.len() {
+        if labels[start] != 0 || !is_member(tileset.tiles()[start]) {
+            continue;
+        }
+        let label = sizes.len() as u32;
+        let mut size = 0;
+        labels[start] = label;
+        queue.push_back(start);
+        while let Some(index) = queue.pop_front() {
+            size += 1;
+            let (x, y) = tileset.position(index);
+            for (dx, dy) in SIDE_OFFSETS.iter() {
+                if let Some(c) = tileset.get(x + dx, y + dy) {
+                    let neighbor = tileset.index(x + dx, y + dy);
+                    if labels[neighbor] == 0 && is_member(c) {
+                        labels[neighbor] = label;
+                        queue.push_back(neighbor);
+                    }
+                }
+            }
+        }
+        sizes.push(size);
+    }
+    (labels, sizes)
+}
+
+// Every tile side-connected to start through walkable tiles
+pub fn reachable_from<F: Fn(char) -> bool>(tileset: &TileGrid, start: usize, is_walkable: F) -> Vec<bool> {
+    let mut reached = vec![false; tileset.len()];
+    if start >= tileset.len() || !is_walkable(tileset.tiles()[start]) {
+        return reached;
+    }
+    let mut queue = VecDeque::new();
+    reached[start] = true;
+    queue.push_back(start);
+    while let Some(index) = queue.pop_front() {
+        let (x, y) = tileset.position(index);
+        for (dx, dy) in SIDE_OFFSETS.iter() {
+            if let Some(c) = tileset.get(x + dx, y + dy) {
+                let neighbor = tileset.index(x + dx, y + dy);
+                if !reached[neighbor] && is_walkable(c) {
+                    reached[neighbor] = true;
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+    }
+    reached
+}
+
+// Percent of walkable tiles that are reachable from start (100 when there is nothing to walk on)
+pub fn reachable_percent<F: Fn(char) -> bool + Copy>(tileset: &TileGrid, start: usize, is_walkable: F) -> f32 {
+    let reached = reachable_from(tileset, start, is_walkable);
+    let walkable = tileset.tiles().iter().filter(|c| is_walkable(**c)).count();
+    if walkable == 0 {
+        return 100.0;
+    }
+    let reachable = reached.iter().filter(|r| **r).count();
+    reachable as f32 * 100.0 / walkable as f32
+}
+
+// Connect every walkable pocket to start by turning the fewest possible tiles into floor_tile
+// 0-1 BFS where walkable tiles are free and everything else costs one dug tile, border tiles are only dug if dig_border
+pub fn carve_tunnels<F: Fn(char) -> bool>(tileset: &mut TileGrid, start: usize, floor_tile: char, dig_border: bool, is_walkable: F) {
+    let (labels, sizes) = label_components(tileset, &is_walkable);
+    let mut dug = vec![u32::max_value(); tileset.len()];
+    let mut parent = vec![usize::max_value(); tileset.len()];
+    let mut deque = VecDeque::new();
+    dug[start] = 0;
+    deque.push_back(start);
+    while let Some(index) = deque.pop_front() {
+        let (x, y) = tileset.position(index);
+        for (dx, dy) in SIDE_OFFSETS.iter() {
+            let c = match tileset.get(x + dx, y + dy) {
+                Some(c) => c,
+                None => continue,
+            };
+            let walkable = is_walkable(c);
+            if !walkable && !dig_border && tileset.is_border(x + dx, y + dy) {
+                continue;
+            }
+            let neighbor = tileset.index(x + dx, y + dy);
+            let cost = dug[index] + if walkable { 0 } else { 1 };
+            if cost < dug[neighbor] {
+                dug[neighbor] = cost;
+                parent[neighbor] = index;
+                if walkable {
+                    deque.push_front(neighbor);
+                } else {
+                    deque.push_back(neighbor);
+                }
+            }
+        }
+    }
+    // Cheapest tile of each pocket (first in row-major order on ties), then dig back towards start
+    let mut closest = vec![usize::max_value(); sizes.len()];
+    for index in 0..tileset.len() {
+        let label = labels[index] as usize;
+        if label == 0 || dug[index] == u32::max_value() {
+            continue;
+        }
+        if closest[label] == usize::max_value() || dug[index] < dug[closest[label]] {
+            closest[label] = index;
+        }
+    }
+    for pocket_tile in closest {
+        let mut index = pocket_tile;
+        while index != usize::max_value() && index != start {
+            if !is_walkable(tileset.tiles()[index]) {
+                tileset.tiles_mut()[index] = floor_tile;
+            }
+            index = parent[index];
+        }
+    }
+}
+
+// Dig the tunnel from start to end that turns the fewest tiles into floor_tile, used to reach exits on the map edge
+// Same 0-1 BFS as carve_tunnels, border tiles other than end are never dug so the outer wall only opens at end
+pub fn carve_tunnel<F: Fn(char) -> bool>(tileset: &mut TileGrid, start: usize, end: usize, floor_tile: char, is_walkable: F) {
+    let mut dug = vec![u32::max_value(); tileset.len()];
+    let mut parent = vec![usize::max_value(); tileset.len()];
+    let mut deque = VecDeque::new();
+    dug[start] = 0;
+    deque.push_back(start);
+    while let Some(index) = deque.pop_front() {
+        if index == end {
+            break;
+        }
+        let (x, y) = tileset.position(index);
+        for (dx, dy) in SIDE_OFFSETS.iter() {
+            let c = match tileset.get(x + dx, y + dy) {
+                Some(c) => c,
+                None => continue,
+            };
+            let neighbor = tileset.index(x + dx, y + dy);
+            let walkable = is_walkable(c);
+            if !walkable && neighbor != end && tileset.is_border(x + dx, y + dy) {
+                continue;
+            }
+            let cost = dug[index] + if walkable { 0 } else { 1 };
+            if cost < dug[neighbor] {
+                dug[neighbor] = cost;
+                parent[neighbor] = index;
+                if walkable {
+                    deque.push_front(neighbor);
+                } else {
+                    deque.push_back(neighbor);
+                }
+            }
+        }
+    }
+    let mut index = end;
+    while index != usize::max_value() && index != start {
+        if !is_walkable(tileset.tiles()[index]) {
+            tileset.tiles_mut()[index] = floor_tile;
+        }
+        index = parent[index];
+    }
+}
+
+// Turn every walkable tile start can't reach into wall_tile
+pub fn prune_pockets<F: Fn(char) -> bool + Copy>(tileset: &mut TileGrid, start: usize, wall_tile: char, is_walkable: F) {
+    let reached = reachable_from(tileset, start, is_walkable);
+    for (index, reachable) in reached.iter().enumerate() {
+        if !reachable && is_walkable(tileset.tiles()[index]) {
+            tileset.tiles_mut()[index] = wall_tile;
+        }
+    }
+}
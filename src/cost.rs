@@ -0,0 +1,163 @@
+// Movement cost profiles for pathfinding, each query picks what it can walk through and how much each tile costs
+// Built-in: walker (gameplay), swimmer, tunneler (digs through walls), road_builder is only used by map generation
+// Each step costs the tile cost times SIDE_STEP or DIAGONAL_STEP (10/14 keeps diagonals close to sqrt(2) in integers)
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+use crate::{MapError, TILE_TYPE};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CostProfile {
+    pub name: String,
+    costs: Vec<Option<i32>>, // Cost to enter each ascii tile char, None is impassable
+    other: Option<i32> // Cost for any tile char not listed
+}
+
+// Layout of a profile definition, tile types can be names ("water") or chars ("~"), null is impassable
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CostProfileDefinition {
+    name: String,
+    costs: BTreeMap<String, Option<i32>>,
+    #[serde(default)]
+    other: Option<i32>
+}
+
+static PROFILE_NAMES: [&str; 3] = ["walker", "swimmer", "tunneler"];
+
+pub static SIDE_STEP: i32 = 10;
+pub static DIAGONAL_STEP: i32 = 14;
+// Highest tile cost a profile definition can set, a step is at most MAX_TILE_COST * DIAGONAL_STEP
+// Path costs still add up with saturating_add so very long expensive paths can't overflow
+pub static MAX_TILE_COST: i32 = 10_000;
+
+// Which neighbors a path can step to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Movement {
+    Sides, // 4-way
+    Diagonal, // 8-way, can squeeze diagonally between two blocked tiles
+    NoCornerCutting // 8-way, diagonal steps need both side tiles next to them to be passable
+}
+
+impl Default for Movement {
+    fn default() -> Movement {
+        Movement::Sides
+    }
+}
+
+impl Movement {
+    // Names used by Godot ("sides", "diagonal", "no_corner_cutting")
+    pub fn from_name(name: &str) -> Option<Movement> {
+        match name {
+            "sides" => Some(Movement::Sides),
+            "diagonal" => Some(Movement::Diagonal),
+            "no_corner_cutting" => Some(Movement::NoCornerCutting),
+            _ => None,
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Movement::Sides => "sides",
+            Movement::Diagonal => "diagonal",
+            Movement::NoCornerCutting => "no_corner_cutting",
+        }
+    }
+    // Fewest step cost units between two tiles (manhattan for sides, octile for diagonal movement)
+    pub fn distance(&self, dx: i32, dy: i32) -> i32 {
+        let dx = dx.abs();
+        let dy = dy.abs();
+        match self {
+            Movement::Sides => (dx + dy) * SIDE_STEP,
+            Movement::Diagonal | Movement::NoCornerCutting => {
+                dx.max(dy) * SIDE_STEP + dx.min(dy) * (DIAGONAL_STEP - SIDE_STEP)
+            },
+        }
+    }
+}
+
+impl CostProfile {
+    // Costs must be 1 to MAX_TILE_COST (the A* heuristic counts on the minimum)
+    pub fn new(name: &str, costs: &[(char, Option<i32>)], other: Option<i32>) -> CostProfile {
+        let mut profile = CostProfile {name: name.to_string(), costs: vec![other; 128], other: other};
+        for (tile_type, cost) in costs.iter() {
+            if (*tile_type as u32) < 128 {
+                profile.costs[*tile_type as usize] = *cost;
+            }
+        }
+        profile
+    }
+    // Only walks on the tiles the connectivity pass keeps reachable
+    pub fn walker() -> CostProfile {
+        CostProfile::new("walker", &[
+            (TILE_TYPE.floor, Some(1)), (TILE_TYPE.sand, Some(1)), (TILE_TYPE.road, Some(1)), (TILE_TYPE.exit, Some(1)),
+            (TILE_TYPE.bridge, Some(1))
+        ], None)
+    }
+    // Prefers water, can still cross land slowly
+    pub fn swimmer() -> CostProfile {
+        CostProfile::new("swimmer", &[
+            (TILE_TYPE.water, Some(1)), (TILE_TYPE.floor, Some(3)), (TILE_TYPE.sand, Some(3)),
+            (TILE_TYPE.road, Some(3)), (TILE_TYPE.exit, Some(3)), (TILE_TYPE.bridge, Some(3))
+        ], None)
+    }
+    // Goes anywhere inside the map, digging through walls is expensive so roads follow open ground when they can
+    pub fn tunneler() -> CostProfile {
+        CostProfile::new("tunneler", &[
+            (TILE_TYPE.floor, Some(1)), (TILE_TYPE.sand, Some(1)), (TILE_TYPE.road, Some(1)), (TILE_TYPE.exit, Some(1)),
+            (TILE_TYPE.bridge, Some(1)), (TILE_TYPE.tree, Some(4)), (TILE_TYPE.water, Some(8)), (TILE_TYPE.wall, Some(20))
+        ], Some(20))
+    }
+    // Roads join existing roads when they can, stay on open ground, clear trees and only bridge water when going around
+    // is a lot longer, walls are a last resort (exits always have a tunnel from spawn to follow)
+    pub fn road_builder() -> CostProfile {
+        CostProfile::new("road_builder", &[
+            (TILE_TYPE.road, Some(1)), (TILE_TYPE.bridge, Some(1)), (TILE_TYPE.exit, Some(1)),
+            (TILE_TYPE.floor, Some(2)), (TILE_TYPE.sand, Some(2)), (TILE_TYPE.tree, Some(6)),
+            (TILE_TYPE.water, Some(16)), (TILE_TYPE.wall, Some(40))
+        ], Some(40))
+    }
+    pub fn builtin(name: &str) -> Option<CostProfile> {
+        match name {
+            "walker" => Some(CostProfile::walker()),
+            "swimmer" => Some(CostProfile::swimmer()),
+            "tunneler" => Some(CostProfile::tunneler()),
+            _ => None,
+        }
+    }
+    pub fn builtin_names() -> Vec<String> {
+        PROFILE_NAMES.iter().map(|name| name.to_string()).collect()
+    }
+    // Parse and validate a json profile definition
+    pub fn from_json(text: &str) -> Result<CostProfile, MapError> {
+        let definition: CostProfileDefinition = serde_json::from_str(text)
+            .map_err(|e| MapError::CostProfile(format!("invalid cost profile: {}", e)))?;
+        let mut costs = Vec::new();
+        for (tile_name, cost) in definition.costs.iter() {
+            let tile_type = match TILE_TYPE.from_name(tile_name) {
+                Some(c) => c,
+                None => return Err(MapError::CostProfile(format!("{}: unknown tile type '{}'", definition.name, tile_name))),
+            };
+            costs.push((tile_type, *cost));
+        }
+        if costs.iter().map(|(_, cost)| cost).chain(Some(&definition.other)).any(|cost| cost.map_or(false, |c| c < 1 || c > MAX_TILE_COST)) {
+            return Err(MapError::CostProfile(format!("{}: costs must be 1 to {}", definition.name, MAX_TILE_COST)));
+        }
+        Ok(CostProfile::new(&definition.name, &costs, definition.other))
+    }
+    // Cost to enter a tile of tile_type, None if impassable
+    pub fn cost(&self, tile_type: char) -> Option<i32> {
+        if (tile_type as u32) < 128 {
+            self.costs[tile_type as usize]
+        } else {
+            self.other
+        }
+    }
+    pub fn is_passable(&self, tile_type: char) -> bool {
+        self.cost(tile_type).is_some()
+    }
+    // Cheapest step this profile can take, scales the A* heuristic
+    pub fn min_cost(&self) -> i32 {
+        self.costs.iter().filter_map(|cost| *cost).min().unwrap_or(1).max(1)
+    }
+}
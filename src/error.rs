@@ -0,0 +1,66 @@
+// Everything that can go wrong loading, saving and pathfinding, returned instead of panicking inside Godot
+// Godot facing methods turn these into an error code and keep the message for godot_last_error()
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum MapError {
+    Io {path: String, source: io::Error}, // Opening, reading or writing a file
+    Decompress {path: String, source: io::Error}, // File isn't valid gzip data
+    Json {path: String, source: serde_json::Error}, // File isn't a valid map/world file
+    MissingMetadata {path: String, field: String}, // Map or world file is missing a field (or it can't be read)
+    InvalidTileKey(String), // Tile key isn't "XxY"
+    TileOutOfBounds {x: i64, y: i64, size_x: i32, size_y: i32}, // x, y as given, coordinates from Godot can be past i32
+    Biome(String), // Biome or climate definition failed to parse or validate
+    CostProfile(String), // Cost profile is unknown or failed to parse or validate
+    NotFound(String), // Named flow field, the node's resident map or world, a finished job map or a world map doesn't exist
+    InvalidTileType(String), // Tile type name or char isn't in TILE_TYPE
+    InvalidMapSize {size_x: i64, size_y: i64} // Map to generate is smaller than 1x1 or bigger than MAX_MAP_SIZE
+}
+
+impl MapError {
+    // Code returned to Godot, 0 is used for success
+    pub fn code(&self) -> i64 {
+        match self {
+            MapError::Io {..} => 1,
+            MapError::Decompress {..} => 2,
+            MapError::Json {..} => 3,
+            MapError::MissingMetadata {..} => 4,
+            MapError::InvalidTileKey(_) => 5,
+            MapError::TileOutOfBounds {..} => 6,
+            MapError::Biome(_) => 7,
+            MapError::CostProfile(_) => 8,
+            MapError::NotFound(_) => 9,
+            MapError::InvalidTileType(_) => 10,
+            MapError::InvalidMapSize {..} => 11,
+        }
+    }
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapError::Io {path, source} => write!(f, "{}: {}", path, source),
+            MapError::Decompress {path, source} => write!(f, "{}: unable to decompress: {}", path, source),
+            MapError::Json {path, source} => write!(f, "{}: invalid json: {}", path, source),
+            MapError::MissingMetadata {path, field} => write!(f, "{}: missing or invalid '{}'", path, field),
+            MapError::InvalidTileKey(key) => write!(f, "invalid tile key '{}', expected XxY", key),
+            MapError::InvalidTileType(name) => write!(f, "unknown tile type '{}'", name),
+            MapError::TileOutOfBounds {x, y, size_x, size_y} => write!(f, "tile {}x{} is outside the {}x{} map", x, y, size_x, size_y),
+            MapError::InvalidMapSize {size_x, size_y} => write!(f, "map size {}x{} must be 1 to {} tiles on each side", size_x, size_y, crate::MAX_MAP_SIZE),
+            MapError::Biome(message) | MapError::CostProfile(message) | MapError::NotFound(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for MapError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MapError::Io {source, ..} | MapError::Decompress {source, ..} => Some(source),
+            MapError::Json {source, ..} => Some(source),
+            _ => None,
+        }
+    }
+}
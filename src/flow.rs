@@ -0,0 +1,150 @@
+// Flow fields (Dijkstra maps) for many agents chasing the same goals, one search covers every agent on the map
+// costs[tile] is the cheapest cost from that tile to the closest goal, agents step to the neighbor given by next_step
+// Moving the goals a few tiles only recomputes the tiles whose best route changed
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use crate::grid::NEIGHBOR_OFFSETS;
+use crate::{CostProfile, Movement, PathMap, TileGrid};
+
+pub static UNREACHABLE: i32 = i32::max_value();
+
+#[derive(Clone, Debug)]
+pub struct FlowField {
+    pub profile: CostProfile,
+    pub movement: Movement,
+    pub goals: Vec<usize>, // grid indexes, sorted
+    costs: Vec<i32>, // cost from each tile to the closest goal, UNREACHABLE if no goal can be reached
+    next: Vec<usize>, // neighbor to step to from each tile, usize::max_value() on goals and unreachable tiles
+    goal_of: Vec<usize> // goal each tile's route ends on, used to find the tiles to redo when a goal is removed
+}
+
+impl FlowField {
+    pub fn new(tileset: &TileGrid, profile: &CostProfile, movement: Movement, goals: &[usize]) -> FlowField {
+        let mut field = FlowField {
+            profile: profile.clone(),
+            movement: movement,
+            goals: Vec::new(),
+            costs: vec![UNREACHABLE; tileset.len()],
+            next: vec![usize::max_value(); tileset.len()],
+            goal_of: vec![usize::max_value(); tileset.len()]
+        };
+        field.set_goals(tileset, goals);
+        field
+    }
+
+    // Move the goals, new goals are spread first so tiles that switch to them keep their route,
+    // then everything still routed to a removed goal is cleared and filled back in from the tiles around it
+    pub fn set_goals(&mut self, tileset: &TileGrid, goals: &[usize]) {
+        let mut goals: Vec<usize> = goals.iter().cloned().filter(|goal| *goal < tileset.len()).collect();
+        goals.sort();
+        goals.dedup();
+        let added: Vec<usize> = goals.iter().cloned().filter(|goal| self.goals.binary_search(goal).is_err()).collect();
+        let removed: Vec<usize> = self.goals.iter().cloned().filter(|goal| goals.binary_search(goal).is_err()).collect();
+        self.goals = goals;
+
+        let mut open_list: BinaryHeap<Reverse<(i32, usize)>> = BinaryHeap::new();
+        for goal in added {
+            self.costs[goal] = 0;
+            self.next[goal] = usize::max_value();
+            self.goal_of[goal] = goal;
+            open_list.push(Reverse((0, goal)));
+        }
+        self.spread(tileset, open_list);
+
+        if removed.is_empty() {
+            return;
+        }
+        let mut cleared = Vec::new();
+        for tile in 0..tileset.len() {
+            if self.costs[tile] != UNREACHABLE && removed.binary_search(&self.goal_of[tile]).is_ok() {
+                self.costs[tile] = UNREACHABLE;
+                self.next[tile] = usize::max_value();
+                self.goal_of[tile] = usize::max_value();
+                cleared.push(tile);
+            }
+        }
+        // Cleared tiles start from their best neighbor that still has a route
+        let mut open_list: BinaryHeap<Reverse<(i32, usize)>> = BinaryHeap::new();
+        for tile in cleared {
+            let (x, y) = tileset.position(tile);
+            for (dx, dy) in NEIGHBOR_OFFSETS.iter() {
+                if !tileset.in_bounds(x + dx, y + dy) {
+                    continue;
+                }
+                let neighbor = tileset.index(x + dx, y + dy);
+                if self.costs[neighbor] == UNREACHABLE {
+                    continue;
+                }
+                if let Some(step_cost) = PathMap::move_cost(tileset, &self.profile, self.movement, tile, neighbor) {
+                    if self.costs[neighbor].saturating_add(step_cost) < self.costs[tile] {
+                        self.costs[tile] = self.costs[neighbor].saturating_add(step_cost);
+                        self.next[tile] = neighbor;
+                        self.goal_of[tile] = self.goal_of[neighbor];
+                    }
+                }
+            }
+            if self.costs[tile] != UNREACHABLE {
+                open_list.push(Reverse((self.costs[tile], tile)));
+            }
+        }
+        self.spread(tileset, open_list);
+    }
+
+    // Dijkstra backwards from the tiles in open_list, a neighbor improves if stepping onto the current tile is cheaper
+    fn spread(&mut self, tileset: &TileGrid, mut open_list: BinaryHeap<Reverse<(i32, usize)>>) {
+        while let Some(Reverse((cost, current))) = open_list.pop() {
+            if cost != self.costs[current] {
+                continue;
+            }
+            let (x, y) = tileset.position(current);
+            for (dx, dy) in NEIGHBOR_OFFSETS.iter() {
+                if !tileset.in_bounds(x + dx, y + dy) {
+                    continue;
+                }
+                let neighbor = tileset.index(x + dx, y + dy);
+                let step_cost = match PathMap::move_cost(tileset, &self.profile, self.movement, neighbor, current) {
+                    Some(step_cost) => step_cost,
+                    None => continue,
+                };
+                if cost.saturating_add(step_cost) < self.costs[neighbor] {
+                    self.costs[neighbor] = cost.saturating_add(step_cost);
+                    self.next[neighbor] = current;
+                    self.goal_of[neighbor] = self.goal_of[current];
+                    open_list.push(Reverse((cost.saturating_add(step_cost), neighbor)));
+                }
+            }
+        }
+    }
+
+    // Cost from a tile to the closest goal, None if no goal can be reached
+    pub fn cost(&self, tile: usize) -> Option<i32> {
+        match self.costs.get(tile) {
+            Some(cost) if *cost != UNREACHABLE => Some(*cost),
+            _ => None,
+        }
+    }
+    pub fn costs(&self) -> &[i32] {
+        &self.costs
+    }
+    // Neighbor to move to from tile, None on a goal or when no goal can be reached
+    pub fn next_step(&self, tile: usize) -> Option<usize> {
+        match self.next.get(tile) {
+            Some(next) if *next != usize::max_value() => Some(*next),
+            _ => None,
+        }
+    }
+    // Index into NEIGHBOR_OFFSETS of each tile's next step, -1 on goals and unreachable tiles
+    pub fn directions(&self, tileset: &TileGrid) -> Vec<i32> {
+        (0..tileset.len()).map(|tile| {
+            let next = match self.next_step(tile) {
+                Some(next) => next,
+                None => return -1,
+            };
+            let (x, y) = tileset.position(tile);
+            let (next_x, next_y) = tileset.position(next);
+            NEIGHBOR_OFFSETS.iter().position(|offset| *offset == (next_x - x, next_y - y)).map_or(-1, |direction| direction as i32)
+        }).collect()
+    }
+}
@@ -0,0 +1,152 @@
+// Dense row-major storage for map tiles, replaces the "XxY" keyed HashMap during generation and pathfinding
+// to_tileset/from_tileset convert to and from the old HashMap<String, Tile> layout used by legacy map files
+
+use std::collections::HashMap;
+
+use crate::{MapError, Tile, TILE_TYPE};
+
+// Offsets for side neighbors first, then corners (same order update_all_neighbors used)
+pub static SIDE_OFFSETS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+pub static NEIGHBOR_OFFSETS: [(i32, i32); 8] = [(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (-1, -1), (1, -1), (-1, 1)];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TileGrid {
+    size_x: i32,
+    size_y: i32,
+    tiles: Vec<char> // tile type of (x, y) stored at y * size_x + x
+}
+
+impl TileGrid {
+    // New grid with every tile set to tile_type
+    pub fn new(size_x: i32, size_y: i32, tile_type: char) -> TileGrid {
+        let size_x = size_x.max(0);
+        let size_y = size_y.max(0);
+        TileGrid {size_x: size_x, size_y: size_y, tiles: vec![tile_type; (size_x * size_y) as usize]}
+    }
+    pub fn size_x(&self) -> i32 {
+        self.size_x
+    }
+    pub fn size_y(&self) -> i32 {
+        self.size_y
+    }
+    // Number of tiles in the grid
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+    pub fn in_bounds(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && x < self.size_x && y < self.size_y
+    }
+    // True for tiles on the outside edge of the map
+    pub fn is_border(&self, x: i32, y: i32) -> bool {
+        x == 0 || y == 0 || x == self.size_x - 1 || y == self.size_y - 1
+    }
+    // Row-major index of (x, y), caller makes sure it's in bounds
+    pub fn index(&self, x: i32, y: i32) -> usize {
+        (y * self.size_x + x) as usize
+    }
+    // Row-major index of an "XxY" tile key, errors if the key is malformed or outside the map
+    pub fn key_index(&self, key: &str) -> Result<usize, MapError> {
+        let (x, y) = Tile::parse_key(key).ok_or_else(|| MapError::InvalidTileKey(key.to_string()))?;
+        if !self.in_bounds(x, y) {
+            return Err(MapError::TileOutOfBounds {x: i64::from(x), y: i64::from(y), size_x: self.size_x, size_y: self.size_y});
+        }
+        Ok(self.index(x, y))
+    }
+    // (x, y) of a row-major index
+    pub fn position(&self, index: usize) -> (i32, i32) {
+        (index as i32 % self.size_x, index as i32 / self.size_x)
+    }
+    // Tile type at (x, y), None if outside the map
+    pub fn get(&self, x: i32, y: i32) -> Option<char> {
+        if self.in_bounds(x, y) {
+            Some(self.tiles[self.index(x, y)])
+        } else {
+            None
+        }
+    }
+    // Tile type at (x, y), panics if outside the map (like indexing the old tileset with a bad key)
+    pub fn at(&self, x: i32, y: i32) -> char {
+        match self.get(x, y) {
+            Some(c) => c,
+            None => panic!("Tile {}x{} is outside the {}x{} map", x, y, self.size_x, self.size_y),
+        }
+    }
+    // Change the tile type at (x, y), ignored if outside the map
+    pub fn set(&mut self, x: i32, y: i32, tile_type: char) {
+        if self.in_bounds(x, y) {
+            let index = self.index(x, y);
+            self.tiles[index] = tile_type;
+        }
+    }
+    // Tile types in row-major order
+    pub fn tiles(&self) -> &[char] {
+        &self.tiles
+    }
+    pub fn tiles_mut(&mut self) -> &mut [char] {
+        &mut self.tiles
+    }
+    // Every row from y = 0 down
+    pub fn rows(&self) -> impl Iterator<Item = &[char]> + '_ {
+        self.tiles.chunks(self.size_x.max(1) as usize)
+    }
+    // Every (x, y, tile_type) in row-major order
+    pub fn iter(&self) -> impl Iterator<Item = (i32, i32, char)> + '_ {
+        let size_x = self.size_x;
+        self.tiles.iter().enumerate().map(move |(i, c)| (i as i32 % size_x, i as i32 / size_x, *c))
+    }
+    // Side and corner neighbors of (x, y) that are inside the map
+    pub fn neighbors(&self, x: i32, y: i32) -> impl Iterator<Item = (i32, i32, char)> + '_ {
+        NEIGHBOR_OFFSETS.iter().filter_map(move |(dx, dy)| self.get(x + dx, y + dy).map(|c| (x + dx, y + dy, c)))
+    }
+    // Side neighbors of (x, y) that are inside the map
+    pub fn side_neighbors(&self, x: i32, y: i32) -> impl Iterator<Item = (i32, i32, char)> + '_ {
+        SIDE_OFFSETS.iter().filter_map(move |(dx, dy)| self.get(x + dx, y + dy).map(|c| (x + dx, y + dy, c)))
+    }
+    // One string per row, the layout used in map files
+    pub fn to_rows(&self) -> Vec<String> {
+        self.rows().map(|row| row.iter().collect()).collect()
+    }
+    // Tile id of every tile in row-major order (TileType::id), what Godot gets instead of rows of chars
+    pub fn to_ids(&self) -> Vec<u8> {
+        self.tiles.iter().map(|c| TILE_TYPE.id(*c)).collect()
+    }
+    // Build a grid from map file rows, short or missing rows are filled with missing_tile
+    pub fn from_rows(size_x: i32, size_y: i32, rows: &[String], missing_tile: char) -> TileGrid {
+        let mut grid = TileGrid::new(size_x, size_y, missing_tile);
+        for (y, row) in rows.iter().enumerate().take(size_y.max(0) as usize) {
+            for (x, c) in row.chars().enumerate().take(size_x.max(0) as usize) {
+                grid.set(x as i32, y as i32, c);
+            }
+        }
+        grid
+    }
+    // Convert to the old "XxY" keyed tileset, neighbor keys are only stored away from the edges (like update_all_neighbors did)
+    pub fn to_tileset(&self) -> HashMap<String, Tile> {
+        let mut tileset = HashMap::with_capacity(self.tiles.len());
+        for (x, y, c) in self.iter() {
+            let mut neighbors = Vec::new();
+            if !(x <= 1 || y <= 1 || x >= self.size_x-1 || y >= self.size_y-1) {
+                for (dx, dy) in NEIGHBOR_OFFSETS.iter() {
+                    neighbors.push(Tile::key(x + dx, y + dy));
+                }
+            }
+            let tile = Tile::new(x, y, c, neighbors);
+            tileset.insert(tile.get_tile_key(), tile);
+        }
+        tileset
+    }
+    // Build a grid from an old tileset, keys that aren't "XxY" tiles (metadata) are skipped
+    // Tiles missing from the tileset are filled with missing_tile
+    pub fn from_tileset(size_x: i32, size_y: i32, tileset: &HashMap<String, Tile>, missing_tile: char) -> TileGrid {
+        let mut grid = TileGrid::new(size_x, size_y, missing_tile);
+        for (key, tile) in tileset.iter() {
+            if Tile::parse_key(key).is_some() {
+                grid.set(tile.x, tile.y, tile.c);
+            }
+        }
+        grid
+    }
+}
@@ -0,0 +1,409 @@
+// Hierarchical pathfinding (HPA*), the map is split into square clusters with entrance nodes on the borders between them
+// Cheapest costs between entrances of the same cluster are precomputed, a query searches that small abstract graph
+// and each abstract step is refined on demand with a search limited to one cluster
+// Paths are near-optimal (they can only cross cluster borders at entrances), the graph is cached next to the map file
+
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::grid::NEIGHBOR_OFFSETS;
+use crate::{CostProfile, Map, MapError, Movement, PathMap, PathResult, TileGrid};
+
+pub static DEFAULT_CLUSTER_SIZE: i32 = 16;
+// Open border runs at least this long get an entrance at both ends and the middle, shorter runs only get the middle one
+static LONG_ENTRANCE: usize = 6;
+// Bump when the cache layout or entrance placement changes, older cache files get rebuilt
+static CACHE_VERSION: u32 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AbstractEdge {
+    pub to: usize, // node index
+    pub cost: i32 // same units as PathResult::cost
+}
+
+// Abstract graph of one map for one cost profile and movement
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClusterGraph {
+    pub version: u32,
+    pub checksum: u64, // tiles, profile costs, movement and cluster size the graph was built from
+    pub size_x: i32,
+    pub size_y: i32,
+    pub cluster_size: i32,
+    pub profile: String,
+    pub movement: String,
+    pub nodes: Vec<usize>, // grid index of each entrance tile
+    pub edges: Vec<Vec<AbstractEdge>>, // outgoing edges of each node, either one step across a border or through one cluster
+    #[serde(skip)]
+    cluster_nodes: Vec<Vec<usize>> // nodes inside each cluster, rebuilt after loading
+}
+
+// Tiles x0..x1, y0..y1 of one cluster
+#[derive(Clone, Copy, Debug)]
+struct Bounds {
+    x0: i32,
+    y0: i32,
+    x1: i32,
+    y1: i32
+}
+
+// Dijkstra limited to one cluster, costs are from start (or to start for a reverse search)
+struct ClusterSearch {
+    bounds: Bounds,
+    costs: Vec<i32>,
+    parents: Vec<usize> // grid index of the previous tile, indexed like costs
+}
+
+impl ClusterGraph {
+    // Place entrances along every cluster border, then connect the entrances inside each cluster
+    pub fn build(tileset: &TileGrid, profile: &CostProfile, movement: Movement, cluster_size: i32) -> ClusterGraph {
+        let cluster_size = cluster_size.max(2);
+        let mut graph = ClusterGraph {
+            version: CACHE_VERSION,
+            checksum: ClusterGraph::checksum(tileset, profile, movement, cluster_size),
+            size_x: tileset.size_x(),
+            size_y: tileset.size_y(),
+            cluster_size: cluster_size,
+            profile: profile.name.clone(),
+            movement: movement.name().to_string(),
+            nodes: Vec::new(),
+            edges: Vec::new(),
+            cluster_nodes: Vec::new()
+        };
+        let mut node_of: HashMap<usize, usize> = HashMap::new();
+        // Borders between cluster columns, each pair is (left tile, right tile)
+        for x in (cluster_size..graph.size_x).step_by(cluster_size as usize) {
+            for y0 in (0..graph.size_y).step_by(cluster_size as usize) {
+                let pairs: Vec<(usize, usize)> = (y0..(y0 + cluster_size).min(graph.size_y))
+                    .map(|y| (tileset.index(x - 1, y), tileset.index(x, y))).collect();
+                graph.add_entrances(tileset, profile, movement, &mut node_of, &pairs);
+            }
+        }
+        // Borders between cluster rows, each pair is (top tile, bottom tile)
+        for y in (cluster_size..graph.size_y).step_by(cluster_size as usize) {
+            for x0 in (0..graph.size_x).step_by(cluster_size as usize) {
+                let pairs: Vec<(usize, usize)> = (x0..(x0 + cluster_size).min(graph.size_x))
+                    .map(|x| (tileset.index(x, y - 1), tileset.index(x, y))).collect();
+                graph.add_entrances(tileset, profile, movement, &mut node_of, &pairs);
+            }
+        }
+        graph.index_clusters(tileset);
+        // Cheapest cost between every pair of entrances that can reach each other without leaving their cluster
+        for cluster in 0..graph.cluster_nodes.len() {
+            let bounds = graph.bounds(cluster);
+            for &from in graph.cluster_nodes[cluster].iter() {
+                let search = ClusterSearch::new(tileset, profile, movement, bounds, graph.nodes[from], None, false);
+                for &to in graph.cluster_nodes[cluster].iter() {
+                    if to == from {
+                        continue;
+                    }
+                    if let Some(cost) = search.cost(tileset, graph.nodes[to]) {
+                        graph.edges[from].push(AbstractEdge {to: to, cost: cost});
+                    }
+                }
+            }
+        }
+        graph
+    }
+
+    // Split one side of a cluster border into open runs (both tiles passable with the same costs as the rest of the run)
+    // and add entrance nodes for each run, splitting on cost keeps cheap gaps in expensive terrain from being skipped
+    fn add_entrances(&mut self, tileset: &TileGrid, profile: &CostProfile, movement: Movement,
+                     node_of: &mut HashMap<usize, usize>, pairs: &[(usize, usize)]) {
+        let pair_costs = |i: usize| -> Option<(i32, i32)> {
+            let (a, b) = *pairs.get(i)?;
+            Some((profile.cost(tileset.tiles()[a])?, profile.cost(tileset.tiles()[b])?))
+        };
+        let mut run_start = None;
+        for i in 0..=pairs.len() {
+            let costs = pair_costs(i);
+            if let Some(start) = run_start {
+                if costs != pair_costs(start) {
+                    let end = i - 1;
+                    let entrances = if end - start + 1 >= LONG_ENTRANCE { vec![start, (start + end) / 2, end] } else { vec![(start + end) / 2] };
+                    for entrance in entrances {
+                        let (a, b) = pairs[entrance];
+                        let node_a = self.node(node_of, a);
+                        let node_b = self.node(node_of, b);
+                        // Side steps between two passable tiles always have a cost
+                        if let Some(cost) = PathMap::move_cost(tileset, profile, movement, a, b) {
+                            self.edges[node_a].push(AbstractEdge {to: node_b, cost: cost});
+                        }
+                        if let Some(cost) = PathMap::move_cost(tileset, profile, movement, b, a) {
+                            self.edges[node_b].push(AbstractEdge {to: node_a, cost: cost});
+                        }
+                    }
+                    run_start = None;
+                }
+            }
+            if run_start.is_none() && costs.is_some() {
+                run_start = Some(i);
+            }
+        }
+    }
+
+    // Node index of an entrance tile, added if it isn't a node yet (corner tiles can be on two borders)
+    fn node(&mut self, node_of: &mut HashMap<usize, usize>, tile: usize) -> usize {
+        let nodes = &mut self.nodes;
+        let edges = &mut self.edges;
+        *node_of.entry(tile).or_insert_with(|| {
+            nodes.push(tile);
+            edges.push(Vec::new());
+            nodes.len() - 1
+        })
+    }
+
+    fn clusters_x(&self) -> i32 {
+        (self.size_x + self.cluster_size - 1) / self.cluster_size
+    }
+    fn clusters_y(&self) -> i32 {
+        (self.size_y + self.cluster_size - 1) / self.cluster_size
+    }
+    fn cluster_of(&self, tileset: &TileGrid, tile: usize) -> usize {
+        let (x, y) = tileset.position(tile);
+        ((y / self.cluster_size) * self.clusters_x() + x / self.cluster_size) as usize
+    }
+    fn bounds(&self, cluster: usize) -> Bounds {
+        let x0 = (cluster as i32 % self.clusters_x()) * self.cluster_size;
+        let y0 = (cluster as i32 / self.clusters_x()) * self.cluster_size;
+        Bounds {x0: x0, y0: y0, x1: (x0 + self.cluster_size).min(self.size_x), y1: (y0 + self.cluster_size).min(self.size_y)}
+    }
+    fn index_clusters(&mut self, tileset: &TileGrid) {
+        let mut cluster_nodes = vec![Vec::new(); (self.clusters_x() * self.clusters_y()) as usize];
+        for (node, tile) in self.nodes.iter().enumerate() {
+            cluster_nodes[self.cluster_of(tileset, *tile)].push(node);
+        }
+        self.cluster_nodes = cluster_nodes;
+    }
+
+    // Cheapest route through the abstract graph, tiles are only start, the entrances passed through and end
+    // Refine it all at once with refine or one step at a time with refine_step
+    pub fn abstract_path(&self, tileset: &TileGrid, profile: &CostProfile, movement: Movement, start_node: usize, end_node: usize) -> Option<PathResult> {
+        if start_node >= tileset.len() || end_node >= tileset.len() {
+            return None;
+        }
+        if start_node == end_node {
+            return Some(PathResult {tiles: vec![start_node], cost: 0});
+        }
+        let start_cluster = self.cluster_of(tileset, start_node);
+        let end_cluster = self.cluster_of(tileset, end_node);
+        // Start and end are joined to the entrances of their own cluster for this query only
+        let start_search = ClusterSearch::new(tileset, profile, movement, self.bounds(start_cluster), start_node, None, false);
+        let end_search = ClusterSearch::new(tileset, profile, movement, self.bounds(end_cluster), end_node, None, true);
+        let start_id = self.nodes.len();
+        let end_id = self.nodes.len() + 1;
+        let tile_of = |id: usize| if id == start_id { start_node } else if id == end_id { end_node } else { self.nodes[id] };
+        let (end_x, end_y) = tileset.position(end_node);
+        let min_cost = profile.min_cost();
+        let heuristic = |id: usize| {
+            let (x, y) = tileset.position(tile_of(id));
+            movement.distance(end_x - x, end_y - y) * min_cost
+        };
+
+        let mut g = vec![i32::max_value(); self.nodes.len() + 2];
+        let mut parent = vec![usize::max_value(); self.nodes.len() + 2];
+        let mut closed = vec![false; self.nodes.len() + 2];
+        let mut open_list: BinaryHeap<Reverse<(i32, usize)>> = BinaryHeap::new();
+        g[start_id] = 0;
+        open_list.push(Reverse((heuristic(start_id), start_id)));
+        while let Some(Reverse((_, current))) = open_list.pop() {
+            if closed[current] {
+                continue;
+            }
+            closed[current] = true;
+            if current == end_id {
+                break;
+            }
+            let mut steps: Vec<(usize, i32)> = Vec::new();
+            if current == start_id {
+                for &node in self.cluster_nodes[start_cluster].iter() {
+                    if let Some(cost) = start_search.cost(tileset, self.nodes[node]) {
+                        steps.push((node, cost));
+                    }
+                }
+                if start_cluster == end_cluster {
+                    if let Some(cost) = start_search.cost(tileset, end_node) {
+                        steps.push((end_id, cost));
+                    }
+                }
+            } else {
+                steps.extend(self.edges[current].iter().map(|edge| (edge.to, edge.cost)));
+                if self.cluster_of(tileset, self.nodes[current]) == end_cluster {
+                    if let Some(cost) = end_search.cost(tileset, self.nodes[current]) {
+                        steps.push((end_id, cost));
+                    }
+                }
+            }
+            for (next, cost) in steps {
+                if !closed[next] && g[current].saturating_add(cost) < g[next] {
+                    g[next] = g[current].saturating_add(cost);
+                    parent[next] = current;
+                    open_list.push(Reverse((g[next].saturating_add(heuristic(next)), next)));
+                }
+            }
+        }
+        if g[end_id] == i32::max_value() {
+            return None;
+        }
+        let mut tiles = vec![end_node];
+        let mut current = end_id;
+        while current != start_id {
+            current = parent[current];
+            tiles.push(tile_of(current));
+        }
+        tiles.reverse();
+        Some(PathResult {tiles: tiles, cost: g[end_id]})
+    }
+
+    // Every tile between two neighboring tiles of an abstract path (both included)
+    pub fn refine_step(&self, tileset: &TileGrid, profile: &CostProfile, movement: Movement, from_node: usize, to_node: usize) -> Vec<usize> {
+        let cluster = self.cluster_of(tileset, from_node);
+        if cluster != self.cluster_of(tileset, to_node) {
+            // Only border crossings join two clusters, and they are a single step
+            return vec![from_node, to_node];
+        }
+        let search = ClusterSearch::new(tileset, profile, movement, self.bounds(cluster), from_node, Some(to_node), false);
+        search.path(tileset, from_node, to_node)
+    }
+
+    // Full tile path of an abstract path, the cost doesn't change
+    pub fn refine(&self, tileset: &TileGrid, profile: &CostProfile, movement: Movement, abstract_path: &PathResult) -> PathResult {
+        let mut tiles = vec![abstract_path.tiles[0]];
+        for step in abstract_path.tiles.windows(2) {
+            tiles.extend(self.refine_step(tileset, profile, movement, step[0], step[1]).into_iter().skip(1));
+        }
+        PathResult {tiles: tiles, cost: abstract_path.cost}
+    }
+
+    // Abstract search then refine the whole path, same result layout as PathMap::find_path
+    pub fn find_path(&self, tileset: &TileGrid, profile: &CostProfile, movement: Movement, start_node: usize, end_node: usize) -> Option<PathResult> {
+        let abstract_path = self.abstract_path(tileset, profile, movement, start_node, end_node)?;
+        Some(self.refine(tileset, profile, movement, &abstract_path))
+    }
+
+    // Hash of everything the graph depends on (FNV-1a), a cache file with another checksum is out of date
+    pub fn checksum(tileset: &TileGrid, profile: &CostProfile, movement: Movement, cluster_size: i32) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let values = [CACHE_VERSION, tileset.size_x() as u32, tileset.size_y() as u32, cluster_size as u32];
+        let tiles = tileset.tiles().iter().map(|c| *c as u32);
+        let movement_name = movement.name().chars().map(|c| c as u32);
+        // Every ascii tile cost plus one non-ascii char for the profile's other cost
+        let costs = (0..128u8).map(char::from).chain(Some('\u{80}')).map(|c| profile.cost(c).map_or(0, |cost| cost as u32 + 1));
+        for value in values.iter().cloned().chain(tiles).chain(movement_name).chain(costs) {
+            for byte in value.to_le_bytes().iter() {
+                hash ^= u64::from(*byte);
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        }
+        hash
+    }
+    pub fn is_current(&self, tileset: &TileGrid, profile: &CostProfile, movement: Movement, cluster_size: i32) -> bool {
+        self.version == CACHE_VERSION && self.checksum == ClusterGraph::checksum(tileset, profile, movement, cluster_size.max(2))
+    }
+
+    // Cache file next to the map, one per profile and movement since each gives a different graph
+    pub fn cache_file(map_file: &str, profile: &CostProfile, movement: Movement) -> String {
+        format!("{}.{}.{}.hpa", map_file, profile.name, movement.name())
+    }
+    // Cache files are always compressed
+    pub fn load(filename: &str, tileset: &TileGrid) -> Result<ClusterGraph, MapError> {
+        let s = Map::read_file(filename, true)?;
+        let mut graph: ClusterGraph = serde_json::from_str(&s).map_err(|e| MapError::Json {path: filename.to_string(), source: e})?;
+        if graph.size_x != tileset.size_x() || graph.size_y != tileset.size_y() || graph.cluster_size < 2 {
+            return Err(MapError::MissingMetadata {path: filename.to_string(), field: String::from("size")});
+        }
+        graph.index_clusters(tileset);
+        Ok(graph)
+    }
+    pub fn save(&self, filename: &str) -> Result<(), MapError> {
+        let serialized = serde_json::to_string(self).expect("Cluster graphs always serialize");
+        Map::write_file(filename, serialized.as_bytes(), true)
+    }
+    // Cached graph for a map file if it still matches the tiles, otherwise build it again and overwrite the cache
+    pub fn load_or_build(map_file: &str, tileset: &TileGrid, profile: &CostProfile, movement: Movement, cluster_size: i32) -> Result<ClusterGraph, MapError> {
+        let cache_file = ClusterGraph::cache_file(map_file, profile, movement);
+        if let Ok(graph) = ClusterGraph::load(&cache_file, tileset) {
+            if graph.is_current(tileset, profile, movement, cluster_size) {
+                return Ok(graph);
+            }
+        }
+        let graph = ClusterGraph::build(tileset, profile, movement, cluster_size);
+        graph.save(&cache_file)?;
+        Ok(graph)
+    }
+}
+
+impl Bounds {
+    fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x0 && y >= self.y0 && x < self.x1 && y < self.y1
+    }
+}
+
+impl ClusterSearch {
+    // Stops early once target is settled, reverse follows steps backwards so costs are from each tile to start
+    fn new(tileset: &TileGrid, profile: &CostProfile, movement: Movement, bounds: Bounds, start: usize, target: Option<usize>, reverse: bool) -> ClusterSearch {
+        let area = ((bounds.x1 - bounds.x0) * (bounds.y1 - bounds.y0)) as usize;
+        let mut search = ClusterSearch {bounds: bounds, costs: vec![i32::max_value(); area], parents: vec![usize::max_value(); area]};
+        let mut closed = vec![false; area];
+        let mut open_list: BinaryHeap<Reverse<(i32, usize)>> = BinaryHeap::new();
+        let start_local = search.local(tileset, start);
+        search.costs[start_local] = 0;
+        open_list.push(Reverse((0, start)));
+        while let Some(Reverse((g, current))) = open_list.pop() {
+            let current_local = search.local(tileset, current);
+            if closed[current_local] {
+                continue;
+            }
+            closed[current_local] = true;
+            if Some(current) == target {
+                break;
+            }
+            let (x, y) = tileset.position(current);
+            for (dx, dy) in NEIGHBOR_OFFSETS.iter() {
+                if !bounds.contains(x + dx, y + dy) {
+                    continue;
+                }
+                let neighbor = tileset.index(x + dx, y + dy);
+                let step_cost = if reverse {
+                    PathMap::move_cost(tileset, profile, movement, neighbor, current)
+                } else {
+                    PathMap::move_cost(tileset, profile, movement, current, neighbor)
+                };
+                let step_cost = match step_cost {
+                    Some(cost) => cost,
+                    None => continue,
+                };
+                let neighbor_local = search.local(tileset, neighbor);
+                if g.saturating_add(step_cost) < search.costs[neighbor_local] {
+                    search.costs[neighbor_local] = g.saturating_add(step_cost);
+                    search.parents[neighbor_local] = current;
+                    open_list.push(Reverse((g.saturating_add(step_cost), neighbor)));
+                }
+            }
+        }
+        search
+    }
+    fn local(&self, tileset: &TileGrid, tile: usize) -> usize {
+        let (x, y) = tileset.position(tile);
+        ((y - self.bounds.y0) * (self.bounds.x1 - self.bounds.x0) + (x - self.bounds.x0)) as usize
+    }
+    fn cost(&self, tileset: &TileGrid, tile: usize) -> Option<i32> {
+        let (x, y) = tileset.position(tile);
+        if !self.bounds.contains(x, y) {
+            return None;
+        }
+        let cost = self.costs[self.local(tileset, tile)];
+        if cost == i32::max_value() { None } else { Some(cost) }
+    }
+    // Forward searches only, start to end (both included)
+    fn path(&self, tileset: &TileGrid, start: usize, end: usize) -> Vec<usize> {
+        let mut tiles = vec![end];
+        let mut current = end;
+        while current != start {
+            current = self.parents[self.local(tileset, current)];
+            tiles.push(current);
+        }
+        tiles.reverse();
+        tiles
+    }
+}
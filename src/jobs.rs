@@ -0,0 +1,234 @@
+// Background map generation, a small pool of worker threads owned by the Godot node
+// Each job has an id to poll its status/progress, cancel it or take the finished map
+// Finished jobs are queued so the node can emit its signals from the main thread
+// Jobs leave the table when they're cancelled or their map is taken, done maps nobody takes are dropped oldest first
+
+use std::collections::{HashMap, VecDeque};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::{Biome, Map, MapError};
+
+pub static DEFAULT_WORKERS: usize = 2;
+pub static DEFAULT_UNCLAIMED_MAPS: usize = 16; // Done maps kept for take_map, one more drops the oldest
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Done,
+    Cancelled
+}
+
+impl JobStatus {
+    // Names used by Godot
+    pub fn name(&self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Done => "done",
+            JobStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+struct Job {
+    id: u64,
+    size_x: i32,
+    size_y: i32,
+    biome: Biome,
+    seed: u64
+}
+
+struct JobState {
+    status: JobStatus,
+    progress: f32,
+    biome_name: String,
+    cancel: Arc<AtomicBool>,
+    map: Option<Map> // Set once the job is done, until take_map
+}
+
+// Everything the workers and the pool share
+struct JobTable {
+    jobs: HashMap<u64, JobState>,
+    finished: Vec<(u64, JobStatus)>, // Done or cancelled since the last call to finished()
+    unclaimed: VecDeque<u64>, // Done jobs whose map wasn't taken yet, oldest first
+    max_unclaimed: usize
+}
+
+impl JobTable {
+    // Job stopped without a map, it's forgotten right away
+    fn cancelled(&mut self, id: u64) {
+        self.jobs.remove(&id);
+        self.finished.push((id, JobStatus::Cancelled));
+    }
+    // Job finished with a map, drops the oldest map nobody took if there are too many
+    fn done(&mut self, id: u64, map: Map) {
+        if let Some(state) = self.jobs.get_mut(&id) {
+            state.status = JobStatus::Done;
+            state.map = Some(map);
+            self.unclaimed.push_back(id);
+            self.finished.push((id, JobStatus::Done));
+        }
+        while self.unclaimed.len() > self.max_unclaimed {
+            if let Some(oldest) = self.unclaimed.pop_front() {
+                self.jobs.remove(&oldest);
+            }
+        }
+    }
+}
+
+pub struct JobPool {
+    sender: Option<Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+    table: Arc<Mutex<JobTable>>,
+    next_id: u64
+}
+
+impl JobPool {
+    pub fn new(workers: usize) -> JobPool {
+        JobPool::with_unclaimed_maps(workers, DEFAULT_UNCLAIMED_MAPS)
+    }
+    // Pool that keeps at most max_unclaimed done maps around for take_map
+    pub fn with_unclaimed_maps(workers: usize, max_unclaimed: usize) -> JobPool {
+        let (sender, receiver) = channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let table = Arc::new(Mutex::new(JobTable {jobs: HashMap::new(), finished: Vec::new(), unclaimed: VecDeque::new(), max_unclaimed: max_unclaimed}));
+        let workers = (0..workers.max(1)).map(|_| {
+            let receiver = Arc::clone(&receiver);
+            let table = Arc::clone(&table);
+            thread::spawn(move || JobPool::work(receiver, table))
+        }).collect();
+        JobPool {sender: Some(sender), workers: workers, table: table, next_id: 1}
+    }
+
+    // Worker thread, takes jobs until the pool is dropped
+    fn work(receiver: Arc<Mutex<Receiver<Job>>>, table: Arc<Mutex<JobTable>>) {
+        loop {
+            let job = match receiver.lock().expect("Job queue lock poisoned").recv() {
+                Ok(job) => job,
+                Err(_) => return,
+            };
+            let cancel = {
+                let mut table = table.lock().expect("Job table lock poisoned");
+                let state = match table.jobs.get_mut(&job.id) {
+                    Some(state) => state,
+                    None => continue,
+                };
+                if state.status != JobStatus::Queued {
+                    continue;
+                }
+                state.status = JobStatus::Running;
+                Arc::clone(&state.cancel)
+            };
+            // A panicking generation ends as cancelled instead of taking the worker down with it
+            let map = panic::catch_unwind(AssertUnwindSafe(|| {
+                Map::new_biome_with_progress(job.size_x, job.size_y, &job.biome, job.seed, &mut |progress| {
+                    if let Some(state) = table.lock().expect("Job table lock poisoned").jobs.get_mut(&job.id) {
+                        state.progress = progress;
+                    }
+                    !cancel.load(Ordering::SeqCst)
+                })
+            })).unwrap_or(None);
+            let mut table = table.lock().expect("Job table lock poisoned");
+            // A job cancelled after its last pass still counts as cancelled
+            match map {
+                Some(map) if !cancel.load(Ordering::SeqCst) => table.done(job.id, map),
+                _ => table.cancelled(job.id),
+            }
+        }
+    }
+
+    // Queue a map for generation, returns the job id (sizes must be 1 to MAX_MAP_SIZE)
+    pub fn generate(&mut self, size_x: i32, size_y: i32, biome: Biome, seed: u64) -> Result<u64, MapError> {
+        Map::check_size(i64::from(size_x), i64::from(size_y))?;
+        let id = self.next_id;
+        self.next_id += 1;
+        self.table.lock().expect("Job table lock poisoned").jobs.insert(id, JobState {
+            status: JobStatus::Queued,
+            progress: 0.0,
+            biome_name: biome.biome_name.clone(),
+            cancel: Arc::new(AtomicBool::new(false)),
+            map: None
+        });
+        let job = Job {id: id, size_x: size_x, size_y: size_y, biome: biome, seed: seed};
+        // Workers only stop when the pool is dropped, a job that can't be queued just ends as cancelled
+        let queued = match self.sender.as_ref() {
+            Some(sender) => sender.send(job).is_ok(),
+            None => false,
+        };
+        if !queued {
+            self.table.lock().expect("Job table lock poisoned").cancelled(id);
+        }
+        Ok(id)
+    }
+    pub fn status(&self, id: u64) -> Option<JobStatus> {
+        self.table.lock().expect("Job table lock poisoned").jobs.get(&id).map(|state| state.status.clone())
+    }
+    // 0.0 to 1.0, updated after each generation pass
+    pub fn progress(&self, id: u64) -> Option<f32> {
+        self.table.lock().expect("Job table lock poisoned").jobs.get(&id).map(|state| state.progress)
+    }
+    pub fn biome_name(&self, id: u64) -> Option<String> {
+        self.table.lock().expect("Job table lock poisoned").jobs.get(&id).map(|state| state.biome_name.clone())
+    }
+    // Stop a queued or running job (running jobs stop after their current pass), false if it already finished
+    pub fn cancel(&self, id: u64) -> bool {
+        let mut table = self.table.lock().expect("Job table lock poisoned");
+        let state = match table.jobs.get_mut(&id) {
+            Some(state) => state,
+            None => return false,
+        };
+        match state.status {
+            JobStatus::Queued => {
+                // Workers skip it when they get to it
+                table.cancelled(id);
+                true
+            },
+            JobStatus::Running => {
+                state.cancel.store(true, Ordering::SeqCst);
+                true
+            },
+            _ => false,
+        }
+    }
+    // Map of a done job, the job is forgotten afterwards
+    // None for jobs that aren't done, were cancelled, or whose map was dropped for newer unclaimed maps
+    pub fn take_map(&self, id: u64) -> Option<Map> {
+        let mut table = self.table.lock().expect("Job table lock poisoned");
+        match table.jobs.get(&id) {
+            Some(state) if state.status == JobStatus::Done => {},
+            _ => return None,
+        }
+        table.unclaimed.retain(|unclaimed| *unclaimed != id);
+        table.jobs.remove(&id).and_then(|state| state.map)
+    }
+    // Jobs that are done or cancelled since the last call, with their status
+    pub fn finished(&self) -> Vec<(u64, JobStatus)> {
+        self.table.lock().expect("Job table lock poisoned").finished.drain(..).collect()
+    }
+    // Jobs still in the table: queued, running, or done with a map nobody took yet
+    pub fn len(&self) -> usize {
+        self.table.lock().expect("Job table lock poisoned").jobs.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// Cancel everything and wait for the workers, so no thread outlives the Godot node
+impl Drop for JobPool {
+    fn drop(&mut self) {
+        let ids: Vec<u64> = self.table.lock().expect("Job table lock poisoned").jobs.keys().cloned().collect();
+        for id in ids {
+            self.cancel(id);
+        }
+        self.sender = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
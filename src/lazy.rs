@@ -0,0 +1,68 @@
+// Worlds that generate each map the first time it's asked for (walking through an exit, a path search...)
+// Maps come from World::generate_map so they only depend on the world seed and their position
+// The last few maps used stay resident, the least recently used one is dropped once there are too many (it's saved already)
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::{BiomeRegistry, Map, MapError, MapStatus, World};
+
+pub static DEFAULT_RESIDENT_MAPS: usize = 9; // The map the player is on and its 8 neighbors
+
+pub struct LazyWorld {
+    pub world: World,
+    biomes: BiomeRegistry,
+    capacity: usize,
+    resident: HashMap<String, Map>,
+    order: VecDeque<String> // Resident map names, least recently used first
+}
+
+impl LazyWorld {
+    pub fn new(world: World, biomes: BiomeRegistry, capacity: usize) -> LazyWorld {
+        LazyWorld {world: world, biomes: biomes, capacity: capacity.max(1), resident: HashMap::new(), order: VecDeque::new()}
+    }
+
+    // Resident map, loaded or generated (then the world file is saved) if it isn't resident
+    pub fn map(&mut self, map_name: &str) -> Result<&Map, MapError> {
+        if self.resident.contains_key(map_name) {
+            self.touch(map_name);
+        } else {
+            // Pending entries (planned, or never saved in a legacy world file) get generated too
+            let generated = match self.world.maps.get(map_name) {
+                Some(entry) => entry.status != MapStatus::Generated,
+                None => true,
+            };
+            let map = self.world.generate_map(&self.biomes, map_name)?;
+            if generated {
+                World::save_world(&self.world)?;
+            }
+            self.insert(map_name, map);
+        }
+        Ok(&self.resident[map_name])
+    }
+    pub fn map_at(&mut self, x: i32, y: i32, z: i32) -> Result<&Map, MapError> {
+        self.map(&World::get_map_name(x, y, z))
+    }
+    // Resident map names, least recently used first
+    pub fn resident(&self) -> Vec<String> {
+        self.order.iter().cloned().collect()
+    }
+    pub fn is_resident(&self, map_name: &str) -> bool {
+        self.resident.contains_key(map_name)
+    }
+
+    fn touch(&mut self, map_name: &str) {
+        if let Some(index) = self.order.iter().position(|name| name == map_name) {
+            self.order.remove(index);
+        }
+        self.order.push_back(map_name.to_string());
+    }
+    fn insert(&mut self, map_name: &str, map: Map) {
+        self.resident.insert(map_name.to_string(), map);
+        self.touch(map_name);
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.resident.remove(&oldest);
+            }
+        }
+    }
+}
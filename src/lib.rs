@@ -4,23 +4,57 @@ extern crate rand;
 extern crate serde;
 extern crate serde_json;
 extern crate flate2;
+extern crate toml;
+
+mod biome;
+mod climate;
+pub mod connectivity;
+mod cost;
+mod error;
+mod flow;
+mod report;
+mod grid;
+mod jobs;
+mod lazy;
+mod route;
+mod tilemap;
+pub mod hpa;
+pub mod voronoi;
 
 use gdnative::*;
-use rand::Rng;
-use std::collections::HashMap;
+use rand::{Rng, SeedableRng, StdRng};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, BTreeMap, HashMap};
+use std::convert::TryFrom;
 use std::string::String;
 use serde::{Serialize, Deserialize};
 use std::fs;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufWriter;
+use std::path::Path;
 use flate2::write::GzEncoder;
 use flate2::read::GzDecoder;
 use flate2::Compression;
 
+pub use biome::{Biome, BiomeControl, BiomeRegistry, SmoothingRules, TileChance};
+pub use climate::{BiomeRule, Climate, ClimateModel};
+pub use cost::{CostProfile, Movement, MAX_TILE_COST};
+pub use error::MapError;
+pub use flow::FlowField;
+pub use grid::TileGrid;
+pub use jobs::{JobPool, JobStatus};
+pub use lazy::{LazyWorld, DEFAULT_RESIDENT_MAPS};
+pub use report::{MapReport, TileStats};
+pub use route::{find_world_path, WorldPath, WorldStep};
+pub use tilemap::TileMapLayers;
+use connectivity::Connectivity;
+use hpa::ClusterGraph;
+use voronoi::{DistanceMetric, RegionBuckets};
+
 // Priority To do:
 // - Get worlds working
-// -- method add exits to maps on correct sides
+// -- method add exits to maps on correct sides (done, World::add_map_exits)
 // -- method returns sides available_exits
 // -- double check loading/saving (getting complex, multiple levels of file reading/writing)
 // -- test old map gen isn't broken without worlds
@@ -32,17 +66,17 @@ use flate2::Compression;
 // - Store biome control and tile chance inside Map (probably not)
 // - BiomeControl is getting bulky! (anything to do? probably not)
 // - combine biome control and tile chance into single function or creation (maybe)
-// - Map can be restructured for simplicity and security: priv Map, pub GodotMap, priv Save/Load/CompressMap (yes please refactor)
+// - Map can be restructured for simplicity and security: priv Map, pub GodotMap (done), priv Save/Load/CompressMap (yes please refactor)
 // -- Separating map into the generation of the tileset, godot interface, and extra tools for load/save/compress (next refactor)
 // - PathMap could export to json... (does it matter?)
 // -- This might actually be required for pre-computed paths!!!
-// - Exits! Exits can be added to the side of a map, to make maps go infinite (Priority)
+// - Exits! Exits can be added to the side of a map, to make maps go infinite (Priority) (done for worlds, World::add_map_exits)
 // -- The idea is that you generate multiple maps in a grid with connections on specific tiles
-// - Roads: connect roads to exits(BiomeControl bool) (Priority)
-// - HPA*: think about or research what kind of data structure is simple enough to store abstracted map data (not yet)
+// - Roads: connect roads to exits(BiomeControl bool) (Priority) (done, Map::add_roads)
+// - HPA*: think about or research what kind of data structure is simple enough to store abstracted map data (done, hpa.rs)
 // -- I think the most important change or addition is the ability to store a separate optional path for each abstracted PathTile?
 // -- It would be useful for path finding across a grid of maps, instead of just tiles too (if it isn't too hard)
-// -- Would require: maps that are connected (easy), pre computed path between exits (needs file storage)(hard), store map data in PathTile(?)
+// -- Would require: maps that are connected (easy), pre computed path between exits (needs file storage)(done, .hpa cache files), store map data in PathTile(?)
 // - Maze Biome
 // - City Biome
 // - Quad-Tree stored world, through a Quad-Sphere or basic cube initially
@@ -55,13 +89,20 @@ pub struct PathTile {
     pub g: i32,
     pub h: i32,
     pub f: i32,
-    pub parent: String,
-    pub neighbors: Vec<String>
+    pub parent: usize, // grid index of parent tile
+    pub neighbors: Vec<usize> // grid index of each neighbor tile
 }
 
-// Used to access PathTile information
+// Used to access PathTile information, path_tiles is indexed the same as the TileGrid
 pub struct PathMap {
-    pub path_tiles: HashMap<String, PathTile>
+    pub path_tiles: Vec<PathTile>
+}
+
+// Path found by PathMap::find_path
+#[derive(Clone, Debug, PartialEq)]
+pub struct PathResult {
+    pub tiles: Vec<usize>, // grid indexes from start to end (both included)
+    pub cost: i32 // sum of move costs along the path
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -71,26 +112,6 @@ pub struct Tile { // Individual tile data, stored in Map struct HashMap
     pub c: char,
     pub neighbors: Vec<String> // this will store a key to game_objects, for each neighbor tiles
 }
-pub struct TileChance { // Used to control the biome tiles on map
-    pub floor: f32, // percentage of map floor
-    pub wall: f32,  // percentage of map wall
-    pub water: f32, // percentage of map water
-    pub sand: f32,
-    pub tree: f32
-}
-pub struct BiomeControl {
-    pub water_edges: bool, // Activate method add_water_edges(), makes floor around water
-    pub outer_wall: bool, // Activate method add_border_walls(), add wall around map
-    pub sparse_trees: bool,
-    pub roads: bool,
-    pub exit_roads: bool,
-    pub exits: bool
-}
-pub struct Biome { // Used to control advanced biome manipulation
-    pub biome_name: String,
-    pub tile_chance: TileChance,
-    pub biome_control: BiomeControl
-}
 pub struct TileType { // Static struct to store char for each type_type '.' '#' '~'
     pub floor: char,
     pub wall: char,
@@ -98,7 +119,8 @@ pub struct TileType { // Static struct to store char for each type_type '.' '#'
     pub sand: char,
     pub tree: char,
     pub road: char,
-    pub exit: char
+    pub exit: char,
+    pub bridge: char // Road over water
 }
 static TILE_TYPE: TileType = TileType { // Static struct of TileType, avoid hardcode chars in methods
     floor: '.',
@@ -106,180 +128,478 @@ static TILE_TYPE: TileType = TileType { // Static struct of TileType, avoid hard
     water: '~',
     sand: ',',
     tree: 't',
-    road: '.', // Same as floor for testing
-    exit: '/'
+    road: '=',
+    exit: '/',
+    bridge: '+'
 };
+impl TileType {
+    // Look up a tile type by name ("sand") or by its char (","), used by biome definition files
+    pub fn from_name(&self, name: &str) -> Option<char> {
+        for (tile_name, tile_char) in self.tile_types().iter() {
+            if name == *tile_name || name == tile_char.to_string() {
+                return Some(*tile_char);
+            }
+        }
+        None
+    }
+    // Every tile type name and char, the position in this list is the tile id sent to Godot
+    pub fn tile_types(&self) -> [(&'static str, char); 8] {
+        [
+            ("floor", self.floor), ("wall", self.wall), ("water", self.water), ("sand", self.sand),
+            ("tree", self.tree), ("road", self.road), ("exit", self.exit), ("bridge", self.bridge)
+        ]
+    }
+    // Tile id of a tile char (first tile type using it), UNKNOWN_TILE_ID for chars that aren't a tile type
+    pub fn id(&self, tile_type: char) -> u8 {
+        self.tile_types().iter().position(|(_, c)| *c == tile_type).map_or(UNKNOWN_TILE_ID, |id| id as u8)
+    }
+    // Tiles the player can stand on (connectivity pass)
+    pub fn is_walkable(&self, tile_type: char) -> bool {
+        tile_type == self.floor || tile_type == self.sand || tile_type == self.road || tile_type == self.exit || tile_type == self.bridge
+    }
+}
+pub static UNKNOWN_TILE_ID: u8 = 255;
+pub static GENERATOR_VERSION: u32 = 4; // Bump whenever the same seed stops giving the same map
+pub static MAX_MAP_SIZE: i32 = 4096; // Biggest map side Godot can ask to generate
+static BIOME_DIRECTORY: &str = "resources/biomes"; // Designer biome definitions, loaded when the Godot node starts
 
-#[derive(gdnative::NativeClass)]
-#[inherit(gdnative::Node)]
+#[derive(Clone, Debug)]
 pub struct Map {
+    pub header: MapHeader,
+    pub tileset: TileGrid
+}
+
+// Everything about a map that isn't a tile, saved next to the tiles in the map file
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct MapHeader {
+    pub generator_version: u32, // GENERATOR_VERSION used to make the map, 0 for upgraded legacy maps
+    pub size_x: i32,
+    pub size_y: i32,
+    pub biome: String, // Empty for upgraded legacy maps (they never stored it)
+    pub seed: u64, // Seed used to generate the tileset, same seed/size/biome gives the same map
     pub world_x: i32,
     pub world_y: i32,
     pub world_z: i32,
-    pub tileset: HashMap<String, Tile>
+    pub default_floor: char,
+    pub default_wall: char,
+    pub spawn_x: i32,
+    pub spawn_y: i32,
+    pub exits: Vec<MapExit>,
+    #[serde(default)]
+    pub reachable_area: f32 // Percent of walkable tiles reachable from spawn when the map was generated
+}
+
+// Tile on the map that leads to another map
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct MapExit {
+    pub side: String, // north, south, east, west, above, below
+    pub x: i32,
+    pub y: i32,
+    pub target_map: String, // World map name, empty if not connected yet
+    pub arrival_x: i32, // Tile the player arrives on in target_map
+    pub arrival_y: i32
+}
+
+// Map file layout, one string per row of tiles
+#[derive(Serialize, Deserialize)]
+struct MapFile {
+    header: MapHeader,
+    tiles: Vec<String>
+}
+
+// Godot facing Map node, keeps the biome registry, cost profiles, flow fields and the resident map loaded between calls
+// Maps generated in the background emit map_ready(job_id, biome_name) or job_cancelled(job_id) from _process
+pub struct GodotMap {
+    pub biomes: BiomeRegistry,
+    pub cost_profiles: HashMap<String, CostProfile>, // Built-in profiles plus any added with godot_add_cost_profile
+    pub flow_fields: HashMap<String, (TileGrid, FlowField)>, // Named flow fields and the tiles they were made on, kept for goal updates
+    pub map: Option<Map>, // Resident map, made by godot_map_generate/godot_map_load and used by the other godot_map_* methods
+    path_map: Option<PathMap>, // Reused between godot_map_path_find calls, only depends on the map size
+    jobs: JobPool, // Background generation started with godot_generate_async
+    world: Option<LazyWorld>, // Opened with godot_world_open or made with godot_world_new
+    last_error: String // Message of the last failed call, empty after a call succeeds
+}
+
+// Registered by hand so Godot still sees the class as Map, the Rust name only avoids clashing with the Map struct
+impl NativeClass for GodotMap {
+    type Base = Node;
+    type UserData = user_data::DefaultUserData<GodotMap>;
+
+    fn class_name() -> &'static str {
+        "Map"
+    }
+
+    fn init(owner: Self::Base) -> Self {
+        Self::_init(owner)
+    }
+
+    fn register_properties(builder: &init::ClassBuilder<Self>) {
+        Self::register_signals(builder);
+    }
 }
 
 // New world structs (this maybe should NOT be a xyz grid) (is there a better way to do this) (research world generation)
 // Stores map_file_name, map_position_on_world, map_biome, connected_map_neighbors
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub struct World {
     pub world_name: String, // Directory name to store maps
     pub size_x: i32, // Max size 5 mean -5 .. 0 .. 5 (inclusive)
     pub size_y: i32, // Allows the manipulation of the world shape
-    pub size_z: i32, // Use z to make a giant tower
+    pub size_z: i32, // Use z to make a giant tower, UNBOUNDED on any axis for worlds that never end
+    #[serde(default)]
+    pub seed: u64, // Map seeds, biomes and exits of maps generated on demand come from this and the map position
+    #[serde(default = "World::default_map_size")]
+    pub map_size_x: i32, // Size of maps generated on demand
+    #[serde(default = "World::default_map_size")]
+    pub map_size_y: i32,
+    #[serde(default = "ClimateModel::builtin")]
+    pub climate: ClimateModel, // Picks the biome of maps generated on demand from their world position
     pub directory: String,
-    pub compress_maps: bool,
-    pub maps: HashMap<String, String> // location(xyz) and file_name / key_south -> neighbor_key?
+    pub compress_maps: bool, // Map files are gzipped (the world file never is)
+    pub maps: BTreeMap<String, WorldMap> // Map name ("x0y0z0") -> what the world knows about that map
+}
+
+// One map of a world, kept in the world file so maps can be listed without opening them
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct WorldMap {
+    pub world_x: i32,
+    pub world_y: i32,
+    pub world_z: i32,
+    pub biome: String,
+    pub seed: u64,
+    pub file: String, // Map file path
+    pub exits: Vec<MapExit>, // Same as the map header exits
+    pub status: MapStatus
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MapStatus {
+    Pending, // Planned (biome and seed picked) but the map file doesn't exist yet
+    Generated
 }
 
+// World file layout, version first then the World fields
+#[derive(Serialize, Deserialize)]
+struct WorldFile {
+    version: u32,
+    #[serde(flatten)]
+    world: World
+}
+pub static WORLD_VERSION: u32 = 1; // Legacy world files (flat string map) have no version
+pub static UNBOUNDED: i32 = i32::max_value(); // World size for an axis without an edge
+
 // Save world in a directory
-// world_name/world_name.json
+// world_name/world_name.world
 // world_name/maps/map_name.map
 
 impl World {
-    fn new (world_name: String, size_x: i32, size_y: i32, size_z: i32) -> World {
+    // Empty world in /tmp/worlds/world_name
+    pub fn new (world_name: String, size_x: i32, size_y: i32, size_z: i32) -> World {
         let directory = String::from("/tmp/worlds/") + &world_name;
-        let _dir = match fs::create_dir_all(&directory) {
+        let _dir = match fs::create_dir_all(directory.to_string() + "/maps") {
             Ok(_dir) => _dir,
             Err(_e) => (),
         };
-        let mut maps: HashMap<String, String> = HashMap::new();
-        maps.insert(String::from("world_name"), world_name.to_string());
-        maps.insert(String::from("size_x"), size_x.to_string());
-        maps.insert(String::from("size_y"), size_y.to_string());
-        maps.insert(String::from("size_z"), size_z.to_string());
-        maps.insert(String::from("directory"), directory.to_string());
-        World {world_name: world_name, size_x: size_x, size_y: size_y, size_z: size_z, directory: directory, compress_maps: false, maps: maps}
+        World {world_name: world_name, size_x: size_x, size_y: size_y, size_z: size_z, seed: 0,
+               map_size_x: World::default_map_size(), map_size_y: World::default_map_size(), climate: ClimateModel::builtin(),
+               directory: directory, compress_maps: false, maps: BTreeMap::new()}
+    }
+    // Empty world without edges in /tmp/worlds/world_name, maps are generated as they're asked for (see LazyWorld)
+    pub fn new_unbounded (world_name: String, seed: u64) -> World {
+        let mut world = World::new(world_name, UNBOUNDED, UNBOUNDED, UNBOUNDED);
+        world.seed = seed;
+        world
+    }
+    fn default_map_size() -> i32 {
+        50
     }
     // Test a cube 5x5x5 world
-    pub fn new_world_test() {
+    pub fn new_world_test() -> Result<(), MapError> {
         // this is dumb, just change it to -3 -> 3 (completely inclusive)
         let mut world = World::new(String::from("meow"), 3, 3, 3);
-        let mut maps = HashMap::new();
-        maps.insert(String::from("world_name"), world.world_name.to_string());
-        maps.insert(String::from("size_x"), world.size_x.to_string());
-        maps.insert(String::from("size_y"), world.size_y.to_string());
-        maps.insert(String::from("size_z"), world.size_z.to_string());
-        maps.insert(String::from("directory"), world.directory.to_string());
+        world.seed = Map::random_seed();
+        let biomes = BiomeRegistry::builtin();
         let world_size_x = world.size_x.clone();
         let world_size_y = world.size_y.clone();
         let world_size_z = world.size_z.clone();
         for x in -world_size_x..world_size_x+1 {
             for y in -world_size_y..world_size_y+1 {
                 for z in -world_size_z..world_size_z+1 {
-                    let mut map = Map::new_biome(50, 50, Map::random_biome());
-                    map = map.add_world_pos_to_map(x, y, z);
-                    let map_name = World::get_map_name(x, y, z);
-                    let map_path = world.get_map_path(map_name.to_string());
-                    Map::save_map(&map_path, &map, false);
-                    maps.insert(map_name.to_string(), map_path.to_string());
+                    // Biome from the world climate, so the cube has regions instead of a random biome per map
+                    let entry = world.plan_map(&World::get_map_name(x, y, z))?;
+                    let biome = biomes.get_or_fallback(&entry.biome);
+                    let mut map = Map::new_biome(world.map_size_x, world.map_size_y, &biome, entry.seed);
+                    map.set_world_position(x, y, z);
+                    world.add_map(&map)?;
                 }
             }
         }
-        // Might not want this... maybe give it to a method that chooses appropriate exits from available
-        // If you do this, reading the maps from world in engine would require regex filtering ;-;
-        // maps.extend(world.get_all_exits());
-        world.maps = maps;
-        World::save_world(&world, false);
+        world.add_map_exits(&biomes)?;
+        World::save_world(&world)
     }
 
-    fn add_map (file_name: String, x: i32, y: i32, z: i32) {
-
+    // Save a map at its world position (from its header) and record it as generated, returns the map name
+    pub fn add_map (&mut self, map: &Map) -> Result<String, MapError> {
+        let map_name = World::get_map_name(map.header.world_x, map.header.world_y, map.header.world_z);
+        let map_path = self.get_map_path(map_name.to_string());
+        Map::save_map(&map_path, map, self.compress_maps)?;
+        self.maps.insert(map_name.to_string(), WorldMap {
+            world_x: map.header.world_x,
+            world_y: map.header.world_y,
+            world_z: map.header.world_z,
+            biome: map.header.biome.clone(),
+            seed: map.header.seed,
+            file: map_path,
+            exits: map.header.exits.clone(),
+            status: MapStatus::Generated
+        });
+        Ok(map_name)
+    }
+    // Load a generated map of this world
+    pub fn load_map (&self, map_name: &str) -> Result<Map, MapError> {
+        if self.maps.get(map_name).map_or(false, |entry| entry.status == MapStatus::Pending) {
+            return Err(MapError::NotFound(format!("map {} of world {} isn't generated yet", map_name, self.world_name)));
+        }
+        Map::load_map(&self.map_file(map_name), self.compress_maps)
+    }
+    // Map file of a map name, maps the world doesn't list yet go in the world's maps directory
+    pub fn map_file (&self, map_name: &str) -> String {
+        match self.maps.get(map_name) {
+            Some(entry) => entry.file.clone(),
+            None => self.get_map_path(map_name.to_string()),
+        }
+    }
+    // Position of a listed map, or of any map name made by get_map_name
+    fn map_position (&self, map_name: &str) -> Result<(i32, i32, i32), MapError> {
+        match self.maps.get(map_name) {
+            Some(entry) => Ok((entry.world_x, entry.world_y, entry.world_z)),
+            None => World::parse_map_name(map_name)
+                .ok_or_else(|| MapError::NotFound(format!("world {} has no map {}", self.world_name, map_name))),
+        }
     }
     // Connect exits to neighbor maps args: (map1, map2) -> modify both maps with exits
 
     // Check if a path exists between all exits on a map -> true/false
 
 
-    // Possibly store map exits in the map itself and ignore the world?
-    fn add_map_exits(&self) {
-        for map_name in self.maps.keys() { // Loop through all maps
-            // Get path_name for map file
-            let map_path = self.get_map_path(map_name.to_string());
-            // Load map
-            let map = Map::load_map(&map_path, self.compress_maps);
-            // modify the map to add exits
-            // change tile on map to be a floor?
-            // add metadata exit info?
-            // AHHHHHHHHHHHHH
-            // Save changes
-            Map::save_map(&map_path, &map, false);
+    // Exits are stored in each map's header, the world only decides where they go
+    // Every pair of neighbor maps (from available_exits) gets an exit on the facing sides, both maps need a biome with exits on
+    // Roads are drawn once a map has all of its exits (biomes with roads/exit_roads on)
+    pub fn add_map_exits(&mut self, biomes: &BiomeRegistry) -> Result<(), MapError> {
+        let map_names: Vec<String> = self.maps.iter().filter(|(_, entry)| entry.status == MapStatus::Generated)
+            .map(|(map_name, _)| map_name.clone()).collect();
+        let mut maps: HashMap<String, Map> = HashMap::new();
+        for map_name in map_names.iter() {
+            maps.insert(map_name.to_string(), self.load_map(map_name)?);
+        }
+        let has_exits = |map: &Map| biomes.get_or_fallback(&map.header.biome).biome_control.exits;
+        for map_name in map_names.iter() {
+            if !has_exits(&maps[map_name]) {
+                continue;
+            }
+            for (side, neighbor_name) in self.neighbors(map_name)? {
+                let exit = match maps.get(&neighbor_name) {
+                    Some(neighbor) if has_exits(neighbor) => {
+                        let map = &maps[map_name];
+                        self.exit_to(map_name, &side, (map.tileset.size_x(), map.tileset.size_y()),
+                                     &neighbor_name, (neighbor.tileset.size_x(), neighbor.tileset.size_y()))
+                    },
+                    _ => continue,
+                };
+                if let (Some(exit), Some(map)) = (exit, maps.get_mut(map_name)) {
+                    map.add_map_exit(exit);
+                }
+            }
+            if let Some(map) = maps.get_mut(map_name) {
+                let biome = biomes.get_or_fallback(&map.header.biome);
+                map.add_roads(&biome);
+            }
+        }
+        for map_name in map_names.iter() {
+            self.add_map(&maps[map_name])?;
+        }
+        Ok(())
+    }
+    // (side, neighbor map name) for each neighbor from available_exits, sorted by side
+    fn neighbors(&self, map_name: &str) -> Result<Vec<(String, String)>, MapError> {
+        // Keys look like "x0y0z0_exit_north"
+        let available_exits = self.available_exits(map_name.to_string())?;
+        let mut neighbors: Vec<(String, String)> = available_exits.into_iter()
+            .filter_map(|(key, neighbor_name)| key.rsplit("_exit_").next().map(|side| (side.to_string(), neighbor_name)))
+            .collect();
+        neighbors.sort();
+        Ok(neighbors)
+    }
+    // Exit on one side of a map leading to neighbor, both maps get their exit at the same spot along the shared edge
+    // (the same tile for above/below) so edges line up, the arrival tile is the neighbor's exit on the facing side
+    fn exit_to(&self, map_name: &str, side: &str, (size_x, size_y): (i32, i32), neighbor_name: &str, (neighbor_x, neighbor_y): (i32, i32)) -> Option<MapExit> {
+        // Hash of the pair is the same whichever map asks, corners are skipped so the tunnel never runs along the border
+        let hash = self.exit_hash(map_name, neighbor_name);
+        let along = |length: i32| if length > 2 { 1 + (hash % (length - 2) as u64) as i32 } else { 0 };
+        let inside = |length: i32, hash: u64| if length > 4 { 2 + (hash % (length - 4) as u64) as i32 } else { length / 2 };
+        let (x, y, arrival_x, arrival_y) = match side {
+            "north" => { let x = along(size_x.min(neighbor_x)); (x, 0, x, neighbor_y - 1) },
+            "south" => { let x = along(size_x.min(neighbor_x)); (x, size_y - 1, x, 0) },
+            "east" => { let y = along(size_y.min(neighbor_y)); (size_x - 1, y, 0, y) },
+            "west" => { let y = along(size_y.min(neighbor_y)); (0, y, neighbor_x - 1, y) },
+            "above" | "below" => {
+                let x = inside(size_x.min(neighbor_x), hash);
+                let y = inside(size_y.min(neighbor_y), hash >> 32);
+                (x, y, x, y)
+            },
+            _ => return None,
+        };
+        Some(MapExit {side: side.to_string(), x: x, y: y, target_map: neighbor_name.to_string(), arrival_x: arrival_x, arrival_y: arrival_y})
+    }
+    // FNV-1a of the world seed and both map names (in sorted order)
+    fn exit_hash(&self, map_name: &str, neighbor_name: &str) -> u64 {
+        let (first, second) = if map_name < neighbor_name { (map_name, neighbor_name) } else { (neighbor_name, map_name) };
+        let seed = self.seed.to_le_bytes();
+        World::fnv1a(seed.iter().cloned().chain(Some(b'/')).chain(first.bytes()).chain(Some(b'/')).chain(second.bytes()))
+    }
+    pub(crate) fn fnv1a<I: Iterator<Item = u8>>(bytes: I) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for byte in bytes {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
         }
+        hash
+    }
+
+    // Seed of the map at a world position, the same world seed always gives the same map seeds
+    pub fn map_seed(&self, x: i32, y: i32, z: i32) -> u64 {
+        let seed = self.seed.to_le_bytes();
+        let position = [x.to_le_bytes(), y.to_le_bytes(), z.to_le_bytes()];
+        World::fnv1a(seed.iter().chain(position.iter().flatten()).cloned())
+    }
+    // Position is inside the world (sizes are inclusive on both sides)
+    pub fn in_bounds(&self, x: i32, y: i32, z: i32) -> bool {
+        x >= -self.size_x && x <= self.size_x && y >= -self.size_y && y <= self.size_y && z >= -self.size_z && z <= self.size_z
+    }
+    // World position from Godot, errors if it doesn't fit in i32 or is outside the world
+    pub fn position(&self, x: i64, y: i64, z: i64) -> Result<(i32, i32, i32), MapError> {
+        match (i32::try_from(x), i32::try_from(y), i32::try_from(z)) {
+            (Ok(x), Ok(y), Ok(z)) if self.in_bounds(x, y, z) => Ok((x, y, z)),
+            _ => Err(MapError::NotFound(format!("world {} has no position {}, {}, {}", self.world_name, x, y, z))),
+        }
+    }
+    // Entry of a map, maps the world doesn't list yet are planned from the world seed (biome and seed picked, still pending)
+    // The biome comes from the world climate at the map's position, so neighbors get related biomes
+    pub fn plan_map(&self, map_name: &str) -> Result<WorldMap, MapError> {
+        if let Some(entry) = self.maps.get(map_name) {
+            return Ok(entry.clone());
+        }
+        let (world_x, world_y, world_z) = match World::parse_map_name(map_name) {
+            Some((x, y, z)) if self.in_bounds(x, y, z) => (x, y, z),
+            _ => return Err(MapError::NotFound(format!("world {} has no map {}", self.world_name, map_name))),
+        };
+        let seed = self.map_seed(world_x, world_y, world_z);
+        Ok(WorldMap {
+            world_x: world_x, world_y: world_y, world_z: world_z, biome: self.climate.biome_at(self.seed, world_x, world_y, world_z), seed: seed,
+            file: self.get_map_path(map_name.to_string()), exits: Vec::new(), status: MapStatus::Pending
+        })
+    }
+    // Load a generated map, or generate it from its plan with exits to every neighbor (generated or not) and save it
+    // Exits only depend on the world seed and both plans, so neighbors generated later agree on them
+    // The world file isn't saved, callers do that once they're done generating
+    pub fn generate_map(&mut self, biomes: &BiomeRegistry, map_name: &str) -> Result<Map, MapError> {
+        let entry = self.plan_map(map_name)?;
+        if entry.status == MapStatus::Generated {
+            return self.load_map(map_name);
+        }
+        // World files can be edited by hand, so the map size is checked again here
+        let (size_x, size_y) = Map::check_size(i64::from(self.map_size_x), i64::from(self.map_size_y))?;
+        let biome = biomes.get_or_fallback(&entry.biome);
+        let mut map = Map::new_biome(size_x, size_y, &biome, entry.seed);
+        map.set_world_position(entry.world_x, entry.world_y, entry.world_z);
+        if biome.biome_control.exits {
+            let size = (map.tileset.size_x(), map.tileset.size_y());
+            for (side, neighbor_name) in self.neighbors(map_name)? {
+                let neighbor = self.plan_map(&neighbor_name)?;
+                if !biomes.get_or_fallback(&neighbor.biome).biome_control.exits {
+                    continue;
+                }
+                // Maps generated on demand all have the world's map size
+                if let Some(exit) = self.exit_to(map_name, &side, size, &neighbor_name, (self.map_size_x, self.map_size_y)) {
+                    map.add_map_exit(exit);
+                }
+            }
+            map.add_roads(&biome);
+        }
+        self.add_map(&map)?;
+        Ok(map)
     }
 
     // Return the exits for every map in world
-    fn get_all_exits (&self) -> HashMap<String,String> {
+    fn get_all_exits (&self) -> Result<HashMap<String,String>, MapError> {
         let mut all_map_exits: HashMap<String,String> = HashMap::new();
         for map in self.maps.keys() {
-            let mut available_exits = self.available_exits(map.to_string());
+            let mut available_exits = self.available_exits(map.to_string())?;
             all_map_exits.extend(available_exits);
         }
-        all_map_exits
+        Ok(all_map_exits)
     }
     // Check map position, check neighbors, calculate max possible exits (useless?)
-    fn max_exits(&self, map_name: String) -> i32 {
-        let map_path = self.get_map_path(map_name.to_string());
-        let map = Map::load_map(&map_path, self.compress_maps);
-        let available_exits = self.available_exits(map_name.to_string());
-        available_exits.keys().len() as i32
+    fn max_exits(&self, map_name: String) -> Result<i32, MapError> {
+        let available_exits = self.available_exits(map_name.to_string())?;
+        Ok(available_exits.keys().len() as i32)
     }
     // Look at position in world grid and see what neighbors exist
-    fn available_exits (&self, map_name: String) -> HashMap<String,String> {
+    fn available_exits (&self, map_name: String) -> Result<HashMap<String,String>, MapError> {
         let mut available_exits: HashMap<String,String> = HashMap::new();
-        available_exits.extend(self.available_exits_x(map_name.to_string()));
-        available_exits.extend(self.available_exits_y(map_name.to_string()));
-        available_exits.extend(self.available_exits_z(map_name.to_string()));
-        available_exits
+        available_exits.extend(self.available_exits_x(map_name.to_string())?);
+        available_exits.extend(self.available_exits_y(map_name.to_string())?);
+        available_exits.extend(self.available_exits_z(map_name.to_string())?);
+        Ok(available_exits)
     }
     // Return neighbor maps on x axis
-    fn available_exits_x(&self, map_name: String) -> HashMap<String,String> {
+    fn available_exits_x(&self, map_name: String) -> Result<HashMap<String,String>, MapError> {
         let world_size_x = self.size_x;
-        let map_path = self.get_map_path(map_name.to_string());
-        let map = Map::load_map(&map_path, false); // Fixes map.world_x/y/z
+        let (world_x, world_y, world_z) = self.map_position(&map_name)?;
         let mut available_exit_maps = HashMap::new();
-        if map.world_x < world_size_x && map.world_x > -world_size_x { // 2 x neighbors
-            available_exit_maps.insert(map_name.to_string() + "_exit_east", World::get_map_name(map.world_x+1, map.world_y, map.world_z));
-            available_exit_maps.insert(map_name.to_string() + "_exit_west", World::get_map_name(map.world_x-1, map.world_y, map.world_z));
-        } else if map.world_x == world_size_x { // only west side neighbor
-            available_exit_maps.insert(map_name.to_string() + "_exit_west", World::get_map_name(map.world_x-1, map.world_y, map.world_z));
-        } else if map.world_x == -world_size_x { // only east side neighbor
-            available_exit_maps.insert(map_name.to_string() + "_exit_east", World::get_map_name(map.world_x+1, map.world_y, map.world_z));
+        if world_x < world_size_x && world_x > -world_size_x { // 2 x neighbors
+            available_exit_maps.insert(map_name.to_string() + "_exit_east", World::get_map_name(world_x+1, world_y, world_z));
+            available_exit_maps.insert(map_name.to_string() + "_exit_west", World::get_map_name(world_x-1, world_y, world_z));
+        } else if world_x == world_size_x { // only west side neighbor
+            available_exit_maps.insert(map_name.to_string() + "_exit_west", World::get_map_name(world_x-1, world_y, world_z));
+        } else if world_x == -world_size_x { // only east side neighbor
+            available_exit_maps.insert(map_name.to_string() + "_exit_east", World::get_map_name(world_x+1, world_y, world_z));
         }
-        available_exit_maps
+        Ok(available_exit_maps)
     }
     // Return neighbor maps on y axis
-    fn available_exits_y(&self, map_name: String) -> HashMap<String,String> {
+    fn available_exits_y(&self, map_name: String) -> Result<HashMap<String,String>, MapError> {
         let world_size_y = self.size_y;
-        let map_path = self.get_map_path(map_name.to_string());
-        let map = Map::load_map(&map_path, false); // Fixes map.world_x/y/z
+        let (world_x, world_y, world_z) = self.map_position(&map_name)?;
         let mut available_exit_maps = HashMap::new();
-        if map.world_y < world_size_y && map.world_y > -world_size_y { // 2 y neighbors
-            available_exit_maps.insert(map_name.to_string() + "_exit_north", World::get_map_name(map.world_x, map.world_y+1, map.world_z));
-            available_exit_maps.insert(map_name.to_string() + "_exit_south", World::get_map_name(map.world_x, map.world_y-1, map.world_z));
-        } else if map.world_y == world_size_y { // only south side neighbor
-            available_exit_maps.insert(map_name.to_string() + "_exit_south", World::get_map_name(map.world_x, map.world_y-1, map.world_z));
-        } else if map.world_y == -world_size_y { // only north side neighbor
-            available_exit_maps.insert(map_name.to_string() + "_exit_north", World::get_map_name(map.world_x, map.world_y+1, map.world_z));
+        if world_y < world_size_y && world_y > -world_size_y { // 2 y neighbors
+            available_exit_maps.insert(map_name.to_string() + "_exit_north", World::get_map_name(world_x, world_y+1, world_z));
+            available_exit_maps.insert(map_name.to_string() + "_exit_south", World::get_map_name(world_x, world_y-1, world_z));
+        } else if world_y == world_size_y { // only south side neighbor
+            available_exit_maps.insert(map_name.to_string() + "_exit_south", World::get_map_name(world_x, world_y-1, world_z));
+        } else if world_y == -world_size_y { // only north side neighbor
+            available_exit_maps.insert(map_name.to_string() + "_exit_north", World::get_map_name(world_x, world_y+1, world_z));
         }
-        available_exit_maps
+        Ok(available_exit_maps)
     }
     // Return neighbor maps on z axis
-    fn available_exits_z(&self, map_name: String) -> HashMap<String,String> {
+    fn available_exits_z(&self, map_name: String) -> Result<HashMap<String,String>, MapError> {
         let world_size_z = self.size_z;
-        let map_path = self.get_map_path(map_name.to_string());
-        let map = Map::load_map(&map_path, false); // Fixes map.world_x/y/z
+        let (world_x, world_y, world_z) = self.map_position(&map_name)?;
         let mut available_exit_maps = HashMap::new();
-        if map.world_z < world_size_z && map.world_z > -world_size_z { // 2 z neighbors
-            available_exit_maps.insert(map_name.to_string() + "_exit_above", World::get_map_name(map.world_x, map.world_y, map.world_z+1));
-            available_exit_maps.insert(map_name.to_string() + "_exit_below", World::get_map_name(map.world_x, map.world_y, map.world_z-1));
-        } else if map.world_z == world_size_z { // only below side neighbor
-            available_exit_maps.insert(map_name.to_string() + "_exit_below", World::get_map_name(map.world_x, map.world_y, map.world_z-1));
-        } else if map.world_z == -world_size_z { // only above side neighbor
-            available_exit_maps.insert(map_name.to_string() + "_exit_above", World::get_map_name(map.world_x, map.world_y, map.world_z+1));
+        if world_z < world_size_z && world_z > -world_size_z { // 2 z neighbors
+            available_exit_maps.insert(map_name.to_string() + "_exit_above", World::get_map_name(world_x, world_y, world_z+1));
+            available_exit_maps.insert(map_name.to_string() + "_exit_below", World::get_map_name(world_x, world_y, world_z-1));
+        } else if world_z == world_size_z { // only below side neighbor
+            available_exit_maps.insert(map_name.to_string() + "_exit_below", World::get_map_name(world_x, world_y, world_z-1));
+        } else if world_z == -world_size_z { // only above side neighbor
+            available_exit_maps.insert(map_name.to_string() + "_exit_above", World::get_map_name(world_x, world_y, world_z+1));
         }
-        available_exit_maps
+        Ok(available_exit_maps)
     }
     // Translate xyz coordinates into a map_key for world.maps
-    fn get_map_name(x: i32, y: i32, z: i32) -> String {
+    pub fn get_map_name(x: i32, y: i32, z: i32) -> String {
         let mut file_name = String::from("x");
         file_name.push_str(&x.to_string());
         file_name.push_str("y");
@@ -288,15 +608,28 @@ impl World {
         file_name.push_str(&z.to_string());
         file_name
     }
+    // xyz coordinates of a map_key made by get_map_name, None for any other key
+    pub fn parse_map_name(map_name: &str) -> Option<(i32, i32, i32)> {
+        if !map_name.starts_with('x') {
+            return None;
+        }
+        let rest = &map_name[1..];
+        let y_at = rest.find('y')?;
+        let z_at = rest.find('z')?;
+        let x = rest[..y_at].parse::<i32>().ok()?;
+        let y = rest.get(y_at + 1..z_at)?.parse::<i32>().ok()?;
+        let z = rest[z_at + 1..].parse::<i32>().ok()?;
+        Some((x, y, z))
+    }
     // Get the map file_path from the map_name String
-    fn get_map_path(&self, map_name: String) -> String {
+    pub fn get_map_path(&self, map_name: String) -> String {
         let mut map_path = self.directory.to_string();
         map_path.push_str("/maps/");
         map_path.push_str(&map_name);
         map_path.push_str(".map");
         map_path
     }
-    // Return absolute path to world file
+    // Return absolute path to world file (worlds made by World::new)
     fn get_world_path(world_name: String) -> String {
         let mut world_path = String::from("/tmp/worlds/");
         world_path.push_str(&world_name);
@@ -305,82 +638,168 @@ impl World {
         world_path.push_str(".world");
         world_path
     }
-    // Serialize hashmap into string, open a file for writing, write to file with compressed bufwriter
-    pub fn save_world (world: &World, compression: bool) {
-        let serialized = serde_json::to_string(&world.maps).unwrap();
-        let world_path = World::get_world_path(world.world_name.to_string());
-        let f = File::create(world_path).expect("Unable to create file");
-        let enc: flate2::write::GzEncoder<std::fs::File>;
-        // if compression enabled, gzip here
-        if compression {
-            enc = Map::compress(f);
-            let mut buf = BufWriter::new(enc);
-            buf.write_all(serialized.as_bytes()).expect("Unable to write data");
+    // World file in the world's own directory
+    pub fn world_path(&self) -> String {
+        let mut world_path = self.directory.to_string();
+        world_path.push_str("/");
+        world_path.push_str(&self.world_name);
+        world_path.push_str(".world");
+        world_path
+    }
+    // Write the world file (metadata and every map entry) to world_path, map files are saved by add_map
+    pub fn save_world (world: &World) -> Result<(), MapError> {
+        let world_file = WorldFile {version: WORLD_VERSION, world: world.clone()};
+        let serialized = serde_json::to_string_pretty(&world_file).expect("Worlds always serialize");
+        Map::write_file(&world.world_path(), serialized.as_bytes(), false)
+    }
+    // Load a world made by World::new from /tmp/worlds
+    pub fn load_world (world_name: &str) -> Result<World, MapError> {
+        World::load_world_file(&World::get_world_path(world_name.to_string()))
+    }
+    // Load a world from a path to a .world file, or by world name from /tmp/worlds
+    pub fn open (world: &str) -> Result<World, MapError> {
+        if world.ends_with(".world") {
+            World::load_world_file(world)
         } else {
-            let mut buf = BufWriter::new(f);
-            buf.write_all(serialized.as_bytes()).expect("Unable to write data");
+            World::load_world(world)
         }
     }
-    // Load world file into new world
-    fn load_world (world_name: &str) -> World {
-        let world_path = World::get_world_path(world_name.to_string());
-        let mut f = File::open(world_path).expect("Unable to open file");
-        let mut s = String::new();
-        f.read_to_string(&mut s).unwrap();
-        let maps: HashMap<String, String> = serde_json::from_str(&s).unwrap();
-        let size_x = maps["size_x"].parse::<i32>().unwrap().clone();
-        let size_y = maps["size_y"].parse::<i32>().unwrap().clone();
-        let size_z = maps["size_z"].parse::<i32>().unwrap().clone();
-        let world = World::new(maps["world_name"].clone(), size_x, size_y, size_z);
-        world
+    // Load any world file, legacy world files are upgraded (saving writes the new layout)
+    pub fn load_world_file (world_path: &str) -> Result<World, MapError> {
+        let s = Map::read_file(world_path, false)?;
+        let json_error = |e| MapError::Json {path: world_path.to_string(), source: e};
+        let value: serde_json::Value = serde_json::from_str(&s).map_err(json_error)?;
+        if value.get("version").is_some() {
+            let world_file: WorldFile = serde_json::from_value(value).map_err(json_error)?;
+            return Ok(world_file.world);
+        }
+        let maps: HashMap<String, String> = serde_json::from_value(value).map_err(json_error)?;
+        World::from_legacy_maps(world_path, &maps)
+    }
+    // Old layout: one flat string map with the world fields and "x0y0z0" -> map file path
+    // Biome, seed and exits come from each map file, maps that can't be read are listed as pending
+    fn from_legacy_maps (world_path: &str, maps: &HashMap<String, String>) -> Result<World, MapError> {
+        let field = |field: &str| maps.get(field).ok_or_else(|| MapError::MissingMetadata {path: world_path.to_string(), field: field.to_string()});
+        // Every size field has to be there and be a number
+        let size = |name: &str| {
+            field(name)?.parse::<i32>().map_err(|_| MapError::MissingMetadata {path: world_path.to_string(), field: name.to_string()})
+        };
+        let mut world = World {
+            world_name: field("world_name")?.clone(),
+            size_x: size("size_x")?,
+            size_y: size("size_y")?,
+            size_z: size("size_z")?,
+            seed: 0,
+            map_size_x: World::default_map_size(),
+            map_size_y: World::default_map_size(),
+            climate: ClimateModel::builtin(),
+            directory: field("directory")?.clone(),
+            compress_maps: false, // Legacy worlds always saved plain map files
+            maps: BTreeMap::new()
+        };
+        for (map_name, map_path) in maps.iter() {
+            let (world_x, world_y, world_z) = match World::parse_map_name(map_name) {
+                Some(position) => position,
+                None => continue,
+            };
+            let mut entry = WorldMap {
+                world_x: world_x, world_y: world_y, world_z: world_z, biome: String::new(), seed: 0,
+                file: map_path.clone(), exits: Vec::new(), status: MapStatus::Pending
+            };
+            if let Ok(map) = Map::load_map(map_path, false) {
+                entry.biome = map.header.biome;
+                entry.seed = map.header.seed;
+                entry.exits = map.header.exits;
+                entry.status = MapStatus::Generated;
+            }
+            world.maps.insert(map_name.clone(), entry);
+        }
+        Ok(world)
     }
 }
 
 
-#[gdnative::methods]
 impl Map {
     pub fn test() { // debug testing only
         Map::prep();
-        let m = Map::new_biome(50, 50, String::from("Forest"));
-        Map::save_map("/tmp/maps/test101.map", &m, false);
+        let biome = BiomeRegistry::builtin().get_or_fallback("Forest");
+        let m = Map::new_biome(50, 50, &biome, 101);
+        Map::save_map("/tmp/maps/test101.map", &m, false).expect("Unable to save test map");
     }
     // Build Map structure (fix the world positions later)
-    fn new(tileset: HashMap<String, Tile>) -> Map {
+    fn new(header: MapHeader, tileset: TileGrid) -> Map {
         let map: Map = Map {
-            world_x: 0,
-            world_y: 0,
-            world_z: 0,
+            header: header,
             tileset: tileset
         };
         map
     }
-    // Pull world position out of tileset for convenience (messy) (uses Tile for storage)
-    fn fix_map_world_position(self) -> Map {
-        let tileset = self.tileset; // Move tileset out
-        let x = tileset["world_x"].x.clone(); // Value stored in Tile.x/y
-        let y = tileset["world_y"].x.clone(); // Value stored in Tile.x/y
-        let z = tileset["world_z"].x.clone(); // Tile has no z
-        let map: Map = Map {
-            world_x: x,
-            world_y: y,
-            world_z: z,
-            tileset: tileset
+    // Upgrade the old layout where metadata was stored as sentinel Tiles ('$') next to the real tiles
+    // filename is only used for error messages
+    fn from_legacy_tileset(filename: &str, tileset: HashMap<String, Tile>) -> Result<Map, MapError> {
+        // Metadata every legacy map file had, anything else gets a default
+        let metadata = |field: &str| {
+            tileset.get(field).ok_or_else(|| MapError::MissingMetadata {path: filename.to_string(), field: field.to_string()})
         };
-        map
-    }
-    // Add maps world position to tileset and map (messy) (uses Tile for storage)
-    fn add_world_pos_to_map(self, x: i32, y: i32, z: i32) -> Map {
-        let mut tileset = self.tileset; // Move tileset out
-        tileset.insert(String::from("world_x"), Tile::new(x, x, '$', Vec::new()));
-        tileset.insert(String::from("world_y"), Tile::new(y, y, '$', Vec::new()));
-        tileset.insert(String::from("world_z"), Tile::new(z, z, '$', Vec::new()));
-        let map: Map = Map {
-            world_x: x,
-            world_y: y,
-            world_z: z,
-            tileset: tileset
+        let mapsize = metadata("mapsize")?;
+        let default_floor = metadata("default_floor")?.c;
+        let default_wall = metadata("default_wall")?.c;
+        let mut exits = Vec::new();
+        for (key, tile) in tileset.iter() {
+            if key.starts_with("exit_") {
+                exits.push(MapExit {
+                    side: key["exit_".len()..].to_string(),
+                    x: tile.x,
+                    y: tile.y,
+                    target_map: String::new(),
+                    arrival_x: 0,
+                    arrival_y: 0
+                });
+            }
+        }
+        exits.sort_by(|a, b| a.side.cmp(&b.side));
+        // Older maps were saved without a seed, seed was split into x (high bits) and y (low bits)
+        let seed = match tileset.get("seed") {
+            Some(tile) => ((tile.x as u32 as u64) << 32) | (tile.y as u32 as u64),
+            None => 0,
         };
-        map
+        let size_x = mapsize.x;
+        let size_y = mapsize.y;
+        let header = MapHeader {
+            generator_version: 0,
+            size_x: size_x,
+            size_y: size_y,
+            biome: String::new(),
+            seed: seed,
+            world_x: tileset.get("world_x").map_or(0, |tile| tile.x), // Value stored in Tile.x/y
+            world_y: tileset.get("world_y").map_or(0, |tile| tile.x),
+            world_z: tileset.get("world_z").map_or(0, |tile| tile.x), // Tile has no z
+            default_floor: default_floor,
+            default_wall: default_wall,
+            spawn_x: size_x/2,
+            spawn_y: size_y/2,
+            exits: exits,
+            reachable_area: 0.0
+        };
+        let grid = TileGrid::from_tileset(size_x, size_y, &tileset, header.default_wall);
+        Ok(Map::new(header, grid))
+    }
+    // Every random step in map generation pulls from this rng, never use thread_rng() during generation
+    // Seeded with both halves so 32-bit targets (usize is u32) don't drop the high bits
+    pub fn seeded_rng(seed: u64) -> StdRng {
+        let rng: StdRng = SeedableRng::from_seed(&[seed as u32 as usize, (seed >> 32) as usize][..]);
+        rng
+    }
+    // Pick a new seed for callers that don't care about reproducing the map
+    pub fn random_seed() -> u64 {
+        let mut rng = rand::thread_rng();
+        rng.gen::<u64>()
+    }
+    // Maps need to know their position in a world grid
+    fn set_world_position(&mut self, x: i32, y: i32, z: i32) {
+        self.header.world_x = x;
+        self.header.world_y = y;
+        self.header.world_z = z;
     }
     // Make new directory, don't error if exists
     fn prep() {
@@ -389,161 +808,159 @@ impl Map {
             Err(_e) => (),
         };
     }
-    // Make required directories, give placeholder object to godot
-    fn _init(_owner: Node) -> Self {
-        Map::prep();
-        let tileset = HashMap::new();
-        let map = Map::new(tileset);
-        map
-    }
-    // Not used
-    #[export]
-    fn _ready(&self, _owner: Node) {
-
+    // Sizes from Godot as map sizes, generation needs at least 1x1 and huge maps would never finish
+    pub fn check_size(size_x: i64, size_y: i64) -> Result<(i32, i32), MapError> {
+        let max = i64::from(MAX_MAP_SIZE);
+        if size_x < 1 || size_y < 1 || size_x > max || size_y > max {
+            return Err(MapError::InvalidMapSize {size_x: size_x, size_y: size_y});
+        }
+        Ok((size_x as i32, size_y as i32))
     }
-    #[export]
-    unsafe fn godot_new_biome(&self, _owner: Node, godot_file_name: GodotString, godot_biome_name: GodotString) {
-        // Convert godot string to rust string
-        let file_name = godot_file_name.to_string();
-        let biome_name = godot_biome_name.to_string();
-        let m = Map::new_biome(50, 50, biome_name);
-        Map::save_map(&file_name.to_string(), &m, false);
+    // Tile type at (x, y), errors if outside the map
+    pub fn tile(&self, x: i64, y: i64) -> Result<char, MapError> {
+        let (x, y) = self.tile_position(x, y)?;
+        Ok(self.tileset.at(x, y))
     }
-    #[export]
-    unsafe fn godot_random_biome(&self, _owner: Node, godot_file_name: GodotString) -> GodotString {
-        let biome_name = Map::random_biome();
-        let file_name = godot_file_name.to_string();
-        let m = Map::new_biome(50, 50, biome_name.clone());
-        Map::save_map(&file_name.to_string(), &m, false);
-        // Return the random biome to godot for logging
-        GodotString::from_str(&biome_name)
+    // Change the tile at (x, y), tile_name is a tile type name ("water") or char ("~")
+    pub fn set_tile(&mut self, x: i64, y: i64, tile_name: &str) -> Result<(), MapError> {
+        let tile_type = TILE_TYPE.from_name(tile_name).ok_or_else(|| MapError::InvalidTileType(tile_name.to_string()))?;
+        let (x, y) = self.tile_position(x, y)?;
+        self.tileset.set(x, y, tile_type);
+        Ok(())
     }
-
-    pub fn random_biome() -> String {
-        let mut rng = rand::thread_rng();
-        let random_biome = rng.gen_range(0, 5);
-        let biome_name;
-        if random_biome == 0 {
-            biome_name = String::from("Cave");
-        } else if random_biome == 1 {
-            biome_name = String::from("Ocean");
-        } else if random_biome == 2 {
-            biome_name = String::from("Underlake");
-        } else if random_biome == 3 {
-            biome_name = String::from("Desert");
-        } else if random_biome == 4 {
-            biome_name = String::from("Forest");
-        } else {
-            biome_name = String::from("Cave");
+    // Coordinates from Godot as a tile position, errors if they're outside the map (or don't even fit in i32)
+    fn tile_position(&self, x: i64, y: i64) -> Result<(i32, i32), MapError> {
+        match (i32::try_from(x), i32::try_from(y)) {
+            (Ok(tile_x), Ok(tile_y)) if self.tileset.in_bounds(tile_x, tile_y) => Ok((tile_x, tile_y)),
+            _ => Err(MapError::TileOutOfBounds {x: x, y: y, size_x: self.tileset.size_x(), size_y: self.tileset.size_y()}),
         }
-        biome_name
     }
-
-    #[export] // Specify a map file to read, and a start_tile and end_tile, return path between
-    pub fn godot_path_find(&self, _owner: Node, godot_file_name: GodotString, start_tile: GodotString, end_tile: GodotString) -> StringArray {
-        // Load map file
-        let file_name = godot_file_name.to_string();
-        let map = Map::load_map(&file_name, false);
-        // Create new PathMap overlay (copy of Map but with cost/parent info)
-        let mut path_map = PathMap::new(map.tileset["mapsize"].x, map.tileset["mapsize"].y, &map.tileset);
-        // Get the path in Vec<String> (where string is tile keys)
-        let mut path = PathMap::find_path(start_tile.to_string(), end_tile.to_string(), path_map.path_tiles, &map.tileset);
-        // Convert to Godot StringArray, and return
-        let mut godot_array: StringArray = StringArray::new();
-        for tile in path {
-            godot_array.push(&GodotString::from_str(&tile))
-        }
-        godot_array
+    // Generate new map of a specific biome (same seed, size and biome always gives the same tileset)
+    pub fn new_biome(sizex: i32, sizey: i32, biome: &Biome, seed: u64) -> Map {
+        Map::new_biome_with_progress(sizex, sizey, biome, seed, &mut |_| true).expect("Generation without progress is never cancelled")
     }
-
-    // Generate new map of a specific biome
-    pub fn new_biome(sizex: i32, sizey: i32, biome_name: String) -> Map {
+    // Same as new_biome, progress gets called after each pass with how much is done (0.0 to 1.0)
+    // Returning false from progress cancels generation, None is returned
+    pub fn new_biome_with_progress(sizex: i32, sizey: i32, biome: &Biome, seed: u64, progress: &mut dyn FnMut(f32) -> bool) -> Option<Map> {
         // Setup basic map creation data
-        let mut tileset: HashMap<String, Tile>; // Will store the final map data, exported to json
-        let biome = Biome::new(biome_name); // Stores data that controls map creation
+        let mut tileset: TileGrid; // Will store the final map data, exported to json
         // Prepare random data for voronoi point selection
-        let mut rng = rand::thread_rng();
+        let mut rng = Map::seeded_rng(seed);
         let number_of_regions = rng.gen_range(sizex+sizey, (sizex+sizey)*2);
         let voronoi_regions: Vec<Tile>;
         // Pass 1: generate voronoi_regions using the TileChance to control biome creation
-        voronoi_regions = Map::create_voronoi_points(sizex, sizey,  &biome, number_of_regions);
+        voronoi_regions = Map::create_voronoi_points(sizex, sizey,  biome, number_of_regions, &mut rng);
+        let region_weights = Map::create_region_weights(&voronoi_regions, biome, &mut rng);
+        if !progress(0.1) {
+            return None;
+        }
         // Pass 2: generate empty tileset
-        tileset = Map::empty_tileset(sizex, sizey);
+        tileset = TileGrid::new(sizex, sizey, TILE_TYPE.floor);
         // Pass 3: convert empty tileset to closest voronoi regions
-        tileset = Map::tiles_to_voronoi(voronoi_regions, tileset);
-        // Pass 4: update neighbors of each tile (include corners)
-        tileset = Map::update_all_neighbors(sizex, sizey, tileset);
-        // Pass 5: make sure all tiles around water are floor
+        Map::tiles_to_voronoi(&voronoi_regions, &region_weights, biome.biome_control.distance_metric, &mut tileset);
+        if !progress(0.5) {
+            return None;
+        }
+        // Pass 3.5: smooth out jagged single tile walls (caves)
+        if biome.biome_control.cellular_smoothing {
+            Map::smooth_tiles(biome, &mut tileset);
+        }
+        if !progress(0.6) {
+            return None;
+        }
+        // Pass 4: make sure all tiles around water are floor
         if biome.biome_control.water_edges {
-            tileset = Map::add_water_edges(sizex, sizey, &biome, tileset);
+            Map::add_water_edges(biome, &mut tileset);
+        }
+        if !progress(0.65) {
+            return None;
         }
-        // Pass 6: Tree sparseness (slow!) turn off for tilesets with little or no trees
+        // Pass 5: Tree sparseness (slow!) turn off for tilesets with little or no trees
         if biome.biome_control.sparse_trees {
-            tileset = Map::add_sparse_trees(sizex, sizey, &biome, tileset);
-        }
-        // Pass 7: Draw a road
-        //if biome.biome_control.roads {
-            // This is debug/testing only (remove completely later)
-            //tileset = Map::draw_road(sizex, sizey, "25x25".to_string(), "15x15".to_string(), tileset);
-        //}
-        // Pass 8: Exits (for infinitely connected maps)
-        if biome.biome_control.exits {
-            //tileset = Map::add_map_exits();
+            Map::add_sparse_trees(biome, &mut tileset, &mut rng);
         }
-
-        // Pass FINAL: update wall_borders
+        if !progress(0.75) {
+            return None;
+        }
+        // Pass 6: update wall_borders
         if biome.biome_control.outer_wall {
-            tileset = Map::add_wall_borders(sizex, sizey, &biome, tileset);
+            Map::add_wall_borders(biome, &mut tileset);
+        }
+        if !progress(0.8) {
+            return None;
         }
+        // Pass 7: Exits (for infinitely connected maps) need the world position, World::add_map_exits adds them
+        // to maps of biomes with exits on once every map in the world exists
+        // Pass 8: make sure the spawn can reach the rest of the walkable tiles
+        let spawn = (sizex/2, sizey/2);
+        Map::connect_walkable(biome, &mut tileset, spawn);
+        if !progress(0.95) {
+            return None;
+        }
+        // Pass 9: Roads link the exits (and spawn), Map::add_roads draws them once World::add_map_exits has added the exits
         // Pass X: triangulation (skipping)
         // Pass X: pathfinding
 
-        // Sneaky secret tiles for map_size and default tiles for each biome
-        let map_size = Tile::new(sizex, sizey, '$', Vec::new());
-        let default_floor = Tile::new(sizex, sizey, biome.default_floor(), Vec::new());
-        let default_wall = Tile::new(sizex, sizey, biome.default_wall(), Vec::new());
-        tileset.insert(String::from("mapsize"), map_size);
-        tileset.insert(String::from("default_floor"), default_floor);
-        tileset.insert(String::from("default_wall"), default_wall);
-        // Some placeholder garbage for worlds
-        tileset.insert(String::from("world_x"), Tile::new(0, 0, '$', Vec::new()));
-        tileset.insert(String::from("world_y"), Tile::new(0, 0, '$', Vec::new()));
-        tileset.insert(String::from("world_z"), Tile::new(0, 0, '$', Vec::new()));
+        // Map size, default tiles for each biome, world position is set later by worlds
+        let header = MapHeader {
+            generator_version: GENERATOR_VERSION,
+            size_x: sizex,
+            size_y: sizey,
+            biome: biome.biome_name.clone(),
+            seed: seed,
+            world_x: 0,
+            world_y: 0,
+            world_z: 0,
+            default_floor: biome.default_floor(),
+            default_wall: biome.default_wall(),
+            spawn_x: spawn.0,
+            spawn_y: spawn.1,
+            exits: Vec::new(),
+            reachable_area: Map::reachable_area(&tileset, spawn)
+        };
 
         // Build Map structure
-        let map = Map::new(tileset);
-        map
+        let map = Map::new(header, tileset);
+        progress(1.0);
+        Some(map)
     }
 
     // Map needs to know it's position in a map grid (aka a world with a world size?)
     // Could a Map be used to abstract an entire world? (no z-axis is big issue) (this is a bad idea)
     // Do I need to create a world before doing infinite map? (I think so... damn)
-    fn add_map_exit(exit_type: String, x: i32, y: i32, mut tileset: HashMap<String,Tile>) -> HashMap<String,Tile> {
-        let mut exit_key = String::from("exit_");
-        exit_key.push_str(&exit_type);
-        let exit_tile = Tile::new(x, y, 'x', Vec::new());
-        tileset.insert(exit_key, exit_tile);
-        tileset
-    }
-
-
-    // Create empty tileset of a specific size
-    fn empty_tileset (sizex: i32, sizey: i32) -> HashMap<String,Tile> {
-        let mut tileset = HashMap::new();
-        // Generate temporary tiles
-        for y in 0..sizey {
-            for x in 0..sizex {
-                let t = Tile::new(x, y, TILE_TYPE.floor, Vec::new());
-                tileset.insert(t.get_tile_key(), t);
+    // Turn the exit tile into an exit and dig the cheapest tunnel to it from spawn (through the outer wall if there is one)
+    // Replaces any exit already on the same side, exits are kept sorted by side
+    pub fn add_map_exit(&mut self, exit: MapExit) {
+        if !self.tileset.in_bounds(exit.x, exit.y) {
+            return;
+        }
+        let spawn = (self.header.spawn_x, self.header.spawn_y);
+        if self.tileset.in_bounds(spawn.0, spawn.1) {
+            let start = self.tileset.index(spawn.0, spawn.1);
+            if !TILE_TYPE.is_walkable(self.tileset.tiles()[start]) {
+                self.tileset.tiles_mut()[start] = self.header.default_floor;
             }
+            let end = self.tileset.index(exit.x, exit.y);
+            self.tileset.tiles_mut()[end] = TILE_TYPE.exit;
+            connectivity::carve_tunnel(&mut self.tileset, start, end, self.header.default_floor, |c| TILE_TYPE.is_walkable(c));
+            self.header.reachable_area = Map::reachable_area(&self.tileset, spawn);
+        } else {
+            self.tileset.set(exit.x, exit.y, TILE_TYPE.exit);
         }
-        tileset
+        self.header.exits.retain(|old| old.side != exit.side);
+        self.header.exits.push(exit);
+        self.header.exits.sort_by(|a, b| a.side.cmp(&b.side));
+    }
+
+    // Tile counts, connected areas, spawn and border checks compared against the biome the map was made with
+    pub fn report(&self, biome: &Biome) -> MapReport {
+        MapReport::new(self, biome)
     }
 
     // This is going to get awful and bloated fast! (maybe rewrite without structs) (think about it)
     // This could become a part of biome? I mean it is used specifically to change based on biome...
     // (enum?)
-    fn create_voronoi_points(sizex: i32, sizey: i32, biome: &Biome, number_of_regions: i32) -> Vec<Tile> {
+    fn create_voronoi_points(sizex: i32, sizey: i32, biome: &Biome, number_of_regions: i32, rng: &mut StdRng) -> Vec<Tile> {
         // Get exact number of tiles needed for each type (from TileChance percentage)
         let number_of_floor = (biome.tile_chance.floor * number_of_regions as f32) as i32;
         let number_of_wall = (biome.tile_chance.wall * number_of_regions as f32) as i32;
@@ -552,181 +969,309 @@ impl Map {
         let number_of_trees = (biome.tile_chance.tree * number_of_regions as f32) as i32;
         let mut voronoi_regions = Vec::new();
         // new_voronoi_tiles returns the exact number of tiles requested of the specific type, xy positions are random
-        voronoi_regions = Tile::new_voronoi_tiles(sizex, sizey, number_of_floor, TILE_TYPE.floor, voronoi_regions);
-        voronoi_regions = Tile::new_voronoi_tiles(sizex, sizey, number_of_wall, TILE_TYPE.wall, voronoi_regions);
-        voronoi_regions = Tile::new_voronoi_tiles(sizex, sizey, number_of_water, TILE_TYPE.water, voronoi_regions);
-        voronoi_regions = Tile::new_voronoi_tiles(sizex, sizey, number_of_sand, TILE_TYPE.sand, voronoi_regions);
-        voronoi_regions = Tile::new_voronoi_tiles(sizex, sizey, number_of_trees, TILE_TYPE.tree, voronoi_regions);
+        voronoi_regions = Tile::new_voronoi_tiles(sizex, sizey, number_of_floor, TILE_TYPE.floor, voronoi_regions, rng);
+        voronoi_regions = Tile::new_voronoi_tiles(sizex, sizey, number_of_wall, TILE_TYPE.wall, voronoi_regions, rng);
+        voronoi_regions = Tile::new_voronoi_tiles(sizex, sizey, number_of_water, TILE_TYPE.water, voronoi_regions, rng);
+        voronoi_regions = Tile::new_voronoi_tiles(sizex, sizey, number_of_sand, TILE_TYPE.sand, voronoi_regions, rng);
+        voronoi_regions = Tile::new_voronoi_tiles(sizex, sizey, number_of_trees, TILE_TYPE.tree, voronoi_regions, rng);
         voronoi_regions.push(Tile::new(sizex/2, sizey/2, biome.default_floor(), Vec::new())); // Player spawn
         voronoi_regions
     }
 
-    // Convert empty tiles in tileset to closest voronoi region type
-    fn tiles_to_voronoi (voronoi_regions: Vec<Tile>, tileset: HashMap<String, Tile>) -> HashMap<String, Tile> {
-        let mut new_tileset = HashMap::new();
-        for tile_key in tileset.keys() {
-            let mut closest_region: usize = 0;
-            // Find closest voronoi region to current tile
-            for region in 0..voronoi_regions.len() {
-                let current_region: usize = region as usize;
-                let diff = Tile::distance(&voronoi_regions[current_region], &tileset[tile_key]);
-                let old_diff = Tile::distance(&voronoi_regions[closest_region], &tileset[tile_key]);
-                if diff < old_diff {
-                    closest_region = current_region;
-                }
+    // Random weight for each voronoi region from the biome region_weights, only used by Weighted/Power metrics
+    // Other metrics don't touch the rng so their maps don't change
+    fn create_region_weights(voronoi_regions: &[Tile], biome: &Biome, rng: &mut StdRng) -> Vec<f64> {
+        let metric = biome.biome_control.distance_metric;
+        let mut region_weights = Vec::with_capacity(voronoi_regions.len());
+        for region in voronoi_regions.iter() {
+            if !metric.uses_weights() {
+                region_weights.push(metric.default_weight());
+                continue;
             }
-            // Convert tile_type to voronoi region tile_type, store in new_tileset
-            let new_tile = Tile::new(tileset[tile_key].x.clone(), tileset[tile_key].y.clone(), voronoi_regions[closest_region].c.clone() , Vec::new());
-            new_tileset.insert(tile_key.to_string(), new_tile);
-        }
-        new_tileset
-    }
-
-    // Store side neighbors and corner neighbors
-    fn update_all_neighbors (sizex: i32, sizey: i32, tileset: HashMap<String, Tile>) -> HashMap<String, Tile> {
-        let mut new_tileset = HashMap::new();
-        for tile_key in tileset.keys() {
-            let x = tileset[tile_key].x;
-            let y = tileset[tile_key].y;
-            let tile_type = tileset[tile_key].c;
-            let new_tile;
-            if x <= 1 || y <= 1 || x >= sizex-1 || y >= sizey-1 {
-                new_tile = Tile::new(x, y, tile_type, Vec::new());
+            let (min_weight, max_weight) = biome.region_weight_range(region.c);
+            if max_weight > min_weight {
+                region_weights.push(rng.gen_range(min_weight, max_weight));
             } else {
-                let neighbors: Vec<String> = vec![
-                    // Sides
-                    (x + 1).to_string() + "x" + &(y).to_string(),
-                    (x - 1).to_string() + "x" + &(y).to_string(),
-                    (x).to_string() + "x" + &(y + 1).to_string(),
-                    (x).to_string() + "x" + &(y - 1).to_string(),
-                    // Corners
-                    (x + 1).to_string() + "x" + &(y + 1).to_string(),
-                    (x - 1).to_string() + "x" + &(y - 1).to_string(),
-                    (x + 1).to_string() + "x" + &(y - 1).to_string(),
-                    (x - 1).to_string() + "x" + &(y + 1).to_string()
-                ];
-                new_tile = Tile::new(x, y, tile_type, neighbors);
+                region_weights.push(min_weight);
             }
-            new_tileset.insert(tile_key.to_string(), new_tile);
         }
-        new_tileset
+        region_weights
     }
 
-    // Change all water tiles touching walls into floor (more walkable space)
-    fn add_water_edges (sizex: i32, sizey: i32, biome: &Biome, tileset: HashMap<String, Tile>) -> HashMap<String, Tile> {
-        let mut new_tileset = HashMap::new();
-        for tile_key in tileset.keys() {
-            let x = tileset[tile_key].x;
-            let y = tileset[tile_key].y;
-            let mut tile_type = tileset[tile_key].c;
-            let neighbors = tileset[tile_key].neighbors.clone();
-            let new_tile;
-            if x <= 2 || y <= 2 || x >= sizex-2 || y >= sizey-2 {
-                new_tile = Tile::new(x, y, tile_type, neighbors);
-            } else {
-                for neighbor_key in neighbors.clone() {
-                    if tileset[tile_key].c == TILE_TYPE.water && (tileset[&neighbor_key].c == TILE_TYPE.wall || tileset[&neighbor_key].c == TILE_TYPE.tree) {
-                        tile_type = biome.default_floor();
+    // Convert empty tiles in tileset to closest voronoi region type
+    fn tiles_to_voronoi (voronoi_regions: &[Tile], region_weights: &[f64], metric: DistanceMetric, tileset: &mut TileGrid) {
+        if voronoi_regions.is_empty() {
+            return;
+        }
+        // Bucket search only measures nearby regions (same result as checking every region)
+        let region_buckets = RegionBuckets::new(tileset.size_x(), tileset.size_y(), voronoi_regions, region_weights, metric);
+        for index in 0..tileset.len() {
+            let (x, y) = tileset.position(index);
+            let closest_region = region_buckets.nearest(x, y);
+            // Convert tile_type to voronoi region tile_type
+            tileset.tiles_mut()[index] = voronoi_regions[closest_region].c;
+        }
+    }
+
+    // Cellular automata smoothing, every iteration reads the last iteration's tiles (no rng, same input same output)
+    // Born tiles become the biome wall (or the first solid type), tiles that die become the biome floor
+    pub fn smooth_tiles (biome: &Biome, tileset: &mut TileGrid) {
+        let rules = &biome.biome_control.smoothing;
+        let solid: Vec<char> = rules.solid.iter().filter_map(|name| TILE_TYPE.from_name(name)).collect();
+        let changeable: Vec<char> = rules.tiles.iter().filter_map(|name| TILE_TYPE.from_name(name)).collect();
+        let born_tile = if solid.contains(&biome.default_wall()) {
+            biome.default_wall()
+        } else {
+            match solid.first() {
+                Some(c) => *c,
+                None => return,
+            }
+        };
+        for _ in 0..rules.iterations {
+            let last_tileset = tileset.clone();
+            for index in 0..last_tileset.len() {
+                let tile_type = last_tileset.tiles()[index];
+                if !changeable.contains(&tile_type) {
+                    continue;
+                }
+                let (x, y) = last_tileset.position(index);
+                let mut solid_neighbors = 0;
+                for (dx, dy) in grid::NEIGHBOR_OFFSETS.iter() {
+                    match last_tileset.get(x + dx, y + dy) {
+                        Some(c) if !solid.contains(&c) => (),
+                        _ => solid_neighbors += 1,
                     }
                 }
-                new_tile = Tile::new(x, y, tile_type, neighbors);
+                if solid.contains(&tile_type) {
+                    if !rules.survival.contains(&solid_neighbors) {
+                        tileset.tiles_mut()[index] = biome.default_floor();
+                    }
+                } else if rules.birth.contains(&solid_neighbors) {
+                    tileset.tiles_mut()[index] = born_tile;
+                }
+            }
+        }
+    }
+
+    // Change all water tiles touching walls into floor (more walkable space)
+    fn add_water_edges (biome: &Biome, tileset: &mut TileGrid) {
+        let sizex = tileset.size_x();
+        let sizey = tileset.size_y();
+        // Changed tiles become floor, so changing in place never affects the wall/tree check of later tiles
+        for y in 3..sizey-2 {
+            for x in 3..sizex-2 {
+                if tileset.at(x, y) != TILE_TYPE.water {
+                    continue;
+                }
+                let touching_wall = tileset.neighbors(x, y).any(|(_, _, c)| c == TILE_TYPE.wall || c == TILE_TYPE.tree);
+                if touching_wall {
+                    tileset.set(x, y, biome.default_floor());
+                }
             }
-            new_tileset.insert(tile_key.to_string(), new_tile);
         }
-        new_tileset
     }
 
     // Delete trees at random if they are touching too many trees
-    fn add_sparse_trees (sizex: i32, sizey: i32, biome: &Biome, tileset: HashMap<String, Tile>) -> HashMap<String, Tile> {
-        let mut new_tileset = HashMap::new();
-        let mut rng = rand::thread_rng();
-        for tile_key in tileset.keys() {
-            let x = tileset[tile_key].x;
-            let y = tileset[tile_key].y;
-            let mut tile_type = tileset[tile_key].c;
-            let neighbors = tileset[tile_key].neighbors.clone();
-            let new_tile;
-            if x <= 2 || y <= 2 || x >= sizex-2 || y >= sizey-2 {
-                new_tile = Tile::new(x, y, tile_type, neighbors);
-            } else {
+    // Walks the tiles in row order so the rng is used the same way for the same seed
+    fn add_sparse_trees (biome: &Biome, tileset: &mut TileGrid, rng: &mut StdRng) {
+        let sizex = tileset.size_x();
+        let sizey = tileset.size_y();
+        // Count trees before deleting any, deleted trees still count for their neighbors
+        let mut deleted_trees = Vec::new();
+        // Every interior tile draws from the rng (not only trees), the draw sequence is part of what a seed gives
+        for y in 3..sizey-2 {
+            for x in 3..sizex-2 {
+                let is_tree = tileset.at(x, y) == TILE_TYPE.tree;
                 let mut number_of_trees = 1;
-                for neighbor_key in neighbors.clone() {
-                    if tileset[tile_key].c == TILE_TYPE.tree && (tileset[&neighbor_key].c == TILE_TYPE.wall || tileset[&neighbor_key].c == TILE_TYPE.tree) {
+                for (_, _, c) in tileset.neighbors(x, y) {
+                    if is_tree && (c == TILE_TYPE.wall || c == TILE_TYPE.tree) {
                         number_of_trees += 1
                     }
                 }
                 let delete_chance = rng.gen_range(0, number_of_trees);
                 if delete_chance >= 3 {
-                    tile_type = biome.default_floor();
+                    deleted_trees.push((x, y));
                 }
-                new_tile = Tile::new(x, y, tile_type, neighbors);
             }
-            new_tileset.insert(tile_key.to_string(), new_tile);
         }
-        new_tileset
+        for (x, y) in deleted_trees {
+            tileset.set(x, y, biome.default_floor());
+        }
+    }
+
+    // Carve tunnels to or fill in walkable pockets the spawn can't reach (BiomeControl connectivity)
+    fn connect_walkable (biome: &Biome, tileset: &mut TileGrid, spawn: (i32, i32)) {
+        if !tileset.in_bounds(spawn.0, spawn.1) {
+            return;
+        }
+        let start = tileset.index(spawn.0, spawn.1);
+        let is_walkable = |c| TILE_TYPE.is_walkable(c);
+        if biome.biome_control.connectivity == Connectivity::None {
+            return;
+        }
+        // Smoothing or trees can cover the spawn point
+        if !is_walkable(tileset.tiles()[start]) {
+            tileset.tiles_mut()[start] = biome.default_floor();
+        }
+        match biome.biome_control.connectivity {
+            // Keep the outer wall whole, pockets on the border can only exist without it
+            Connectivity::Carve => {
+                let dig_border = !biome.biome_control.outer_wall;
+                connectivity::carve_tunnels(tileset, start, biome.default_floor(), dig_border, is_walkable)
+            },
+            Connectivity::Prune => connectivity::prune_pockets(tileset, start, biome.default_wall(), is_walkable),
+            Connectivity::None => (),
+        }
+    }
+
+    // Percent of walkable tiles reachable from spawn
+    pub fn reachable_area (tileset: &TileGrid, spawn: (i32, i32)) -> f32 {
+        if !tileset.in_bounds(spawn.0, spawn.1) {
+            return 0.0;
+        }
+        connectivity::reachable_percent(tileset, tileset.index(spawn.0, spawn.1), |c| TILE_TYPE.is_walkable(c))
     }
 
     // Convert all tiles found at edges of map to wall
-    fn add_wall_borders (sizex: i32, sizey: i32, biome: &Biome, tileset: HashMap<String, Tile>) -> HashMap<String, Tile> {
-        let mut new_tileset = HashMap::new();
-        for tile_key in tileset.keys() {
-            let x = tileset[tile_key].x;
-            let y = tileset[tile_key].y;
-            let neighbors = tileset[tile_key].neighbors.clone();
-            let new_tile;
-            if x == 0 || y == 0 || x == sizex-1 || y == sizey-1 {
-                new_tile = Tile::new(x, y, biome.default_wall(), neighbors);
-            } else {
-                new_tile = tileset[tile_key].clone();
+    fn add_wall_borders (biome: &Biome, tileset: &mut TileGrid) {
+        for index in 0..tileset.len() {
+            let (x, y) = tileset.position(index);
+            if tileset.is_border(x, y) {
+                tileset.tiles_mut()[index] = biome.default_wall();
             }
-            new_tileset.insert(tile_key.to_string(), new_tile);
         }
-        new_tileset
     }
 
-    //
-    pub fn draw_road(sizex: i32, sizey: i32, start_tile: String, end_tile: String, mut tileset: HashMap<String, Tile>) -> HashMap<String, Tile> {
-        let mut path_map = PathMap::new(sizex, sizey, &tileset);
-        let path = PathMap::find_path(start_tile, end_tile, path_map.path_tiles, &tileset);
-        for tile in path {
-            let new_tile = Tile::new(tileset[&tile].x, tileset[&tile].y, TILE_TYPE.road, tileset[&tile].neighbors.clone());
-            tileset.insert(tile.to_string(), new_tile);
+    // Road network between the exits (exit_roads) and the spawn (roads), points are joined in order (spawn first, then
+    // exits by side) and each one is routed to the closest point already on the network, reusing the roads on the way
+    pub fn add_roads(&mut self, biome: &Biome) {
+        let mut points: Vec<(i32, i32)> = Vec::new();
+        if biome.biome_control.roads {
+            points.push((self.header.spawn_x, self.header.spawn_y));
+        }
+        if biome.biome_control.exit_roads {
+            points.extend(self.header.exits.iter().map(|exit| (exit.x, exit.y)));
+        }
+        points.retain(|(x, y)| self.tileset.in_bounds(*x, *y));
+        let mut path_map = PathMap::new(&self.tileset);
+        for i in 1..points.len() {
+            let (x, y) = points[i];
+            let closest = points[..i].iter().cloned().min_by_key(|(other_x, other_y)| (other_x - x).abs() + (other_y - y).abs());
+            if let Some(closest) = closest {
+                Map::draw_road_with(&mut path_map, closest, points[i], &mut self.tileset);
+            }
         }
-        tileset
     }
 
+    // Roads follow open ground and old roads, cross water on bridges and only tunnel through walls when there is no way around
+    pub fn draw_road(start_tile: (i32, i32), end_tile: (i32, i32), tileset: &mut TileGrid) {
+        let mut path_map = PathMap::new(tileset);
+        Map::draw_road_with(&mut path_map, start_tile, end_tile, tileset);
+    }
+    // Endpoints outside the map draw nothing
+    fn draw_road_with(path_map: &mut PathMap, start_tile: (i32, i32), end_tile: (i32, i32), tileset: &mut TileGrid) {
+        if !tileset.in_bounds(start_tile.0, start_tile.1) || !tileset.in_bounds(end_tile.0, end_tile.1) {
+            return;
+        }
+        let start_node = tileset.index(start_tile.0, start_tile.1);
+        let end_node = tileset.index(end_tile.0, end_tile.1);
+        if let Some(path) = path_map.find_path(start_node, end_node, tileset, &CostProfile::road_builder(), Movement::Sides) {
+            for tile in path.tiles {
+                let c = tileset.tiles()[tile];
+                // Exits stay exits so the world can still find them
+                if c == TILE_TYPE.water {
+                    tileset.tiles_mut()[tile] = TILE_TYPE.bridge;
+                } else if c != TILE_TYPE.exit && c != TILE_TYPE.bridge {
+                    tileset.tiles_mut()[tile] = TILE_TYPE.road;
+                }
+            }
+        }
+    }
+
+
+    // Opens a file for reading to decompress, deserialize, and store as Map
+    // Legacy map files (tileset HashMap with '$' metadata tiles) are upgraded, saving writes the new layout
+    pub fn load_map(filename: &str, compression: bool) -> Result<Map, MapError> {
+        let s = Map::read_file(filename, compression)?;
+        let json_error = |e| MapError::Json {path: filename.to_string(), source: e};
+        let value: serde_json::Value = serde_json::from_str(&s).map_err(json_error)?;
+        if value.get("header").is_some() {
+            let map_file: MapFile = serde_json::from_value(value).map_err(json_error)?;
+            let header = map_file.header;
+            let grid = TileGrid::from_rows(header.size_x, header.size_y, &map_file.tiles, header.default_wall);
+            Ok(Map::new(header, grid))
+        } else {
+            let tileset: HashMap<String, Tile> = serde_json::from_value(value).map_err(json_error)?;
+            Map::from_legacy_tileset(filename, tileset)
+        }
+    }
 
-    // Opens a file for reading to decompress, deserialize, and store as hashmap
-    pub fn load_map(filename: &str, compression: bool) -> Map {
-        let mut f = File::open(filename).expect("Unable to open file");
+    // Serialize header and tile rows into string, open a file for writing, write to file with compressed bufwriter
+    pub fn save_map (filename: &str, map: &Map, compression: bool) -> Result<(), MapError> {
+        let map_file = MapFile {header: map.header.clone(), tiles: map.tileset.to_rows()};
+        let serialized = serde_json::to_string(&map_file).expect("Map files always serialize");
+        Map::write_file(filename, serialized.as_bytes(), compression)
+    }
+    // Read a whole map/world file, gunzip first if compression
+    fn read_file (filename: &str, compression: bool) -> Result<String, MapError> {
+        let mut f = File::open(filename).map_err(|e| MapError::Io {path: filename.to_string(), source: e})?;
         let mut s = String::new();
         if compression {
-            s = Map::decompress(&f);
+            s = Map::decompress(&f).map_err(|e| MapError::Decompress {path: filename.to_string(), source: e})?;
         } else {
-            f.read_to_string(&mut s).unwrap();
+            f.read_to_string(&mut s).map_err(|e| MapError::Io {path: filename.to_string(), source: e})?;
         }
-        //GzDecoder::new(f).read_to_string(&mut s).unwrap();
-        let tileset: HashMap<String, Tile> = serde_json::from_str(&s).unwrap();
-        let mut map = Map::new(tileset);
-        map = map.fix_map_world_position();
-        map
+        Ok(s)
     }
-
-    // Serialize hashmap into string, open a file for writing, write to file with compressed bufwriter
-    pub fn save_map (filename: &str, map: &Map, compression: bool) {
-        let serialized = serde_json::to_string(&map.tileset).unwrap();
-        let f = File::create(filename).expect("Unable to create file");
-        let enc: flate2::write::GzEncoder<std::fs::File>;
-        // if compression enabled, gzip here
+    // Write a whole map/world file, gzip first if compression (flushes so write errors aren't lost on drop)
+    fn write_file (filename: &str, data: &[u8], compression: bool) -> Result<(), MapError> {
+        let io_error = |e| MapError::Io {path: filename.to_string(), source: e};
+        let f = File::create(filename).map_err(io_error)?;
         if compression {
-            enc = Map::compress(f);
-            let mut buf = BufWriter::new(enc);
-            buf.write_all(serialized.as_bytes()).expect("Unable to write data");
+            let mut buf = BufWriter::new(Map::compress(f));
+            buf.write_all(data).map_err(io_error)?;
+            let enc = buf.into_inner().map_err(|e| io_error(e.into()))?;
+            enc.finish().map_err(io_error)?;
         } else {
-            //enc = f;
             let mut buf = BufWriter::new(f);
-            buf.write_all(serialized.as_bytes()).expect("Unable to write data");
+            buf.write_all(data).map_err(io_error)?;
+            buf.flush().map_err(io_error)?;
         }
+        Ok(())
+    }
+    // Load a map file and find a path between two "XxY" tile keys, returns the tile keys of the path
+    pub fn path_find_file (filename: &str, start_tile: &str, end_tile: &str, profile: &CostProfile, movement: Movement) -> Result<Vec<String>, MapError> {
+        // Load map file
+        let map = Map::load_map(filename, false)?;
+        // Convert tile keys to grid indexes
+        let start_node = map.tileset.key_index(start_tile)?;
+        let end_node = map.tileset.key_index(end_tile)?;
+        // Create new PathMap overlay (copy of Map but with cost/parent info)
+        let mut path_map = PathMap::new(&map.tileset);
+        // Get the path in Vec<usize> (grid indexes), no path gives an empty list
+        let path = match path_map.find_path(start_node, end_node, &map.tileset, profile, movement) {
+            Some(path) => path.tiles,
+            None => Vec::new(),
+        };
+        Ok(path.iter().map(|tile| {
+            let (x, y) = map.tileset.position(*tile);
+            Tile::key(x, y)
+        }).collect())
+    }
+    // Same as path_find_file but through the HPA* graph cached next to the map file (built and saved on first use)
+    pub fn path_find_file_hierarchical (filename: &str, start_tile: &str, end_tile: &str, profile: &CostProfile, movement: Movement) -> Result<Vec<String>, MapError> {
+        let map = Map::load_map(filename, false)?;
+        let start_node = map.tileset.key_index(start_tile)?;
+        let end_node = map.tileset.key_index(end_tile)?;
+        let graph = ClusterGraph::load_or_build(filename, &map.tileset, profile, movement, hpa::DEFAULT_CLUSTER_SIZE)?;
+        let path = match graph.find_path(&map.tileset, profile, movement, start_node, end_node) {
+            Some(path) => path.tiles,
+            None => Vec::new(),
+        };
+        Ok(path.iter().map(|tile| {
+            let (x, y) = map.tileset.position(*tile);
+            Tile::key(x, y)
+        }).collect())
     }
     // Write wrapper to compress file, return encoder file
     pub fn compress(file: File) -> flate2::write::GzEncoder<std::fs::File>  {
@@ -734,12 +1279,589 @@ impl Map {
         enc
     }
     // Write wrapper to decompress file, return string
-    pub fn decompress(f: &std::fs::File) -> String{
+    pub fn decompress(f: &std::fs::File) -> std::io::Result<String> {
         let mut s = String::new();
-        GzDecoder::new(f).read_to_string(&mut s).unwrap();
-        s
+        GzDecoder::new(f).read_to_string(&mut s)?;
+        Ok(s)
+    }
+
+}
+
+#[gdnative::methods]
+impl GodotMap {
+    // Make required directories, load built-in biomes and any biomes in resources/biomes
+    fn _init(_owner: Node) -> Self {
+        Map::prep();
+        let mut biomes = BiomeRegistry::builtin();
+        if Path::new(BIOME_DIRECTORY).is_dir() {
+            if let Err(e) = biomes.load_dir(BIOME_DIRECTORY) {
+                godot_error!("Unable to load biomes: {}", e);
+            }
+        }
+        let mut cost_profiles = HashMap::new();
+        for profile_name in CostProfile::builtin_names() {
+            if let Some(profile) = CostProfile::builtin(&profile_name) {
+                cost_profiles.insert(profile_name, profile);
+            }
+        }
+        GodotMap {biomes: biomes, cost_profiles: cost_profiles, flow_fields: HashMap::new(), map: None, path_map: None,
+                 jobs: JobPool::new(jobs::DEFAULT_WORKERS), world: None, last_error: String::new()}
+    }
+    fn register_signals(builder: &init::ClassBuilder<Self>) {
+        builder.add_signal(init::Signal {
+            name: "map_ready",
+            args: &[
+                init::SignalArgument {
+                    name: "job_id",
+                    default: Variant::from_i64(0),
+                    export_info: init::ExportInfo::new(VariantType::I64),
+                    usage: init::PropertyUsage::DEFAULT,
+                },
+                init::SignalArgument {
+                    name: "biome_name",
+                    default: Variant::from_str(""),
+                    export_info: init::ExportInfo::new(VariantType::GodotString),
+                    usage: init::PropertyUsage::DEFAULT,
+                },
+            ],
+        });
+        builder.add_signal(init::Signal {
+            name: "job_cancelled",
+            args: &[
+                init::SignalArgument {
+                    name: "job_id",
+                    default: Variant::from_i64(0),
+                    export_info: init::ExportInfo::new(VariantType::I64),
+                    usage: init::PropertyUsage::DEFAULT,
+                },
+            ],
+        });
+    }
+    // Not used
+    #[export]
+    fn _ready(&self, _owner: Node) {
+
+    }
+    // Signals for background jobs that finished since the last frame
+    #[export]
+    unsafe fn _process(&mut self, mut owner: Node, _delta: f64) {
+        for (id, status) in self.jobs.finished() {
+            if status == JobStatus::Done {
+                let biome_name = self.jobs.biome_name(id).unwrap_or_default();
+                owner.emit_signal(GodotString::from_str("map_ready"), &[Variant::from_i64(id as i64), Variant::from_str(&biome_name)]);
+            } else {
+                owner.emit_signal(GodotString::from_str("job_cancelled"), &[Variant::from_i64(id as i64)]);
+            }
+        }
+    }
+    #[export] // Message of the last call that failed, empty if the last call worked
+    fn godot_last_error(&self, _owner: Node) -> GodotString {
+        GodotString::from_str(&self.last_error)
+    }
+    #[export] // Load extra biome definitions from a directory, returns number loaded or -1 if any file is invalid
+    fn godot_load_biomes(&mut self, _owner: Node, godot_directory: GodotString) -> i64 {
+        let result = self.biomes.load_dir(&godot_directory.to_string());
+        match self.check(result) {
+            Some(number_loaded) => number_loaded as i64,
+            None => -1,
+        }
+    }
+    #[export] // Names of every biome that can be passed to godot_new_biome
+    fn godot_biome_names(&self, _owner: Node) -> StringArray {
+        let mut godot_array: StringArray = StringArray::new();
+        for biome_name in self.biomes.names() {
+            godot_array.push(&GodotString::from_str(&biome_name));
+        }
+        godot_array
+    }
+    #[export] // Same seed and biome always writes the same map file, returns 0 or an error code (see godot_last_error)
+    unsafe fn godot_new_biome(&mut self, _owner: Node, godot_file_name: GodotString, godot_biome_name: GodotString, seed: i64) -> i64 {
+        // Convert godot string to rust string
+        let file_name = godot_file_name.to_string();
+        let biome = self.biomes.get_or_fallback(&godot_biome_name.to_string());
+        let m = Map::new_biome(50, 50, &biome, seed as u64);
+        let result = Map::save_map(&file_name.to_string(), &m, false);
+        self.check_code(result)
+    }
+    #[export] // Seed picks the biome as well as the map, same seed always gives the same biome, empty string if saving failed
+    unsafe fn godot_random_biome(&mut self, _owner: Node, godot_file_name: GodotString, seed: i64) -> GodotString {
+        let biome_name = self.biomes.random_biome(seed as u64);
+        let biome = self.biomes.get_or_fallback(&biome_name);
+        let file_name = godot_file_name.to_string();
+        let m = Map::new_biome(50, 50, &biome, seed as u64);
+        let result = Map::save_map(&file_name.to_string(), &m, false);
+        if self.check(result).is_none() {
+            return GodotString::new();
+        }
+        // Return the random biome to godot for logging
+        GodotString::from_str(&biome_name)
+    }
+    #[export] // Statistics of a map file as a Dictionary (same fields as MapReport), uses the biome named in the map header
+    fn godot_map_report(&mut self, _owner: Node, godot_file_name: GodotString) -> Dictionary {
+        let mut dictionary = Dictionary::new();
+        let result = Map::load_map(&godot_file_name.to_string(), false);
+        let map = match self.check(result) {
+            Some(map) => map,
+            None => return dictionary,
+        };
+        let biome = self.biomes.get_or_fallback(&map.header.biome);
+        let report = serde_json::to_value(map.report(&biome)).expect("Map reports always serialize");
+        if let serde_json::Value::Object(fields) = report {
+            for (key, value) in fields.iter() {
+                dictionary.set(&Variant::from_str(key), &json_to_variant(value));
+            }
+        }
+        dictionary
+    }
+
+    #[export] // Add or replace a cost profile from json ({"name": "climber", "costs": {"floor": 1, "wall": 5, "water": null}}), returns 0 or an error code
+    fn godot_add_cost_profile(&mut self, _owner: Node, godot_json: GodotString) -> i64 {
+        let result = CostProfile::from_json(&godot_json.to_string()).map(|profile| {
+            self.cost_profiles.insert(profile.name.clone(), profile);
+        });
+        self.check_code(result)
+    }
+    #[export] // Names of every cost profile that can be passed to godot_path_find
+    fn godot_cost_profile_names(&self, _owner: Node) -> StringArray {
+        let mut profile_names: Vec<&String> = self.cost_profiles.keys().collect();
+        profile_names.sort();
+        let mut godot_array: StringArray = StringArray::new();
+        for profile_name in profile_names {
+            godot_array.push(&GodotString::from_str(profile_name));
+        }
+        godot_array
+    }
+    #[export] // Specify a map file to read, start_tile, end_tile, cost profile ("walker") and movement ("sides", "diagonal", "no_corner_cutting")
+    // Returns the path from start to end (empty if anything failed)
+    pub fn godot_path_find(&mut self, _owner: Node, godot_file_name: GodotString, start_tile: GodotString, end_tile: GodotString,
+                           godot_profile: GodotString, godot_movement: GodotString) -> StringArray {
+        let result = self.cost_profile(&godot_profile.to_string()).and_then(|profile| {
+            let movement = GodotMap::movement(&godot_movement.to_string())?;
+            Map::path_find_file(&godot_file_name.to_string(), &start_tile.to_string(), &end_tile.to_string(), &profile, movement)
+        });
+        // Convert to Godot StringArray of tile keys, and return
+        let mut godot_array: StringArray = StringArray::new();
+        for key in self.check(result).unwrap_or_default() {
+            godot_array.push(&GodotString::from_str(&key))
+        }
+        godot_array
+    }
+    #[export] // Same as godot_path_find but near-optimal and much faster on big maps, first call per profile/movement builds the .hpa cache file
+    pub fn godot_path_find_hierarchical(&mut self, _owner: Node, godot_file_name: GodotString, start_tile: GodotString, end_tile: GodotString,
+                                        godot_profile: GodotString, godot_movement: GodotString) -> StringArray {
+        let result = self.cost_profile(&godot_profile.to_string()).and_then(|profile| {
+            let movement = GodotMap::movement(&godot_movement.to_string())?;
+            Map::path_find_file_hierarchical(&godot_file_name.to_string(), &start_tile.to_string(), &end_tile.to_string(), &profile, movement)
+        });
+        let mut godot_array: StringArray = StringArray::new();
+        for key in self.check(result).unwrap_or_default() {
+            godot_array.push(&GodotString::from_str(&key))
+        }
+        godot_array
+    }
+    #[export] // Flow field from every tile of a map file to the closest goal tile, kept under field_name for godot_flow_move_goals
+    // Returns the cost of each tile in row-major order (-1 where no goal can be reached), empty if anything failed
+    pub fn godot_flow_field(&mut self, _owner: Node, field_name: GodotString, godot_file_name: GodotString, goals: StringArray,
+                            godot_profile: GodotString, godot_movement: GodotString) -> Int32Array {
+        let result = self.cost_profile(&godot_profile.to_string()).and_then(|profile| {
+            let movement = GodotMap::movement(&godot_movement.to_string())?;
+            let map = Map::load_map(&godot_file_name.to_string(), false)?;
+            let goals = GodotMap::tile_indexes(&map.tileset, &goals)?;
+            let field = FlowField::new(&map.tileset, &profile, movement, &goals);
+            Ok((map.tileset, field))
+        });
+        match self.check(result) {
+            Some((tileset, field)) => {
+                let costs = GodotMap::flow_costs(&field);
+                self.flow_fields.insert(field_name.to_string(), (tileset, field));
+                costs
+            },
+            None => Int32Array::new(),
+        }
+    }
+    #[export] // Move the goals of a flow field, only tiles whose route changed are recomputed, returns the costs like godot_flow_field
+    pub fn godot_flow_move_goals(&mut self, _owner: Node, field_name: GodotString, goals: StringArray) -> Int32Array {
+        let result = match self.flow_fields.get_mut(&field_name.to_string()) {
+            Some((tileset, field)) => GodotMap::tile_indexes(tileset, &goals).map(|goals| {
+                field.set_goals(tileset, &goals);
+                GodotMap::flow_costs(field)
+            }),
+            None => Err(MapError::NotFound(format!("unknown flow field '{}'", field_name.to_string()))),
+        };
+        self.check(result).unwrap_or_else(Int32Array::new)
+    }
+    #[export] // Direction to step from each tile (index into (1,0) (-1,0) (0,1) (0,-1) (1,1) (-1,-1) (1,-1) (-1,1)), -1 on goals and unreachable tiles
+    pub fn godot_flow_directions(&mut self, _owner: Node, field_name: GodotString) -> Int32Array {
+        let mut directions = Int32Array::new();
+        match self.flow_fields.get(&field_name.to_string()) {
+            Some((tileset, field)) => {
+                for direction in field.directions(tileset) {
+                    directions.push(direction);
+                }
+            },
+            None => {
+                let result: Result<(), MapError> = Err(MapError::NotFound(format!("unknown flow field '{}'", field_name.to_string())));
+                self.check(result);
+            },
+        }
+        directions
+    }
+    #[export] // Forget a flow field
+    pub fn godot_flow_clear(&mut self, _owner: Node, field_name: GodotString) {
+        self.flow_fields.remove(&field_name.to_string());
+    }
+    #[export] // Generate a map into the node (replaces the resident map), unknown biome names use the fallback biome
+    // Returns 0 or an error code (sizes must be 1 to MAX_MAP_SIZE)
+    pub fn godot_map_generate(&mut self, _owner: Node, godot_biome_name: GodotString, size_x: i64, size_y: i64, seed: i64) -> i64 {
+        let (size_x, size_y) = match Map::check_size(size_x, size_y) {
+            Ok(size) => size,
+            Err(e) => return self.check_code(Err(e)),
+        };
+        let biome = self.biomes.get_or_fallback(&godot_biome_name.to_string());
+        self.set_resident(Map::new_biome(size_x, size_y, &biome, seed as u64));
+        self.check_code(Ok(()))
+    }
+    #[export] // Generate a map of a biome picked from the seed into the node, returns the biome name (empty for bad sizes)
+    pub fn godot_map_generate_random(&mut self, _owner: Node, size_x: i64, size_y: i64, seed: i64) -> GodotString {
+        let (size_x, size_y) = match self.check(Map::check_size(size_x, size_y)) {
+            Some(size) => size,
+            None => return GodotString::new(),
+        };
+        let biome_name = self.biomes.random_biome(seed as u64);
+        let biome = self.biomes.get_or_fallback(&biome_name);
+        self.set_resident(Map::new_biome(size_x, size_y, &biome, seed as u64));
+        GodotString::from_str(&biome_name)
+    }
+    #[export] // Generate a map on a worker thread, empty biome name picks one from the seed, returns the job id (map_ready is emitted when done)
+    // Returns -1 without queuing anything for sizes outside 1 to MAX_MAP_SIZE
+    pub fn godot_generate_async(&mut self, _owner: Node, godot_biome_name: GodotString, size_x: i64, size_y: i64, seed: i64) -> i64 {
+        let (size_x, size_y) = match self.check(Map::check_size(size_x, size_y)) {
+            Some(size) => size,
+            None => return -1,
+        };
+        let mut biome_name = godot_biome_name.to_string();
+        if biome_name.is_empty() {
+            biome_name = self.biomes.random_biome(seed as u64);
+        }
+        let biome = self.biomes.get_or_fallback(&biome_name);
+        let result = self.jobs.generate(size_x, size_y, biome, seed as u64);
+        self.check(result).map_or(-1, |id| id as i64)
+    }
+    #[export] // "queued", "running" or "done", empty string for unknown jobs (cancelled, already taken or dropped as unclaimed)
+    pub fn godot_job_status(&self, _owner: Node, job_id: i64) -> GodotString {
+        match self.jobs.status(job_id as u64) {
+            Some(status) => GodotString::from_str(status.name()),
+            None => GodotString::new(),
+        }
+    }
+    #[export] // 0.0 to 1.0, -1.0 for unknown jobs
+    pub fn godot_job_progress(&self, _owner: Node, job_id: i64) -> f64 {
+        self.jobs.progress(job_id as u64).map_or(-1.0, |progress| progress as f64)
+    }
+    #[export] // Stop a queued or running job, false if it already finished (job_cancelled is emitted once it stops)
+    pub fn godot_cancel_job(&mut self, _owner: Node, job_id: i64) -> bool {
+        self.jobs.cancel(job_id as u64)
+    }
+    #[export] // Make a finished job's map the resident map, returns 0 or an error code (the job is forgotten afterwards)
+    pub fn godot_job_take_map(&mut self, _owner: Node, job_id: i64) -> i64 {
+        let result = match self.jobs.take_map(job_id as u64) {
+            Some(map) => Ok(self.set_resident(map)),
+            None => Err(MapError::NotFound(format!("job {} has no finished map", job_id))),
+        };
+        self.check_code(result)
+    }
+    #[export] // Load a map file into the node, returns 0 or an error code (the resident map is kept if loading fails)
+    pub fn godot_map_load(&mut self, _owner: Node, godot_file_name: GodotString) -> i64 {
+        let result = Map::load_map(&godot_file_name.to_string(), false).map(|map| self.set_resident(map));
+        self.check_code(result)
+    }
+    #[export] // Save the resident map (with any edits) to a file, returns 0 or an error code
+    pub fn godot_map_save(&mut self, _owner: Node, godot_file_name: GodotString) -> i64 {
+        let result = self.resident().and_then(|map| Map::save_map(&godot_file_name.to_string(), map, false));
+        self.check_code(result)
+    }
+    #[export] // Header of the resident map as a Dictionary (size, biome, seed, spawn, exits...), empty if there is no map
+    pub fn godot_map_header(&mut self, _owner: Node) -> Dictionary {
+        let mut dictionary = Dictionary::new();
+        let result = self.resident().map(|map| serde_json::to_value(&map.header).expect("Map headers always serialize"));
+        if let Some(serde_json::Value::Object(fields)) = self.check(result) {
+            for (key, value) in fields.iter() {
+                dictionary.set(&Variant::from_str(key), &json_to_variant(value));
+            }
+        }
+        dictionary
+    }
+    #[export] // Tile ids of the resident map in row-major order (index y * size_x + x), see godot_map_metadata for what each id is
+    pub fn godot_map_tiles(&mut self, _owner: Node) -> ByteArray {
+        let mut tiles = ByteArray::new();
+        let result = self.resident().map(|map| map.tileset.to_ids());
+        for id in self.check(result).unwrap_or_default() {
+            tiles.push(id);
+        }
+        tiles
+    }
+    #[export] // Header of the resident map plus "tile_names" and "tile_chars" (both indexed by tile id), empty if there is no map
+    pub fn godot_map_metadata(&mut self, owner: Node) -> Dictionary {
+        let mut dictionary = self.godot_map_header(owner);
+        if self.map.is_none() {
+            return dictionary;
+        }
+        let mut tile_names = StringArray::new();
+        let mut tile_chars = StringArray::new();
+        for (tile_name, tile_char) in TILE_TYPE.tile_types().iter() {
+            tile_names.push(&GodotString::from_str(tile_name));
+            tile_chars.push(&GodotString::from_str(&tile_char.to_string()));
+        }
+        dictionary.set(&Variant::from_str("tile_names"), &Variant::from_string_array(&tile_names));
+        dictionary.set(&Variant::from_str("tile_chars"), &Variant::from_string_array(&tile_chars));
+        dictionary
+    }
+    #[export] // Draw the resident map into TileMap nodes (both are cleared first), trees go on overlay with the default floor under them
+    // tile_indexes maps tile type names or chars to TileSet tile indexes ({"floor": 0, "wall": 1, "t": 4}), unmapped tiles stay empty
+    // Pass the same TileMap twice to skip the floor under trees, returns 0 or an error code
+    pub unsafe fn godot_map_fill_tilemap(&mut self, _owner: Node, mut ground: TileMap, mut overlay: TileMap, godot_tile_indexes: Dictionary) -> i64 {
+        let result = GodotMap::tileset_indexes(&godot_tile_indexes).and_then(|tile_indexes| {
+            let map = self.resident()?;
+            Ok((map.tileset.size_x(), TileMapLayers::new(map, &tile_indexes)))
+        });
+        let (size_x, layers) = match result {
+            Ok(cells) => cells,
+            Err(e) => return self.check_code(Err(e)),
+        };
+        ground.clear();
+        overlay.clear();
+        let no_autotile = Vector2::new(0.0, 0.0);
+        for (i, (ground_index, overlay_index)) in layers.ground.iter().zip(layers.overlay.iter()).enumerate() {
+            let (x, y) = (i as i64 % size_x as i64, i as i64 / size_x as i64);
+            if *ground_index >= 0 {
+                ground.set_cell(x, y, *ground_index, false, false, false, no_autotile);
+            }
+            if *overlay_index >= 0 {
+                overlay.set_cell(x, y, *overlay_index, false, false, false, no_autotile);
+            }
+        }
+        self.check_code(Ok(()))
+    }
+    #[export] // Tile char at x, y of the resident map, empty string if there is no map or it's outside the map
+    pub fn godot_map_get_tile(&mut self, _owner: Node, x: i64, y: i64) -> GodotString {
+        let result = self.resident().and_then(|map| map.tile(x, y));
+        match self.check(result) {
+            Some(c) => GodotString::from_str(&c.to_string()),
+            None => GodotString::new(),
+        }
+    }
+    #[export] // Change a tile of the resident map, tile type is a name ("water") or char ("~"), returns 0 or an error code
+    pub fn godot_map_set_tile(&mut self, _owner: Node, x: i64, y: i64, godot_tile_type: GodotString) -> i64 {
+        let result = match self.map.as_mut() {
+            Some(map) => map.set_tile(x, y, &godot_tile_type.to_string()),
+            None => Err(GodotMap::no_resident_map()),
+        };
+        self.check_code(result)
+    }
+    #[export] // True if the player can stand on a tile type, a name ("road") or char ("="), same tiles the connectivity pass walks on
+    pub fn godot_is_walkable(&self, _owner: Node, godot_tile_type: GodotString) -> bool {
+        match TILE_TYPE.from_name(&godot_tile_type.to_string()) {
+            Some(tile_type) => TILE_TYPE.is_walkable(tile_type),
+            None => false,
+        }
+    }
+    #[export] // Same as godot_path_find on the resident map, no file is read
+    pub fn godot_map_path_find(&mut self, _owner: Node, start_tile: GodotString, end_tile: GodotString,
+                               godot_profile: GodotString, godot_movement: GodotString) -> StringArray {
+        let result = self.cost_profile(&godot_profile.to_string()).and_then(|profile| {
+            let movement = GodotMap::movement(&godot_movement.to_string())?;
+            let map = self.map.as_ref().ok_or_else(GodotMap::no_resident_map)?;
+            let start_node = map.tileset.key_index(&start_tile.to_string())?;
+            let end_node = map.tileset.key_index(&end_tile.to_string())?;
+            let path_map = self.path_map.get_or_insert_with(|| PathMap::new(&map.tileset));
+            let path = path_map.find_path(start_node, end_node, &map.tileset, &profile, movement).map(|path| path.tiles).unwrap_or_default();
+            Ok(path.iter().map(|tile| {
+                let (x, y) = map.tileset.position(*tile);
+                Tile::key(x, y)
+            }).collect::<Vec<String>>())
+        });
+        let mut godot_array: StringArray = StringArray::new();
+        for key in self.check(result).unwrap_or_default() {
+            godot_array.push(&GodotString::from_str(&key))
+        }
+        godot_array
     }
+    #[export] // Same as godot_flow_field on the resident map, later edits to the map don't change the field
+    pub fn godot_map_flow_field(&mut self, _owner: Node, field_name: GodotString, goals: StringArray,
+                                godot_profile: GodotString, godot_movement: GodotString) -> Int32Array {
+        let result = self.cost_profile(&godot_profile.to_string()).and_then(|profile| {
+            let movement = GodotMap::movement(&godot_movement.to_string())?;
+            let map = self.resident()?;
+            let goals = GodotMap::tile_indexes(&map.tileset, &goals)?;
+            let field = FlowField::new(&map.tileset, &profile, movement, &goals);
+            Ok((map.tileset.clone(), field))
+        });
+        match self.check(result) {
+            Some((tileset, field)) => {
+                let costs = GodotMap::flow_costs(&field);
+                self.flow_fields.insert(field_name.to_string(), (tileset, field));
+                costs
+            },
+            None => Int32Array::new(),
+        }
+    }
+    #[export] // Path across the maps of a saved world (a .world path or a world name in /tmp/worlds), start/end are a world map name ("x0y0z0") and a tile key
+    // Routes are near-optimal (HPA* inside each map), returns one Dictionary per tile ({"map": "x0y0z0", "tile": "5x5"}), empty if anything failed or there is no route
+    pub fn godot_world_path_find(&mut self, _owner: Node, godot_world: GodotString, start_map: GodotString, start_tile: GodotString,
+                                 end_map: GodotString, end_tile: GodotString, godot_profile: GodotString, godot_movement: GodotString) -> VariantArray {
+        let result = self.cost_profile(&godot_profile.to_string()).and_then(|profile| {
+            let movement = GodotMap::movement(&godot_movement.to_string())?;
+            let world = World::open(&godot_world.to_string())?;
+            find_world_path(&world, &start_map.to_string(), &start_tile.to_string(), &end_map.to_string(), &end_tile.to_string(), &profile, movement)
+        });
+        let mut godot_array = VariantArray::new();
+        if let Some(path) = self.check(result).and_then(|path| path) {
+            for step in path.steps {
+                let mut dictionary = Dictionary::new();
+                dictionary.set(&Variant::from_str("map"), &Variant::from_str(&step.map));
+                dictionary.set(&Variant::from_str("tile"), &Variant::from_str(&Tile::key(step.x, step.y)));
+                godot_array.push(&Variant::from_dictionary(&dictionary));
+            }
+        }
+        godot_array
+    }
+    #[export] // Open a world file (path to a .world file, or a world name in /tmp/worlds), returns 0 or an error code
+    pub fn godot_world_open(&mut self, _owner: Node, godot_world: GodotString) -> i64 {
+        let result = World::open(&godot_world.to_string()).map(|world| self.world = Some(LazyWorld::new(world, self.biomes.clone(), DEFAULT_RESIDENT_MAPS)));
+        self.check_code(result)
+    }
+    #[export] // New world without edges in /tmp/worlds/world_name (replaces the open world), maps are generated by godot_world_load_map
+    // Returns 0 or an error code (map sizes must be 1 to MAX_MAP_SIZE)
+    pub fn godot_world_new(&mut self, _owner: Node, godot_world_name: GodotString, seed: i64, map_size_x: i64, map_size_y: i64) -> i64 {
+        let (map_size_x, map_size_y) = match Map::check_size(map_size_x, map_size_y) {
+            Ok(size) => size,
+            Err(e) => return self.check_code(Err(e)),
+        };
+        let mut world = World::new_unbounded(godot_world_name.to_string(), seed as u64);
+        world.map_size_x = map_size_x;
+        world.map_size_y = map_size_y;
+        let result = World::save_world(&world).map(|_| self.world = Some(LazyWorld::new(world, self.biomes.clone(), DEFAULT_RESIDENT_MAPS)));
+        self.check_code(result)
+    }
+    #[export] // World fields plus "maps" (map name -> biome, seed, file, exits, status...) of the open world, empty if none is open
+    pub fn godot_world_manifest(&mut self, _owner: Node) -> Dictionary {
+        let mut dictionary = Dictionary::new();
+        let result = self.open_world().map(|world| serde_json::to_value(&world.world).expect("Worlds always serialize"));
+        if let Some(serde_json::Value::Object(fields)) = self.check(result) {
+            for (key, value) in fields.iter() {
+                dictionary.set(&Variant::from_str(key), &json_to_variant(value));
+            }
+        }
+        dictionary
+    }
+    #[export] // Load a map of the open world into the node (replaces the resident map), maps that don't exist yet are generated first
+    // (e.g. the target_map of an exit the player walked through), returns 0 or an error code
+    pub fn godot_world_load_map(&mut self, _owner: Node, map_name: GodotString) -> i64 {
+        let result = self.open_world().and_then(|world| world.map(&map_name.to_string()).map(|map| map.clone())).map(|map| self.set_resident(map));
+        self.check_code(result)
+    }
+    #[export] // Climate of the open world at a position: elevation, moisture, temperature (0.0 to 1.0), depth and the biome it picks
+    // Empty if no world is open or the position is outside it, enough to draw a world map without generating anything
+    pub fn godot_world_climate(&mut self, _owner: Node, x: i64, y: i64, z: i64) -> Dictionary {
+        let mut dictionary = Dictionary::new();
+        let result = self.open_world().and_then(|world| {
+            let (x, y, z) = world.world.position(x, y, z)?;
+            let climate = world.world.climate.climate_at(world.world.seed, x, y, z);
+            Ok((serde_json::to_value(climate).expect("Climates always serialize"), world.world.climate.biome_for(&climate)))
+        });
+        if let Some((serde_json::Value::Object(fields), biome)) = self.check(result) {
+            for (key, value) in fields.iter() {
+                dictionary.set(&Variant::from_str(key), &json_to_variant(value));
+            }
+            dictionary.set(&Variant::from_str("biome"), &Variant::from_str(&biome));
+        }
+        dictionary
+    }
+    #[export] // Replace the open world's climate with a json/toml layout file and save the world file, returns 0 or an error code
+    // Maps that are already generated keep their biome
+    pub fn godot_world_load_climate(&mut self, _owner: Node, file: GodotString) -> i64 {
+        let result = ClimateModel::load_file(Path::new(&file.to_string())).and_then(|climate| {
+            let world = self.open_world()?;
+            world.world.climate = climate;
+            World::save_world(&world.world)
+        });
+        self.check_code(result)
+    }
+}
 
+// Error bookkeeping for the exported methods, failures are logged and kept for godot_last_error
+impl GodotMap {
+    fn cost_profile(&self, profile_name: &str) -> Result<CostProfile, MapError> {
+        self.cost_profiles.get(profile_name).cloned()
+            .ok_or_else(|| MapError::CostProfile(format!("unknown cost profile '{}'", profile_name)))
+    }
+    fn movement(movement_name: &str) -> Result<Movement, MapError> {
+        Movement::from_name(movement_name)
+            .ok_or_else(|| MapError::CostProfile(format!("unknown movement '{}'", movement_name)))
+    }
+    fn open_world(&mut self) -> Result<&mut LazyWorld, MapError> {
+        self.world.as_mut().ok_or_else(|| MapError::NotFound(String::from("no world open, call godot_world_open or godot_world_new first")))
+    }
+    fn resident(&self) -> Result<&Map, MapError> {
+        self.map.as_ref().ok_or_else(GodotMap::no_resident_map)
+    }
+    fn no_resident_map() -> MapError {
+        MapError::NotFound(String::from("no map loaded, call godot_map_generate or godot_map_load first"))
+    }
+    // New resident map, the cached PathMap only fits the old map size
+    fn set_resident(&mut self, map: Map) {
+        let same_size = self.map.as_ref().map_or(false, |old| old.tileset.size_x() == map.tileset.size_x() && old.tileset.size_y() == map.tileset.size_y());
+        if !same_size {
+            self.path_map = None;
+        }
+        self.map = Some(map);
+    }
+    // Godot {tile type name or char: TileSet index} Dictionary to tile chars
+    fn tileset_indexes(godot_tile_indexes: &Dictionary) -> Result<HashMap<char, i64>, MapError> {
+        let mut tile_indexes = HashMap::new();
+        let keys = godot_tile_indexes.keys();
+        for i in 0..keys.len() {
+            let key = keys.get_val(i);
+            let tile_name = key.to_godot_string().to_string();
+            let tile_type = TILE_TYPE.from_name(&tile_name).ok_or_else(|| MapError::InvalidTileType(tile_name.clone()))?;
+            let index = godot_tile_indexes.get(&key).try_to_i64()
+                .ok_or_else(|| MapError::InvalidTileType(format!("{} (TileSet index has to be an int)", tile_name)))?;
+            tile_indexes.insert(tile_type, index);
+        }
+        Ok(tile_indexes)
+    }
+    fn tile_indexes(tileset: &TileGrid, keys: &StringArray) -> Result<Vec<usize>, MapError> {
+        (0..keys.len()).map(|i| tileset.key_index(&keys.get(i).to_string())).collect()
+    }
+    fn flow_costs(field: &FlowField) -> Int32Array {
+        let mut costs = Int32Array::new();
+        for cost in field.costs() {
+            costs.push(if *cost == flow::UNREACHABLE { -1 } else { *cost });
+        }
+        costs
+    }
+    fn check<T>(&mut self, result: Result<T, MapError>) -> Option<T> {
+        match result {
+            Ok(value) => {
+                self.last_error.clear();
+                Some(value)
+            },
+            Err(e) => {
+                godot_error!("{}", e);
+                self.last_error = e.to_string();
+                None
+            }
+        }
+    }
+    // 0 for success, MapError::code otherwise
+    fn check_code(&mut self, result: Result<(), MapError>) -> i64 {
+        let code = match &result {
+            Ok(_) => 0,
+            Err(e) => e.code(),
+        };
+        self.check(result);
+        code
+    }
 }
 
 impl Tile {
@@ -747,8 +1869,7 @@ impl Tile {
         Tile { x: x, y: y, c: c, neighbors: neighbors }
     }
     // Return new vector filled with tiles, random xy positions, set specific tile type
-    pub fn new_voronoi_tiles(sizex: i32, sizey: i32, number_of_tiles: i32, tile_type: char, mut voronoi_regions: Vec<Tile>) -> Vec<Tile> {
-        let mut rng = rand::thread_rng();
+    pub fn new_voronoi_tiles(sizex: i32, sizey: i32, number_of_tiles: i32, tile_type: char, mut voronoi_regions: Vec<Tile>, rng: &mut StdRng) -> Vec<Tile> {
         let mut tiles_remaining = number_of_tiles;
         while tiles_remaining > 0 {
             tiles_remaining -= 1;
@@ -765,8 +1886,12 @@ impl Tile {
     }
     // Create new tile key string, xy coordinate with separator
     pub fn get_tile_key(&self) -> String {
-        let tx = &self.x.to_string();
-        let ty = &self.y.to_string();
+        Tile::key(self.x, self.y)
+    }
+    // Tile key string for any xy coordinate
+    pub fn key(x: i32, y: i32) -> String {
+        let tx = &x.to_string();
+        let ty = &y.to_string();
         let sep = String::from("x");
         let mut s = String::new();
         s.push_str(&tx);
@@ -774,6 +1899,13 @@ impl Tile {
         s.push_str(&ty);
         s
     }
+    // Read xy coordinate back out of a tile key, None for metadata keys like "mapsize"
+    pub fn parse_key(key: &str) -> Option<(i32, i32)> {
+        let mut parts = key.splitn(2, 'x');
+        let x = parts.next()?.parse::<i32>().ok()?;
+        let y = parts.next()?.parse::<i32>().ok()?;
+        Some((x, y))
+    }
     // Calculate distance between tiles for v-regions in gen_map
     pub fn distance(v: &Tile, t: &Tile) -> i32 {
         let distance = (v.x - t.x).abs() + (v.y - t.y).abs();
@@ -795,179 +1927,105 @@ impl Tile {
     }
 }
 
-// Abstract biome a little so it's not just dumb data
-impl Biome {
-    fn new(biome_name: String) -> Biome {
-        let biome;
-        let tile_chance;
-        let biome_control;
-        if biome_name == "Cave" {
-            tile_chance = TileChance{floor: 0.3, wall: 0.5, water: 0.2, sand: 0.0, tree: 0.0};
-            biome_control = BiomeControl{outer_wall: true, water_edges: true, sparse_trees: false,
-                                        roads: false, exit_roads: false, exits: false};
-        } else if biome_name == "Ocean" {
-            tile_chance = TileChance{floor: 0.0, wall: 0.05, water: 0.7, sand: 0.15, tree: 0.1};
-            biome_control = BiomeControl{outer_wall: false, water_edges: true, sparse_trees: true,
-                                        roads: false, exit_roads: false, exits: false};
-        } else if biome_name == "Underlake" {
-            tile_chance = TileChance{floor: 0.2, wall: 0.2, water: 0.6, sand: 0.0, tree: 0.0};
-            biome_control = BiomeControl{outer_wall: true, water_edges: true, sparse_trees: false,
-                                            roads: false, exit_roads: false, exits: false};
-        } else if biome_name == "Desert" {
-            tile_chance = TileChance{floor: 0.0, wall: 0.2, water: 0.15, sand: 0.5, tree: 0.15};
-            biome_control = BiomeControl{outer_wall: false, water_edges: true, sparse_trees: true,
-                                        roads: false, exit_roads: false, exits: false};
-        } else if biome_name == "Forest" {
-            tile_chance = TileChance{floor: 0.0, wall: 0.2, water: 0.2, sand: 0.2, tree: 0.4};
-            biome_control = BiomeControl{outer_wall: true, water_edges: true, sparse_trees: true,
-                                        roads: false, exit_roads: false, exits: false};
-        } else {
-            tile_chance = TileChance{floor: 0.33, wall: 0.33, water: 0.33, sand: 0.0, tree: 0.0};
-            biome_control = BiomeControl{outer_wall: true, water_edges: true, sparse_trees: false,
-                                        roads: false, exit_roads: false, exits: false};
-        }
-        biome = Biome {
-            biome_name: biome_name,
-            tile_chance: tile_chance,
-            biome_control: biome_control
-        };
-        biome
-    }
-    pub fn default_floor(&self) -> char {
-        let floor = match self.biome_name.as_ref() {
-            "Cave" => TILE_TYPE.floor,
-            "Ocean" => TILE_TYPE.sand,
-            "Underlake" => TILE_TYPE.floor,
-            "Desert" => TILE_TYPE.sand,
-            "Forest" => TILE_TYPE.sand, //dirt next plz
-            _ => '.',
-        };
-        floor
-    }
-    pub fn default_wall(&self) -> char {
-        let wall = match self.biome_name.as_ref() {
-            "Cave" => TILE_TYPE.wall,
-            "Ocean" => TILE_TYPE.wall, // rock?
-            "Underlake" => TILE_TYPE.wall,
-            "Desert" => TILE_TYPE.wall, // sandy cliff?
-            "Forest" => TILE_TYPE.tree, // tree?
-            _ => TILE_TYPE.wall,
-        };
-        wall
-    }
-}
-
 // Structure copy of the entire map, used to pathfind
 impl PathMap {
     // Create new PathMap, path_tiles is a copy of tileset with costs and parent data (could abstract HPA* later?)
-    pub fn new(map_size_x: i32, map_size_y: i32, tileset: &HashMap<String, Tile>) -> PathMap {
-        let mut path_tiles: HashMap<String, PathTile> = HashMap::new();
-        for tile in tileset.values() {
-            let mut path_tile = PathTile::new(tile.x, tile.y, map_size_x, map_size_y);
-            path_tiles.insert(path_tile.get_tile_key(), path_tile);
+    pub fn new(tileset: &TileGrid) -> PathMap {
+        let mut path_tiles: Vec<PathTile> = Vec::with_capacity(tileset.len());
+        for (x, y, _) in tileset.iter() {
+            path_tiles.push(PathTile::new(x, y, tileset));
         }
         PathMap {path_tiles: path_tiles}
     }
 
-    // Temporary function for pathfinding through usable tiles (might need to path through walls for map-gen)
-    // Might need to be an enum or more customizable (build bridges over water, tunnels through mountains, etc)
-    pub fn is_walkable(tile_type: char) -> bool {
-        let walkable = match tile_type {
-            '.' => true,
-            ',' => true,
-            '#' => true,
-            't' => true,
-            '~' => true,
-            _   => true
-        };
-        walkable
-    }
-
-    // is_walkable could be an enum/struct/something provided to this (control which tiles are walkable on a per path basis)
-    // This is a little hard to read, maybe calculating costs can be shrunk down (separate method for costs)
-    // A* pathfinding -> returns the shortest_path between two tiles using A* (slow) (goes through walls)
-    pub fn find_path(start_node: String, end_node: String, mut path_tiles: HashMap<String, PathTile>, tileset: &HashMap<String, Tile>) -> Vec<String> {
-        let mut open_list: Vec<String> = Vec::new();
-        let mut closed_list: Vec<String> = Vec::new();
-        let mut shortest_path: Vec<String> = Vec::new();
-        let mut exit: bool = false;
-        let mut lowest_f_cost_node: String = String::from("");
-        let mut current_tile;
-        open_list.push(start_node.clone());
-        while ! exit {
-            // Find lowest f cost in open list
-            for current in 0..open_list.len() {
-                if lowest_f_cost_node == "" {
-                    // Calculate costs for starting node, update tile in path_tiles
-                    lowest_f_cost_node = path_tiles[&open_list[current]].get_tile_key();
-                    let parent = start_node.to_string();
-                    let g = Tile::distance(&tileset[&start_node], &tileset[&end_node]);
-                    let h = Tile::heuristic_distance(&tileset[&start_node], &tileset[&end_node]);
-                    let f = g + h;
-                    path_tiles.insert(start_node.clone(), path_tiles[&start_node].tile_update(g, h, f, parent));
-                } else if path_tiles[&open_list[current]].f < path_tiles[&lowest_f_cost_node].f {
-                    lowest_f_cost_node = path_tiles[&open_list[current]].get_tile_key();
+    // Cost of stepping from one tile onto a neighbor tile, None if the profile can't enter it or movement doesn't allow the step
+    // Only the tile being entered matters, so the start tile never has to be walkable
+    pub fn move_cost(tileset: &TileGrid, profile: &CostProfile, movement: Movement, from_node: usize, to_node: usize) -> Option<i32> {
+        let tile_cost = profile.cost(tileset.tiles()[to_node])?;
+        let (from_x, from_y) = tileset.position(from_node);
+        let (to_x, to_y) = tileset.position(to_node);
+        if from_x == to_x || from_y == to_y {
+            return Some(tile_cost * cost::SIDE_STEP);
+        }
+        match movement {
+            Movement::Sides => None,
+            Movement::Diagonal => Some(tile_cost * cost::DIAGONAL_STEP),
+            Movement::NoCornerCutting => {
+                // Both tiles the diagonal passes between have to be passable
+                let side_x = tileset.at(to_x, from_y);
+                let side_y = tileset.at(from_x, to_y);
+                if profile.is_passable(side_x) && profile.is_passable(side_y) {
+                    Some(tile_cost * cost::DIAGONAL_STEP)
+                } else {
+                    None
                 }
+            },
+        }
+    }
+
+    // A* pathfinding -> returns the cheapest path between two tiles (start to end) and its total cost
+    // Open list is a binary heap of (f, h, tile), closed list is one bool per grid tile, stale heap entries are skipped
+    // g is the accumulated cost from start_node, heuristic is never more than the real cost so the path is optimal
+    pub fn find_path(&mut self, start_node: usize, end_node: usize, tileset: &TileGrid, profile: &CostProfile, movement: Movement) -> Option<PathResult> {
+        let number_of_tiles = self.path_tiles.len();
+        if start_node >= number_of_tiles || end_node >= number_of_tiles {
+            return None;
+        }
+        let mut open_list: BinaryHeap<Reverse<(i32, i32, usize)>> = BinaryHeap::new();
+        let mut closed_list: Vec<bool> = vec![false; number_of_tiles];
+        // Costs left from an older search are forgotten
+        for path_tile in self.path_tiles.iter_mut() {
+            path_tile.g = i32::max_value();
+        }
+        // Every step costs at least min_cost, so scaling the heuristic keeps it from overestimating
+        let min_cost = profile.min_cost();
+        let h = self.path_tiles[start_node].heuristic_distance(&self.path_tiles[end_node], movement) * min_cost;
+        self.path_tiles[start_node].tile_update(0, h, h, start_node);
+        open_list.push(Reverse((h, h, start_node)));
+        while let Some(Reverse((_, _, current_tile))) = open_list.pop() {
+            if closed_list[current_tile] {
+                continue;
             }
-            // Set the lowest_f_cost_node to the current_tile, remove from open_list, add to closed_list
-            open_list.retain(|tile_key| tile_key.to_string() != lowest_f_cost_node);
-            closed_list.push(path_tiles[&lowest_f_cost_node].get_tile_key());
-            current_tile = lowest_f_cost_node.to_string();
-            // Search all neighbors to current_tile for destination, calculate new costs
-            for neighbor_key in path_tiles[&current_tile].neighbors.clone() {
-                // If tile is NOT walkable (skip)
-                if ! PathMap::is_walkable(tileset[&neighbor_key].c) {
-                    continue;
-                }
-                // If tile is in the closed list (skip)
-                if closed_list.iter().any(|x| x == &neighbor_key) {
+            closed_list[current_tile] = true;
+            // Path success between start and end, return path of grid indexes
+            if current_tile == end_node {
+                return Some(PathResult {
+                    tiles: PathMap::trace_path(start_node, end_node, &self.path_tiles),
+                    cost: self.path_tiles[end_node].g
+                });
+            }
+            // Search all neighbors to current_tile, keep the cheaper g cost
+            for n in 0..self.path_tiles[current_tile].neighbors.len() {
+                let neighbor = self.path_tiles[current_tile].neighbors[n];
+                if closed_list[neighbor] {
                     continue;
                 }
-                // If tile is NOT on the open list, add it to the open list
-                if ! open_list.iter().any(|x| x == &neighbor_key) {
-                    // Calculate costs for starting node, update tile in path_tiles
-                    open_list.push(neighbor_key.to_string());
-                    let parent = current_tile.to_string();
-                    let g = Tile::distance(&tileset[&current_tile], &tileset[&neighbor_key]);
-                    let h = Tile::heuristic_distance(&tileset[&neighbor_key], &tileset[&end_node]);
-                    let f = g + h;
-                    path_tiles.insert(neighbor_key.clone(), path_tiles[&neighbor_key].tile_update(g, h, f, parent));
-                } else { // Tile IS on the open list, check if this path's g-cost is lower than the previous cost
-                    let new_g_cost = Tile::distance(&tileset[&current_tile], &tileset[&neighbor_key]);
-                    // if this new path's g-cost is lower, calculate new costs and update path_tiles
-                    if new_g_cost < path_tiles[&neighbor_key].g {
-                        let parent = current_tile.to_string();
-                        let g = Tile::distance(&tileset[&current_tile], &tileset[&neighbor_key]);
-                        let h = Tile::heuristic_distance(&tileset[&neighbor_key], &tileset[&end_node]);
-                        let f = g + h;
-                        path_tiles.insert(neighbor_key.clone(), path_tiles[&neighbor_key].tile_update(g, h, f, parent));
-                    }
+                let step_cost = match PathMap::move_cost(tileset, profile, movement, current_tile, neighbor) {
+                    Some(cost) => cost,
+                    None => continue,
+                };
+                let g = self.path_tiles[current_tile].g.saturating_add(step_cost);
+                if g < self.path_tiles[neighbor].g {
+                    let h = self.path_tiles[neighbor].heuristic_distance(&self.path_tiles[end_node], movement) * min_cost;
+                    self.path_tiles[neighbor].tile_update(g, h, g.saturating_add(h), current_tile);
+                    open_list.push(Reverse((g.saturating_add(h), h, neighbor)));
                 }
-            } // end of current_tile.neighbors
-            // Path success between start and end, exit main loop, return path Vec<string> of tile keys
-            if closed_list.iter().any(|x| x == &end_node) {
-                shortest_path = PathMap::trace_path(start_node.clone(), end_node.clone(), &path_tiles);
-                exit = true;
-                break;
-            // Path failed to connect start and end nodes (possible if no walkable route)
-            } else if open_list.len() == 0 && ! closed_list.iter().any(|x| x == &end_node) {
-                shortest_path = Vec::new();
-                exit = true;
-                break;
             }
-        } // end of while exit
-        shortest_path
+        }
+        // Path failed to connect start and end nodes (no walkable route)
+        None
     }
 
-    // Used once find_path gets the end_node in closed_list, traces parents back to start_node
-    pub fn trace_path (start_node: String, end_node: String, path_tiles: &HashMap<String, PathTile>) -> Vec<String> {
-        let mut current_node = end_node.to_string();
-        let mut shortest_path: Vec<String> = Vec::new();
+    // Used once find_path reaches end_node, traces parents back to start_node, returned in start to end order
+    pub fn trace_path (start_node: usize, end_node: usize, path_tiles: &[PathTile]) -> Vec<usize> {
+        let mut current_node = end_node;
+        let mut shortest_path: Vec<usize> = Vec::new();
         while current_node != start_node {
-            shortest_path.push(current_node.clone());
-            current_node = path_tiles[&current_node].parent.clone();
+            shortest_path.push(current_node);
+            current_node = path_tiles[current_node].parent;
         }
-        shortest_path.push(start_node.clone());
+        shortest_path.push(start_node);
+        shortest_path.reverse();
         shortest_path
     }
 
@@ -975,81 +2033,76 @@ impl PathMap {
 
 // Individual tiles for pathfinding
 impl PathTile {
-    pub fn new(x: i32, y: i32, map_size_x: i32, map_size_y: i32) -> PathTile {
-        let mut path_tile = PathTile {
+    pub fn new(x: i32, y: i32, tileset: &TileGrid) -> PathTile {
+        let path_tile = PathTile {
             x: x,
             y: y,
             g: 0,
             h: 0,
             f: 0,
-            parent: String::from(""),
-            neighbors: PathTile::get_neighbors(x, y, map_size_x, map_size_y)
+            parent: tileset.index(x, y),
+            neighbors: PathTile::get_neighbors(x, y, tileset)
         };
         path_tile
     }
 
-    // Generate neighbor keys
-    pub fn get_neighbors(x: i32, y: i32, map_size_x: i32, map_size_y: i32) -> Vec<String> {
-        let mut neighbors: Vec<String> = Vec::new();
-        if x+1 < map_size_x {
-            let right_side = (x + 1).to_string() + "x" + &(y).to_string();
-            neighbors.push(right_side);
-        }
-        if x-1 > 0 {
-            let left_side = (x - 1).to_string() + "x" + &(y).to_string();
-            neighbors.push(left_side);
-        }
-        if y+1 < map_size_y {
-            let bottom_side = (x).to_string() + "x" + &(y + 1).to_string();
-            neighbors.push(bottom_side);
-        }
-        if y-1 > 0 {
-            let top_side = (x).to_string() + "x" + &(y - 1).to_string();
-            neighbors.push(top_side);
+    // Generate neighbor grid indexes, sides first then corners (find_path skips corners for Movement::Sides)
+    // Tiles on the map edges get every neighbor that is inside the map
+    pub fn get_neighbors(x: i32, y: i32, tileset: &TileGrid) -> Vec<usize> {
+        let mut neighbors: Vec<usize> = Vec::new();
+        for (nx, ny, _) in tileset.neighbors(x, y) {
+            neighbors.push(tileset.index(nx, ny));
         }
         neighbors
     }
-    // Maybe calculate costs here?
-    pub fn tile_update(&self, g: i32, h: i32, f: i32, parent: String) -> PathTile {
-        let new_path_tile = PathTile {
-            x: self.x.clone(),
-            y: self.y.clone(),
-            g: g,
-            h: h,
-            f: f,
-            parent: parent,
-            neighbors: self.neighbors.clone()
-        };
-        new_path_tile
-    }
-    // Do we need two different update functions?
-    pub fn change_parent(&self, parent: String) -> PathTile {
-        let new_path_tile = PathTile {
-            x: self.x.clone(),
-            y: self.y.clone(),
-            g: self.g.clone(),
-            h: self.h.clone(),
-            f: self.f.clone(),
-            parent: parent,
-            neighbors: self.neighbors.clone()
-        };
-        new_path_tile
+    // Store new costs and parent in place
+    pub fn tile_update(&mut self, g: i32, h: i32, f: i32, parent: usize) {
+        self.g = g;
+        self.h = h;
+        self.f = f;
+        self.parent = parent;
+    }
+    // Estimate cost units between path tiles for A* (manhattan or octile), never more than the real cost at tile cost 1
+    pub fn heuristic_distance(&self, other: &PathTile, movement: Movement) -> i32 {
+        movement.distance(self.x - other.x, self.y - other.y)
     }
     // Create new tile key string, xy coordinate with separator
     pub fn get_tile_key(&self) -> String {
-        let tx = &self.x.to_string();
-        let ty = &self.y.to_string();
-        let sep = String::from("x");
-        let mut s = String::new();
-        s.push_str(&tx);
-        s.push_str(&sep);
-        s.push_str(&ty);
-        s
+        Tile::key(self.x, self.y)
+    }
+}
+
+// Convert json values (serialized rust structs) into Godot variants, objects become Dictionary and arrays Array
+fn json_to_variant(value: &serde_json::Value) -> Variant {
+    match value {
+        serde_json::Value::Null => Variant::new(),
+        serde_json::Value::Bool(b) => Variant::from_bool(*b),
+        // u64 above i64::MAX (seeds) go back as the same bits, Godot passed them in as a negative int
+        serde_json::Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+            (Some(i), _) => Variant::from_i64(i),
+            (None, Some(u)) => Variant::from_i64(u as i64),
+            (None, None) => Variant::from_f64(n.as_f64().unwrap_or(0.0)),
+        },
+        serde_json::Value::String(s) => Variant::from_str(s),
+        serde_json::Value::Array(values) => {
+            let mut array = VariantArray::new();
+            for v in values.iter() {
+                array.push(&json_to_variant(v));
+            }
+            Variant::from_array(&array)
+        },
+        serde_json::Value::Object(fields) => {
+            let mut dictionary = Dictionary::new();
+            for (k, v) in fields.iter() {
+                dictionary.set(&Variant::from_str(k), &json_to_variant(v));
+            }
+            Variant::from_dictionary(&dictionary)
+        },
     }
 }
 
 fn init(handle: gdnative::init::InitHandle) {
-    handle.add_class::<Map>();
+    handle.add_class::<GodotMap>();
 }
 
 godot_gdnative_init!();
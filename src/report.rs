@@ -0,0 +1,132 @@
+// What a generated map actually contains, used to reject bad maps in a pipeline
+// Compares tile counts against the biome TileChance and checks spawn/border problems
+
+use serde::Serialize;
+
+use crate::connectivity;
+use crate::{Biome, Map, TILE_TYPE};
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TileStats {
+    pub tile_type: char,
+    pub count: usize,
+    pub percent: f32, // Percent of every tile on the map
+    pub requested_percent: f32, // From the biome TileChance, 0.0 for tile types it doesn't control
+    pub components: usize // Number of side-connected groups of this tile type
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct MapReport {
+    pub biome: String,
+    pub size_x: i32,
+    pub size_y: i32,
+    pub tiles: Vec<TileStats>, // One entry per tile type found on the map or requested by the biome, in TILE_TYPE order
+    pub walkable_tiles: usize,
+    pub walkable_components: usize,
+    pub largest_walkable_area: usize, // Tiles in the biggest side-connected walkable group
+    pub spawn_walkable: bool,
+    pub spawn_reachable_tiles: usize, // Walkable tiles the spawn can walk to (including the spawn)
+    pub reachable_area: f32, // Percent of walkable tiles the spawn can walk to
+    pub border_checked: bool, // Border is only checked when outer_wall is on
+    pub border_breaks: Vec<(i32, i32)> // Border tiles that aren't the default wall or an exit
+}
+
+impl MapReport {
+    pub fn new(map: &Map, biome: &Biome) -> MapReport {
+        let tileset = &map.tileset;
+        let total = tileset.len().max(1) as f32;
+        let chance = &biome.tile_chance;
+        let requested = [
+            (TILE_TYPE.floor, chance.floor), (TILE_TYPE.wall, chance.wall), (TILE_TYPE.water, chance.water),
+            (TILE_TYPE.sand, chance.sand), (TILE_TYPE.tree, chance.tree)
+        ];
+        let tile_types = [
+            TILE_TYPE.floor, TILE_TYPE.wall, TILE_TYPE.water, TILE_TYPE.sand, TILE_TYPE.tree, TILE_TYPE.road, TILE_TYPE.exit, TILE_TYPE.bridge
+        ];
+        let mut listed: Vec<char> = tile_types.to_vec();
+        for c in tileset.tiles() {
+            if !listed.contains(c) {
+                listed.push(*c);
+            }
+        }
+        let mut tiles = Vec::new();
+        for tile_type in listed {
+            let count = tileset.tiles().iter().filter(|c| **c == tile_type).count();
+            let requested_percent = requested.iter().find(|(c, _)| *c == tile_type).map(|(_, p)| *p * 100.0).unwrap_or(0.0);
+            if count == 0 && requested_percent == 0.0 {
+                continue;
+            }
+            let (_, sizes) = connectivity::label_components(tileset, |c| c == tile_type);
+            tiles.push(TileStats {
+                tile_type: tile_type,
+                count: count,
+                percent: count as f32 * 100.0 / total,
+                requested_percent: requested_percent,
+                components: sizes.len() - 1
+            });
+        }
+
+        let is_walkable = |c| TILE_TYPE.is_walkable(c);
+        let (_, walkable_sizes) = connectivity::label_components(tileset, is_walkable);
+        let walkable_tiles = walkable_sizes.iter().sum();
+        let largest_walkable_area = walkable_sizes.iter().cloned().max().unwrap_or(0);
+        let spawn = (map.header.spawn_x, map.header.spawn_y);
+        let spawn_walkable = tileset.get(spawn.0, spawn.1).map(is_walkable).unwrap_or(false);
+        let mut spawn_reachable_tiles = 0;
+        if spawn_walkable {
+            let reached = connectivity::reachable_from(tileset, tileset.index(spawn.0, spawn.1), is_walkable);
+            spawn_reachable_tiles = reached.iter().filter(|r| **r).count();
+        }
+        let reachable_area = if walkable_tiles == 0 {
+            100.0
+        } else {
+            spawn_reachable_tiles as f32 * 100.0 / walkable_tiles as f32
+        };
+
+        let border_checked = biome.biome_control.outer_wall;
+        let mut border_breaks = Vec::new();
+        if border_checked {
+            for (x, y, c) in tileset.iter() {
+                if tileset.is_border(x, y) && c != map.header.default_wall && c != TILE_TYPE.exit {
+                    border_breaks.push((x, y));
+                }
+            }
+        }
+
+        MapReport {
+            biome: map.header.biome.clone(),
+            size_x: tileset.size_x(),
+            size_y: tileset.size_y(),
+            tiles: tiles,
+            walkable_tiles: walkable_tiles,
+            walkable_components: walkable_sizes.len() - 1,
+            largest_walkable_area: largest_walkable_area,
+            spawn_walkable: spawn_walkable,
+            spawn_reachable_tiles: spawn_reachable_tiles,
+            reachable_area: reachable_area,
+            border_checked: border_checked,
+            border_breaks: border_breaks
+        }
+    }
+
+    // Reasons to throw the map away, empty if it looks fine
+    // max_chance_error is how far (in percent) each tile type can be from its requested percent
+    pub fn problems(&self, min_reachable_area: f32, max_chance_error: f32) -> Vec<String> {
+        let mut problems = Vec::new();
+        if !self.spawn_walkable {
+            problems.push(String::from("spawn is not walkable"));
+        }
+        if self.reachable_area < min_reachable_area {
+            problems.push(format!("only {:.1}% of walkable tiles are reachable from spawn", self.reachable_area));
+        }
+        if !self.border_breaks.is_empty() {
+            problems.push(format!("outer wall has {} gaps", self.border_breaks.len()));
+        }
+        for tile in self.tiles.iter() {
+            if (tile.percent - tile.requested_percent).abs() > max_chance_error {
+                problems.push(format!("'{}' covers {:.1}% of the map, requested {:.1}%", tile.tile_type, tile.percent, tile.requested_percent));
+            }
+        }
+        problems
+    }
+}
@@ -0,0 +1,219 @@
+// Pathfinding across the maps of a World, map exits join a tile of one map to the arrival tile of a neighbor (including above/below)
+// The search runs over exits, costs inside each map come from its cached HPA* graph
+// Maps are only loaded once the search reaches them, so a route only touches the maps along (or close to) it
+
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::cost::SIDE_STEP;
+use crate::hpa::{ClusterGraph, DEFAULT_CLUSTER_SIZE};
+use crate::{CostProfile, Map, MapError, Movement, World};
+
+// One tile of a world route
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct WorldStep {
+    pub map: String, // World map name ("x0y0z0")
+    pub x: i32,
+    pub y: i32
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorldPath {
+    pub steps: Vec<WorldStep>, // every tile from start to end, taking an exit moves straight to the arrival tile of the next map
+    pub cost: i32, // same units as PathResult::cost, arriving on a map costs one side step onto the arrival tile
+    pub maps_loaded: Vec<String> // maps the search had to load, in load order
+}
+
+struct LoadedMap {
+    map: Map,
+    graph: ClusterGraph
+}
+
+// Where a route node is, entries are the start and arrival tiles (walk to exits from there), exits lead to another map
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct RouteNode {
+    map: String,
+    tile: usize,
+    is_exit: bool
+}
+
+struct RouteSearch<'a> {
+    world: &'a World,
+    profile: &'a CostProfile,
+    movement: Movement,
+    maps: HashMap<String, LoadedMap>,
+    maps_loaded: Vec<String>
+}
+
+impl<'a> RouteSearch<'a> {
+    // Load a map and its HPA* graph the first time the search needs it
+    fn load(&mut self, map_name: &str) -> Result<&LoadedMap, MapError> {
+        if !self.maps.contains_key(map_name) {
+            let map_path = self.world.map_file(map_name);
+            let map = Map::load_map(&map_path, self.world.compress_maps)?;
+            let graph = ClusterGraph::load_or_build(&map_path, &map.tileset, self.profile, self.movement, DEFAULT_CLUSTER_SIZE)?;
+            self.maps.insert(map_name.to_string(), LoadedMap {map: map, graph: graph});
+            self.maps_loaded.push(map_name.to_string());
+        }
+        Ok(&self.maps[map_name])
+    }
+    // Cheapest cost between two tiles of a loaded map
+    fn cost(&self, map_name: &str, from: usize, to: usize) -> Option<i32> {
+        let loaded = &self.maps[map_name];
+        loaded.graph.abstract_path(&loaded.map.tileset, self.profile, self.movement, from, to).map(|path| path.cost)
+    }
+    // Every tile between two tiles of a loaded map (both included)
+    fn tiles(&self, map_name: &str, from: usize, to: usize) -> Vec<usize> {
+        let loaded = &self.maps[map_name];
+        loaded.graph.find_path(&loaded.map.tileset, self.profile, self.movement, from, to).map(|path| path.tiles).unwrap_or_default()
+    }
+    // Tile position in the whole world, maps sit side by side (north is world_y + 1 and row 0 is the north edge of a map)
+    // In i64, world positions times the map size don't fit in i32 far from the middle of an unbounded world
+    fn world_position(&self, map_name: &str, tile: usize) -> (i64, i64, i64) {
+        let tileset = &self.maps[map_name].map.tileset;
+        let (x, y) = tileset.position(tile);
+        let (x, y, size_x, size_y) = (i64::from(x), i64::from(y), i64::from(tileset.size_x()), i64::from(tileset.size_y()));
+        match World::parse_map_name(map_name) {
+            Some((world_x, world_y, world_z)) => (i64::from(world_x) * size_x + x, y - i64::from(world_y) * size_y, i64::from(world_z)),
+            None => (x, y, 0),
+        }
+    }
+    // Tile distance through the world plus a step per level up or down, never more than the real cost as long as
+    // exits arrive next to where they leave (what World exits do), far away maps are never loaded
+    // Distances are capped so they can't overflow, a smaller heuristic still never overestimates
+    fn heuristic(&self, node: &RouteNode, end_map: &str, end_tile: usize) -> i32 {
+        let (x, y, z) = self.world_position(&node.map, node.tile);
+        let (end_x, end_y, end_z) = self.world_position(end_map, end_tile);
+        let steps = |d: i64| d.abs().min(i64::from(i32::max_value() / (2 * SIDE_STEP))) as i32;
+        self.movement.distance(steps(end_x - x), steps(end_y - y))
+            .saturating_add(steps(end_z - z) * SIDE_STEP)
+            .saturating_mul(self.profile.min_cost())
+    }
+}
+
+// Route from a tile ("XxY") of one world map to a tile of another (or the same) map, Ok(None) if there isn't one
+// Costs inside each map come from HPA* (like path_find_hierarchical), so routes are near-optimal rather than the cheapest
+pub fn find_world_path(world: &World, start_map: &str, start_tile: &str, end_map: &str, end_tile: &str,
+                       profile: &CostProfile, movement: Movement) -> Result<Option<WorldPath>, MapError> {
+    let mut search = RouteSearch {world: world, profile: profile, movement: movement, maps: HashMap::new(), maps_loaded: Vec::new()};
+    let start_index = search.load(start_map)?.map.tileset.key_index(start_tile)?;
+    let end_index = search.load(end_map)?.map.tileset.key_index(end_tile)?;
+
+    let start = RouteNode {map: start_map.to_string(), tile: start_index, is_exit: false};
+    let end = RouteNode {map: end_map.to_string(), tile: end_index, is_exit: false};
+    if start == end {
+        let (x, y) = search.maps[start_map].map.tileset.position(start_index);
+        let steps = vec![WorldStep {map: start_map.to_string(), x: x, y: y}];
+        return Ok(Some(WorldPath {steps: steps, cost: 0, maps_loaded: search.maps_loaded}));
+    }
+
+    // Node ids index nodes/g/parent, 0 is the end tile (walked to from any entry on the end map, or arrived on) and 1 is start
+    let end_id = 0;
+    let mut node_ids: HashMap<RouteNode, usize> = HashMap::new();
+    node_ids.insert(end.clone(), end_id);
+    let mut nodes: Vec<RouteNode> = vec![end];
+    let mut g: Vec<i32> = vec![i32::max_value()];
+    let mut parent: Vec<usize> = vec![usize::max_value()];
+    let start_h = search.heuristic(&start, end_map, end_index);
+    node_ids.insert(start.clone(), 1);
+    nodes.push(start);
+    g.push(0);
+    parent.push(usize::max_value());
+    // Heuristic isn't consistent across map edges, so nodes can be opened again when a cheaper g turns up
+    let mut open_list: BinaryHeap<Reverse<(i32, i32, usize)>> = BinaryHeap::new();
+    open_list.push(Reverse((start_h, 0, 1)));
+    while let Some(Reverse((_, current_g, current))) = open_list.pop() {
+        if current_g != g[current] {
+            continue;
+        }
+        if current == end_id {
+            break;
+        }
+        let node = nodes[current].clone();
+        let mut steps: Vec<(RouteNode, i32)> = Vec::new();
+        if node.is_exit {
+            // Take the exit, costs a step onto the arrival tile
+            let exits: Vec<_> = {
+                let tileset = &search.maps[&node.map].map.tileset;
+                search.maps[&node.map].map.header.exits.iter()
+                    .filter(|exit| !exit.target_map.is_empty() && tileset.in_bounds(exit.x, exit.y) && tileset.index(exit.x, exit.y) == node.tile)
+                    .cloned().collect()
+            };
+            for exit in exits {
+                let target = search.load(&exit.target_map)?;
+                let tileset = &target.map.tileset;
+                if !tileset.in_bounds(exit.arrival_x, exit.arrival_y) {
+                    continue;
+                }
+                if let Some(tile_cost) = profile.cost(tileset.at(exit.arrival_x, exit.arrival_y)) {
+                    let arrival = RouteNode {map: exit.target_map.clone(), tile: tileset.index(exit.arrival_x, exit.arrival_y), is_exit: false};
+                    steps.push((arrival, tile_cost * SIDE_STEP));
+                }
+            }
+        } else {
+            // Walk to every exit of this map, and to the end tile on the end map
+            let exit_tiles: Vec<usize> = {
+                let tileset = &search.maps[&node.map].map.tileset;
+                search.maps[&node.map].map.header.exits.iter()
+                    .filter(|exit| !exit.target_map.is_empty() && tileset.in_bounds(exit.x, exit.y))
+                    .map(|exit| tileset.index(exit.x, exit.y)).collect()
+            };
+            for exit_tile in exit_tiles {
+                if let Some(cost) = search.cost(&node.map, node.tile, exit_tile) {
+                    steps.push((RouteNode {map: node.map.clone(), tile: exit_tile, is_exit: true}, cost));
+                }
+            }
+            if node.map == end_map {
+                if let Some(cost) = search.cost(&node.map, node.tile, end_index) {
+                    steps.push((nodes[end_id].clone(), cost));
+                }
+            }
+        }
+        for (next, cost) in steps {
+            let next_id = match node_ids.get(&next) {
+                Some(id) => *id,
+                None => {
+                    node_ids.insert(next.clone(), nodes.len());
+                    nodes.push(next);
+                    g.push(i32::max_value());
+                    parent.push(usize::max_value());
+                    nodes.len() - 1
+                }
+            };
+            if g[current].saturating_add(cost) < g[next_id] {
+                g[next_id] = g[current].saturating_add(cost);
+                parent[next_id] = current;
+                let h = if next_id == end_id { 0 } else { search.heuristic(&nodes[next_id], end_map, end_index) };
+                open_list.push(Reverse((g[next_id].saturating_add(h), g[next_id], next_id)));
+            }
+        }
+    }
+    if g[end_id] == i32::max_value() {
+        return Ok(None);
+    }
+
+    // Route nodes from start to end, then fill in the tiles walked on each map
+    let mut route = vec![end_id];
+    while *route.last().unwrap() != 1 {
+        route.push(parent[*route.last().unwrap()]);
+    }
+    route.reverse();
+    let mut steps: Vec<WorldStep> = Vec::new();
+    for pair in route.windows(2) {
+        let (from, to) = (&nodes[pair[0]], &nodes[pair[1]]);
+        let tiles = if from.map == to.map {
+            search.tiles(&from.map, from.tile, to.tile)
+        } else {
+            vec![from.tile, to.tile]
+        };
+        let skip = if steps.is_empty() { 0 } else { 1 };
+        for (i, tile) in tiles.into_iter().enumerate().skip(skip) {
+            // Crossing to another map, the second tile belongs to the target map
+            let map_name = if i == 0 { &from.map } else { &to.map };
+            let (x, y) = search.maps[map_name].map.tileset.position(tile);
+            steps.push(WorldStep {map: map_name.clone(), x: x, y: y});
+        }
+    }
+    Ok(Some(WorldPath {steps: steps, cost: g[end_id], maps_loaded: search.maps_loaded}))
+}
@@ -0,0 +1,31 @@
+// Cells for Godot TileMap nodes, so drawing a map is one call instead of a Sprite instance per tile
+// Trees go on the overlay layer with the map's default floor under them on the ground layer (what draw_map_tiles did)
+
+use std::collections::HashMap;
+
+use crate::{Map, TILE_TYPE};
+
+// Row-major TileMap tile indexes for each layer, -1 leaves the cell empty
+#[derive(Clone, Debug, PartialEq)]
+pub struct TileMapLayers {
+    pub ground: Vec<i64>,
+    pub overlay: Vec<i64>
+}
+
+impl TileMapLayers {
+    // tile_indexes maps a tile char to the TileSet tile index, chars without an index are left empty
+    pub fn new(map: &Map, tile_indexes: &HashMap<char, i64>) -> TileMapLayers {
+        let index_of = |c: char| tile_indexes.get(&c).cloned().unwrap_or(-1);
+        let mut layers = TileMapLayers {ground: Vec::with_capacity(map.tileset.len()), overlay: Vec::with_capacity(map.tileset.len())};
+        for c in map.tileset.tiles() {
+            if *c == TILE_TYPE.tree {
+                layers.ground.push(index_of(map.header.default_floor));
+                layers.overlay.push(index_of(*c));
+            } else {
+                layers.ground.push(index_of(*c));
+                layers.overlay.push(-1);
+            }
+        }
+        layers
+    }
+}
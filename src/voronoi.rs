@@ -0,0 +1,189 @@
+// Nearest voronoi region lookup for tiles_to_voronoi
+// Regions are dropped into square buckets, searching rings of buckets outward from the tile means only
+// nearby regions are measured instead of every region for every tile
+
+use serde::Deserialize;
+
+use crate::Tile;
+
+// How the distance between a tile and a voronoi region is measured, picked by the biome definition
+// Manhattan gives diamond regions, Euclidean round regions, Chebyshev square regions
+// Weighted and Power give each region its own weight (BiomeControl region_weights), bigger weight = bigger region
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DistanceMetric {
+    Manhattan,
+    Euclidean,
+    Chebyshev,
+    Weighted, // Euclidean distance divided by the region weight
+    Power // Squared Euclidean distance minus the squared region weight (power diagram)
+}
+
+impl Default for DistanceMetric {
+    fn default() -> DistanceMetric {
+        DistanceMetric::Manhattan
+    }
+}
+
+impl DistanceMetric {
+    // Value used to compare regions, smaller is closer (Euclidean based metrics skip the sqrt)
+    pub fn measure(&self, dx: i32, dy: i32, weight: f64) -> f64 {
+        let dx = dx.abs() as f64;
+        let dy = dy.abs() as f64;
+        match self {
+            DistanceMetric::Manhattan => dx + dy,
+            DistanceMetric::Euclidean => dx * dx + dy * dy,
+            DistanceMetric::Chebyshev => dx.max(dy),
+            DistanceMetric::Weighted => (dx * dx + dy * dy) / (weight * weight),
+            DistanceMetric::Power => dx * dx + dy * dy - weight * weight,
+        }
+    }
+    // Smallest measure possible for a region at least gap tiles away on x or y (weight no bigger than max_weight)
+    fn lower_bound(&self, gap: i32, max_weight: f64) -> f64 {
+        let gap = gap as f64;
+        match self {
+            DistanceMetric::Manhattan | DistanceMetric::Chebyshev => gap,
+            DistanceMetric::Euclidean => gap * gap,
+            DistanceMetric::Weighted => (gap * gap) / (max_weight * max_weight),
+            DistanceMetric::Power => gap * gap - max_weight * max_weight,
+        }
+    }
+    // Only Weighted and Power need random region weights (others skip the rng so seeds don't change)
+    pub fn uses_weights(&self) -> bool {
+        match self {
+            DistanceMetric::Weighted | DistanceMetric::Power => true,
+            _ => false,
+        }
+    }
+    // Weight for tile types missing from region_weights (same size regions)
+    pub fn default_weight(&self) -> f64 {
+        match self {
+            DistanceMetric::Power => 0.0,
+            _ => 1.0,
+        }
+    }
+}
+
+pub struct RegionBuckets<'a> {
+    regions: &'a [Tile],
+    weights: &'a [f64], // Weight of each region, same order as regions
+    max_weight: f64,
+    metric: DistanceMetric,
+    bucket_size: i32, // Width and height of each bucket in tiles
+    buckets_x: i32,
+    buckets_y: i32,
+    buckets: Vec<Vec<usize>> // Region indexes in each bucket (ascending), row-major
+}
+
+impl<'a> RegionBuckets<'a> {
+    // Bucket size is picked so each bucket holds about one region
+    pub fn new(size_x: i32, size_y: i32, regions: &'a [Tile], weights: &'a [f64], metric: DistanceMetric) -> RegionBuckets<'a> {
+        let area = (size_x.max(1) * size_y.max(1)) as f64;
+        let bucket_size = (area / regions.len().max(1) as f64).sqrt().ceil().max(1.0) as i32;
+        let buckets_x = (size_x.max(1) + bucket_size - 1) / bucket_size;
+        let buckets_y = (size_y.max(1) + bucket_size - 1) / bucket_size;
+        let mut buckets = vec![Vec::new(); (buckets_x * buckets_y) as usize];
+        for (index, region) in regions.iter().enumerate() {
+            // Regions are always placed inside the map, clamp just in case
+            let bx = (region.x / bucket_size).max(0).min(buckets_x - 1);
+            let by = (region.y / bucket_size).max(0).min(buckets_y - 1);
+            buckets[(by * buckets_x + bx) as usize].push(index);
+        }
+        // Metrics only use the squared weight, so the bound needs the biggest weight either side of 0.0
+        let max_weight = weights.iter().map(|weight| weight.abs()).fold(metric.default_weight(), f64::max);
+        RegionBuckets {
+            regions: regions,
+            weights: weights,
+            max_weight: max_weight,
+            metric: metric,
+            bucket_size: bucket_size,
+            buckets_x: buckets_x,
+            buckets_y: buckets_y,
+            buckets: buckets
+        }
+    }
+
+    // Index of the closest region to (x, y), ties go to the lowest index (same answer as nearest_region_exhaustive)
+    pub fn nearest(&self, x: i32, y: i32) -> usize {
+        let bx = (x / self.bucket_size).max(0).min(self.buckets_x - 1);
+        let by = (y / self.bucket_size).max(0).min(self.buckets_y - 1);
+        let mut closest: Option<(f64, usize)> = None;
+        let mut ring = 0;
+        loop {
+            for (cx, cy) in RegionBuckets::ring_buckets(bx, by, ring) {
+                if cx < 0 || cy < 0 || cx >= self.buckets_x || cy >= self.buckets_y {
+                    continue;
+                }
+                for &region in self.buckets[(cy * self.buckets_x + cx) as usize].iter() {
+                    let distance = region_distance(self.regions, self.weights, self.metric, region, x, y);
+                    let closer = match closest {
+                        Some((best_distance, best_region)) => distance < best_distance || (distance == best_distance && region < best_region),
+                        None => true,
+                    };
+                    if closer {
+                        closest = Some((distance, region));
+                    }
+                }
+            }
+            // Every bucket searched
+            if ring > self.buckets_x.max(self.buckets_y) {
+                break;
+            }
+            // Anything in the next ring is at least this far away on one axis, so it can't be closer (or tie)
+            if let Some((best_distance, _)) = closest {
+                let gap = self.next_ring_distance(x, y, bx, by, ring);
+                if best_distance < self.metric.lower_bound(gap, self.max_weight) {
+                    break;
+                }
+            }
+            ring += 1;
+        }
+        match closest {
+            Some((_, region)) => region,
+            None => 0,
+        }
+    }
+
+    // Smallest gap along x or y between (x, y) and any bucket outside ring
+    fn next_ring_distance(&self, x: i32, y: i32, bx: i32, by: i32, ring: i32) -> i32 {
+        let left = x - (bx - ring) * self.bucket_size + 1;
+        let right = (bx + ring + 1) * self.bucket_size - x;
+        let top = y - (by - ring) * self.bucket_size + 1;
+        let bottom = (by + ring + 1) * self.bucket_size - y;
+        left.min(right).min(top).min(bottom)
+    }
+
+    // Buckets exactly ring steps away from (bx, by) (the edge of a square)
+    fn ring_buckets(bx: i32, by: i32, ring: i32) -> Vec<(i32, i32)> {
+        if ring == 0 {
+            return vec![(bx, by)];
+        }
+        let mut ring_buckets = Vec::with_capacity((ring * 8) as usize);
+        for cx in bx-ring..bx+ring+1 {
+            ring_buckets.push((cx, by - ring));
+            ring_buckets.push((cx, by + ring));
+        }
+        for cy in by-ring+1..by+ring {
+            ring_buckets.push((bx - ring, cy));
+            ring_buckets.push((bx + ring, cy));
+        }
+        ring_buckets
+    }
+}
+
+// Measure from (x, y) to one region
+fn region_distance(regions: &[Tile], weights: &[f64], metric: DistanceMetric, region: usize, x: i32, y: i32) -> f64 {
+    let weight = weights.get(region).cloned().unwrap_or(metric.default_weight());
+    metric.measure(regions[region].x - x, regions[region].y - y, weight)
+}
+
+// Check every region (the original tiles_to_voronoi search), kept to compare against in benchmarks
+pub fn nearest_region_exhaustive(regions: &[Tile], weights: &[f64], metric: DistanceMetric, x: i32, y: i32) -> usize {
+    let mut closest_region: usize = 0;
+    for region in 0..regions.len() {
+        if region_distance(regions, weights, metric, region, x, y) < region_distance(regions, weights, metric, closest_region, x, y) {
+            closest_region = region;
+        }
+    }
+    closest_region
+}
@@ -0,0 +1,97 @@
+// Biome definitions, valid json/toml files load and every validation error is reported instead of loading a broken biome
+// region_weights can name a tile type or use its char, but only once
+
+use maps::{Biome, MapError};
+
+static VALID: &str = r#"{
+    "name": "Swamp",
+    "default_floor": "floor",
+    "default_wall": "tree",
+    "tile_chance": {"floor": 0.4, "water": 0.4, "tree": 0.2},
+    "biome_control": {"water_edges": true, "exits": true}
+}"#;
+
+// Message of the error a broken definition gives, panics if it loads
+fn biome_error(file_name: &str, text: &str) -> String {
+    match Biome::from_definition(file_name, text) {
+        Err(error @ MapError::Biome(_)) => error.to_string(),
+        Err(error) => panic!("{}: expected a biome error, got {}", file_name, error),
+        Ok(biome) => panic!("{}: {} should not load", file_name, biome.biome_name),
+    }
+}
+
+#[test]
+fn valid_definitions_load() {
+    let biome = Biome::from_definition("swamp.json", VALID).unwrap();
+    assert_eq!(biome.biome_name, "Swamp");
+    assert_eq!((biome.default_floor(), biome.default_wall()), ('.', 't'));
+    assert_eq!((biome.tile_chance.water, biome.tile_chance.sand), (0.4, 0.0));
+    assert!(biome.biome_control.exits && !biome.biome_control.roads);
+
+    // Default tiles can be chars too, toml works the same
+    let toml = Biome::from_definition("swamp.toml", r#"
+        name = "Swamp"
+        default_floor = "."
+        default_wall = "t"
+        [tile_chance]
+        floor = 0.4
+        water = 0.4
+        tree = 0.2
+        [biome_control]
+        water_edges = true
+        exits = true
+    "#).unwrap();
+    assert_eq!((toml.default_floor(), toml.default_wall()), ('.', 't'));
+    assert_eq!(toml.tile_chance.water, biome.tile_chance.water);
+}
+
+#[test]
+fn invalid_definitions_are_rejected() {
+    // tile_chance has to add up to 1.0 and can't be negative
+    let error = biome_error("sum.json", &VALID.replace(r#""tree": 0.2"#, r#""tree": 0.5"#));
+    assert!(error.contains("adds up to"), "{}", error);
+    let error = biome_error("negative.json", &VALID.replace(r#""floor": 0.4, "water": 0.4"#, r#""floor": 1.0, "water": -0.2"#));
+    assert!(error.contains("negative"), "{}", error);
+
+    // Tile names have to be tile types
+    let error = biome_error("floor.json", &VALID.replace(r#""default_floor": "floor""#, r#""default_floor": "lava""#));
+    assert!(error.contains("unknown default_floor tile type 'lava'"), "{}", error);
+    let error = biome_error("wall.json", &VALID.replace(r#""default_wall": "tree""#, r#""default_wall": "?""#));
+    assert!(error.contains("unknown default_wall tile type '?'"), "{}", error);
+
+    // Unknown fields are typos, not ignored
+    let error = biome_error("chance.json", &VALID.replace(r#""tree": 0.2"#, r#""tree": 0.2, "lava": 0.0"#));
+    assert!(error.contains("unknown field `lava`"), "{}", error);
+    let error = biome_error("control.json", &VALID.replace(r#""exits": true"#, r#""exits": true, "exit": true"#));
+    assert!(error.contains("unknown field `exit`"), "{}", error);
+    let error = biome_error("top.toml", "name = \"Swamp\"\ncolour = \"green\"\n");
+    assert!(error.starts_with("top.toml: "), "{}", error);
+
+    // Missing required fields and broken files
+    let error = biome_error("missing.json", r#"{"name": "Swamp"}"#);
+    assert!(error.contains("missing field"), "{}", error);
+    biome_error("broken.json", "{");
+}
+
+#[test]
+fn region_weights_are_keyed_by_tile() {
+    let with_weights = |metric: &str, weights: &str| VALID.replace(r#""exits": true"#,
+        &format!(r#""exits": true, "distance_metric": "{}", "region_weights": {}"#, metric, weights));
+    let biome = Biome::from_definition("weights.json", &with_weights("weighted", r#"{"water": [2.0, 3.0], "t": [0.5, 0.5]}"#)).unwrap();
+    assert_eq!(biome.region_weight_range('~'), (2.0, 3.0));
+    assert_eq!(biome.region_weight_range('t'), (0.5, 0.5));
+    assert_eq!(biome.region_weight_range('.'), (1.0, 1.0)); // Metric default
+
+    // The same tile type twice (by name and by char) would pick a weight at random
+    let error = biome_error("twice.json", &with_weights("weighted", r#"{"sand": [1.0, 2.0], ",": [3.0, 4.0]}"#));
+    assert!(error.contains("more than once"), "{}", error);
+    let error = biome_error("unknown.json", &with_weights("power", r#"{"lava": [1.0, 2.0]}"#));
+    assert!(error.contains("unknown region_weights tile type 'lava'"), "{}", error);
+    let error = biome_error("range.json", &with_weights("power", r#"{"sand": [2.0, 1.0]}"#));
+    assert!(error.contains("min bigger than max"), "{}", error);
+    let error = biome_error("weighted.json", &with_weights("weighted", r#"{"sand": [0.0, 1.0]}"#));
+    assert!(error.contains("above 0.0"), "{}", error);
+    let error = biome_error("power.json", &with_weights("power", r#"{"sand": [-1.0, 1.0]}"#));
+    assert!(error.contains("can't be negative"), "{}", error);
+    assert!(Biome::from_definition("power.json", &with_weights("power", r#"{"sand": [0.0, 1.0]}"#)).is_ok());
+}
@@ -0,0 +1,13 @@
+// Helpers shared by the integration tests
+
+use maps::TileGrid;
+
+pub fn rows(rows: &[&str]) -> Vec<String> {
+    rows.iter().map(|row| row.to_string()).collect()
+}
+
+// Grid sized to the rows, every row is as long as the first
+pub fn grid(rows: &[&str]) -> TileGrid {
+    let rows = self::rows(rows);
+    TileGrid::from_rows(rows[0].len() as i32, rows.len() as i32, &rows, '#')
+}
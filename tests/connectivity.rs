@@ -0,0 +1,84 @@
+// Connectivity pass, after carving every walkable tile is reachable from spawn, after pruning the unreachable pockets are gone
+// Both hold on fixed grids and on generated maps of every biome
+
+mod common;
+
+use common::grid;
+use maps::connectivity::{carve_tunnels, prune_pockets, reachable_from, Connectivity};
+use maps::{BiomeRegistry, Map, TileGrid};
+
+// Floor, sand, road, exit and bridge (same as TileType::is_walkable)
+fn is_walkable(c: char) -> bool {
+    ".,=/+".contains(c)
+}
+
+// Walkable tiles the tile at start can't reach
+fn unreachable(tileset: &TileGrid, start: usize) -> usize {
+    let reachable = reachable_from(tileset, start, is_walkable);
+    tileset.tiles().iter().enumerate().filter(|(index, c)| is_walkable(**c) && !reachable[*index]).count()
+}
+
+// Spawn in the left room, a pocket behind one wall, a sand pocket behind two more and a corner pocket inside the border
+static POCKETS: [&str; 7] = [
+    "##########",
+    "#...#.####",
+    "#...#.##,#",
+    "#...###,,#",
+    "#...######",
+    "##########",
+    "#########.",
+];
+
+#[test]
+fn carving_connects_every_pocket() {
+    let start = grid(&POCKETS);
+    let spawn = start.index(2, 2);
+    assert_eq!(unreachable(&start, spawn), 6);
+
+    let mut tileset = start.clone();
+    carve_tunnels(&mut tileset, spawn, '.', false, is_walkable);
+    // The corner pocket can't be reached without digging the outer wall
+    assert_eq!(unreachable(&tileset, spawn), 1);
+    assert!(tileset.to_rows()[0].chars().all(|c| c == '#'));
+    // Only the fewest walls needed are dug: one for the first pocket and two more for the sand pocket
+    let dug = |tileset: &TileGrid| start.tiles().iter().zip(tileset.tiles().iter()).filter(|(before, after)| before != after).count();
+    assert_eq!(dug(&tileset), 3);
+    // Tiles that were walkable keep their type
+    assert_eq!(tileset.at(8, 2), ',');
+
+    let mut tileset = start.clone();
+    carve_tunnels(&mut tileset, spawn, '.', true, is_walkable);
+    assert_eq!(unreachable(&tileset, spawn), 0);
+    assert!(dug(&tileset) > 3);
+}
+
+#[test]
+fn pruning_fills_every_pocket() {
+    let start = grid(&POCKETS);
+    let spawn = start.index(2, 2);
+    let mut tileset = start.clone();
+    prune_pockets(&mut tileset, spawn, '#', is_walkable);
+    assert_eq!(unreachable(&tileset, spawn), 0);
+    // The spawn room is untouched, everything else is wall
+    let expected: Vec<String> = POCKETS.iter()
+        .map(|row| row.char_indices().map(|(x, c)| if (1..4).contains(&x) { c } else { '#' }).collect()).collect();
+    assert_eq!(tileset.to_rows(), expected);
+}
+
+#[test]
+fn generated_maps_are_connected() {
+    let biomes = BiomeRegistry::builtin();
+    for name in biomes.names() {
+        for connectivity in [Connectivity::Carve, Connectivity::Prune].iter() {
+            let mut biome = biomes.get_or_fallback(&name);
+            biome.biome_control.connectivity = *connectivity;
+            for seed in 0..4 {
+                let map = Map::new_biome(48, 32, &biome, seed);
+                let spawn = map.tileset.index(map.header.spawn_x, map.header.spawn_y);
+                assert!(is_walkable(map.tileset.tiles()[spawn]), "{} {:?} {}", name, connectivity, seed);
+                assert_eq!(unreachable(&map.tileset, spawn), 0, "{} {:?} {}", name, connectivity, seed);
+                assert_eq!(map.header.reachable_area, 100.0, "{} {:?} {}", name, connectivity, seed);
+            }
+        }
+    }
+}
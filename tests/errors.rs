@@ -0,0 +1,58 @@
+// MapError variants and the error codes Godot gets back for a missing map file, an unknown tile type and tiles outside the map
+
+use maps::{BiomeRegistry, Map, MapError, TileGrid};
+
+fn small_map() -> Map {
+    Map::new_biome(8, 6, &BiomeRegistry::builtin().get_or_fallback("Cave"), 3)
+}
+
+#[test]
+fn missing_map_file_is_an_io_error() {
+    let file = std::env::temp_dir().join("maps_missing_file_test.map").to_string_lossy().to_string();
+    let _ = std::fs::remove_file(&file);
+    match Map::load_map(&file, false) {
+        Err(error @ MapError::Io {..}) => {
+            assert_eq!(error.code(), 1);
+            assert!(error.to_string().starts_with(&file));
+        },
+        other => panic!("expected an io error, got {:?}", other.map(|map| map.header)),
+    }
+}
+
+#[test]
+fn unknown_tile_type_is_rejected() {
+    let mut map = small_map();
+    let before = map.tileset.clone();
+    match map.set_tile(1, 1, "lava") {
+        Err(error @ MapError::InvalidTileType(_)) => {
+            assert_eq!(error.code(), 10);
+            assert_eq!(error.to_string(), "unknown tile type 'lava'");
+        },
+        other => panic!("expected an invalid tile type, got {:?}", other),
+    }
+    assert_eq!(map.tileset, before);
+    // Names and chars both work
+    map.set_tile(1, 1, "water").unwrap();
+    map.set_tile(2, 1, "~").unwrap();
+    assert_eq!((map.tile(1, 1).unwrap(), map.tile(2, 1).unwrap()), ('~', '~'));
+}
+
+#[test]
+fn tiles_outside_the_map_are_out_of_bounds() {
+    let mut map = small_map();
+    // Coordinates too big for i32 don't wrap around onto the map
+    for (x, y) in &[(8, 0), (0, 6), (-1, 0), (0, -1), (1 << 32, 0), (0, (1 << 32) + 1), (i64::min_value(), 0)] {
+        match map.tile(*x, *y) {
+            Err(error @ MapError::TileOutOfBounds {size_x: 8, size_y: 6, ..}) => assert_eq!(error.code(), 6),
+            other => panic!("{}x{} gave {:?}", x, y, other),
+        }
+        match map.set_tile(*x, *y, "floor") {
+            Err(error @ MapError::TileOutOfBounds {..}) => assert_eq!(error.code(), 6),
+            other => panic!("{}x{} gave {:?}", x, y, other),
+        }
+    }
+    match TileGrid::new(8, 6, '.').key_index("8x0") {
+        Err(error) => assert_eq!((error.code(), error.to_string().as_str()), (6, "tile 8x0 is outside the 8x6 map")),
+        Ok(index) => panic!("8x0 gave index {}", index),
+    }
+}
@@ -0,0 +1,139 @@
+// Map generation, the same seed, size and biome always have to give the same map file
+// The voronoi bucket search has to pick the same region as checking every region, for every distance metric
+// Cellular smoothing follows its birth/survival rules exactly
+// Sizes from Godot are checked before generating, anything outside 1 to MAX_MAP_SIZE is an error
+
+mod common;
+
+use rand::Rng;
+
+use common::grid;
+use maps::voronoi::{nearest_region_exhaustive, DistanceMetric, RegionBuckets};
+use maps::{Biome, BiomeRegistry, Map, SmoothingRules, Tile, MAX_MAP_SIZE};
+
+#[test]
+fn same_seed_gives_identical_map_files() {
+    let directory = std::env::temp_dir().join("maps_generation_seed_test");
+    std::fs::create_dir_all(&directory).unwrap();
+    let biomes = BiomeRegistry::builtin();
+    let map_file = |name: &str, size_x: i32, size_y: i32, seed: u64, compression: bool, run: u32| -> Vec<u8> {
+        let file = directory.join(format!("{}_{}_{}.map", name, seed, run));
+        let file = file.to_string_lossy().to_string();
+        Map::save_map(&file, &Map::new_biome(size_x, size_y, &biomes.get_or_fallback(name), seed), compression).unwrap();
+        std::fs::read(&file).unwrap()
+    };
+    for name in biomes.names() {
+        for &(size_x, size_y, seed) in [(50, 50, 0), (40, 25, 101), (64, 33, u64::max_value())].iter() {
+            for &compression in [false, true].iter() {
+                let files: Vec<Vec<u8>> = (0..2).map(|run| map_file(&name, size_x, size_y, seed, compression, run)).collect();
+                assert!(files[0] == files[1], "{} {}x{} seed {} compressed {}", name, size_x, size_y, seed, compression);
+            }
+        }
+        // A different seed gives a different map, even when only the high 32 bits differ
+        assert!(map_file(&name, 50, 50, 1, false, 0) != map_file(&name, 50, 50, 2, false, 0), "{}", name);
+        assert!(map_file(&name, 50, 50, 1, false, 0) != map_file(&name, 50, 50, (1 << 32) | 1, false, 0), "{}", name);
+    }
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn bucket_search_matches_exhaustive_search() {
+    let metrics = [DistanceMetric::Manhattan, DistanceMetric::Euclidean, DistanceMetric::Chebyshev, DistanceMetric::Weighted, DistanceMetric::Power];
+    for &(size_x, size_y) in [(1, 1), (7, 3), (50, 50), (120, 37)].iter() {
+        for seed in 0..4 {
+            let mut rng = Map::seeded_rng(seed);
+            // From a single region up to as many as new_biome picks, regions can share a tile
+            let number_of_regions = match seed {
+                0 => 1,
+                1 => 3,
+                _ => rng.gen_range(size_x + size_y, (size_x + size_y) * 2) as usize,
+            };
+            let regions: Vec<Tile> = (0..number_of_regions)
+                .map(|_| Tile::new(rng.gen_range(0, size_x), rng.gen_range(0, size_y), '.', Vec::new())).collect();
+            for metric in metrics.iter() {
+                // Weighted regions get very different sizes, the other metrics get no weights (their default)
+                let weights: Vec<f64> = match metric {
+                    DistanceMetric::Weighted => regions.iter().map(|_| rng.gen_range(0.2, 4.0)).collect(),
+                    // Power only squares the weight, RegionBuckets has to get all negative weights right too
+                    DistanceMetric::Power if seed % 2 == 1 => regions.iter().map(|_| -rng.gen_range(0.0, 8.0)).collect(),
+                    DistanceMetric::Power => regions.iter().map(|_| rng.gen_range(0.0, 8.0)).collect(),
+                    _ => Vec::new(),
+                };
+                let buckets = RegionBuckets::new(size_x, size_y, &regions, &weights, *metric);
+                for y in 0..size_y {
+                    for x in 0..size_x {
+                        assert_eq!(buckets.nearest(x, y), nearest_region_exhaustive(&regions, &weights, *metric, x, y),
+                                   "{:?} {}x{} seed {} tile {}x{}", metric, size_x, size_y, seed, x, y);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn smoothing_rules_on_a_fixed_grid() {
+    // Classic B5678/S45678 rules on floor and wall, outside the map counts as wall, water and sand are left alone
+    let mut biome = Biome::fallback(String::from("Smooth"));
+    biome.biome_control.smoothing = SmoothingRules {iterations: 1, ..SmoothingRules::default()};
+    let start = grid(&[
+        "#.##.#",
+        "..#...",
+        "~.....",
+        "#..,.#",
+        "##..##",
+    ]);
+    let mut tileset = start.clone();
+    Map::smooth_tiles(&biome, &mut tileset);
+    // Top row floors have 5 or more walls around them (3 of them off the map) and fill in, the wall at 2x1 has only 2 and opens up
+    assert_eq!(tileset.to_rows(), vec![
+        "######",
+        "......",
+        "~.....",
+        "#..,.#",
+        "##..##",
+    ]);
+
+    // Every iteration reads the last one, two iterations are one iteration twice
+    let mut twice = tileset.clone();
+    Map::smooth_tiles(&biome, &mut twice);
+    biome.biome_control.smoothing.iterations = 2;
+    let mut two_iterations = start.clone();
+    Map::smooth_tiles(&biome, &mut two_iterations);
+    assert_eq!(two_iterations, twice);
+
+    // Water counted as solid is never changed when it isn't in tiles, but grows walls around it
+    biome.biome_control.smoothing = SmoothingRules {iterations: 1, birth: vec![3], survival: vec![], solid: vec![String::from("water")],
+                                                    tiles: vec![String::from("floor")]};
+    let mut tileset = grid(&[
+        ".......",
+        "..~~...",
+        "..~....",
+        ".......",
+        ".......",
+    ]);
+    Map::smooth_tiles(&biome, &mut tileset);
+    // The biome wall isn't solid here, so born tiles are the first solid type
+    // Edge tiles (not corners) have exactly 3 solid neighbors off the map, so they all turn to water
+    assert_eq!(tileset.to_rows(), vec![
+        ".....~.",
+        "~.~~..~",
+        "~.~~..~",
+        "~.....~",
+        ".~~~~~.",
+    ]);
+}
+
+#[test]
+fn map_sizes_are_checked() {
+    let max = i64::from(MAX_MAP_SIZE);
+    assert_eq!(Map::check_size(1, 1).unwrap(), (1, 1));
+    assert_eq!(Map::check_size(max, 7).unwrap(), (MAX_MAP_SIZE, 7));
+    for &(size_x, size_y) in [(0, 10), (10, 0), (-5, 10), (10, -1), (max + 1, 10), (10, max + 1), (i64::max_value(), 1)].iter() {
+        let error = Map::check_size(size_x, size_y).unwrap_err();
+        assert_eq!(error.code(), 11, "{}x{}", size_x, size_y);
+    }
+    // The smallest accepted size still generates
+    let map = Map::new_biome(1, 1, &BiomeRegistry::builtin().get_or_fallback("Cave"), 2);
+    assert_eq!((map.tileset.size_x(), map.tileset.size_y()), (1, 1));
+}
@@ -0,0 +1,74 @@
+// TileGrid storage, "XxY" key lookups, conversion to and from the old tileset layout and padding of short map file rows
+// Tile ids sent to Godot are row-major, chars that aren't a tile type get UNKNOWN_TILE_ID
+
+mod common;
+
+use common::{grid, rows};
+use maps::{MapError, TileGrid, UNKNOWN_TILE_ID};
+
+#[test]
+fn key_index_is_row_major() {
+    let grid = TileGrid::new(4, 3, '.');
+    assert_eq!(grid.key_index("0x0").unwrap(), 0);
+    assert_eq!(grid.key_index("3x0").unwrap(), 3);
+    assert_eq!(grid.key_index("1x2").unwrap(), 9);
+    assert_eq!(grid.position(9), (1, 2));
+}
+
+#[test]
+fn key_index_rejects_malformed_keys() {
+    let grid = TileGrid::new(4, 3, '.');
+    for key in &["", "1", "1x", "x1", "1y2", "ax2", "1x2x3", "spawn_x"] {
+        match grid.key_index(key) {
+            Err(MapError::InvalidTileKey(bad)) => assert_eq!(bad, *key),
+            other => panic!("{:?} gave {:?}", key, other),
+        }
+    }
+}
+
+#[test]
+fn key_index_rejects_keys_outside_the_map() {
+    let grid = TileGrid::new(4, 3, '.');
+    for key in &["4x0", "0x3", "-1x0", "0x-1"] {
+        match grid.key_index(key) {
+            Err(MapError::TileOutOfBounds {size_x: 4, size_y: 3, ..}) => {}
+            other => panic!("{:?} gave {:?}", key, other),
+        }
+    }
+}
+
+#[test]
+fn tileset_round_trip_keeps_every_tile() {
+    let grid = grid(&["#####", "#.,~#", "#T=.#", "#####"]);
+    let tileset = grid.to_tileset();
+    assert_eq!(tileset.len(), grid.len());
+    assert_eq!(tileset["2x1"].c, ',');
+    assert_eq!(tileset["1x2"].c, 'T');
+    assert_eq!(TileGrid::from_tileset(5, 4, &tileset, '#'), grid);
+}
+
+#[test]
+fn from_tileset_skips_metadata_and_fills_missing_tiles() {
+    let mut tileset = TileGrid::new(3, 3, '.').to_tileset();
+    tileset.remove("1x1");
+    let mut metadata = tileset["0x0"].clone();
+    metadata.c = '~';
+    tileset.insert("spawn_x".to_string(), metadata);
+    let grid = TileGrid::from_tileset(3, 3, &tileset, '#');
+    assert_eq!(grid.at(1, 1), '#');
+    assert_eq!(grid.at(0, 0), '.');
+    assert_eq!(grid.tiles().iter().filter(|c| **c == '.').count(), 8);
+}
+
+#[test]
+fn from_rows_pads_short_and_missing_rows() {
+    let grid = TileGrid::from_rows(4, 3, &rows(&["..", "....."]), '#');
+    assert_eq!(grid.to_rows(), vec!["..##", "....", "####"]);
+}
+
+#[test]
+fn tile_ids_are_row_major() {
+    // Ids follow TileType::tile_types: floor, wall, water, sand, tree, road, exit, bridge
+    let grid = grid(&["#.~,", "t=/+", "#?.#"]);
+    assert_eq!(grid.to_ids(), vec![1, 0, 2, 3, 4, 5, 6, 7, 1, UNKNOWN_TILE_ID, 0, 1]);
+}
@@ -0,0 +1,115 @@
+// Background generation through JobPool, maps must match Map::new_biome for the same biome and seed
+// Cancelled jobs never give a map, and every job shows up once in finished()
+// The job table drains, cancelled and taken jobs leave it and done maps nobody takes are dropped oldest first
+// Bad sizes are rejected before anything is queued
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use maps::{BiomeRegistry, JobPool, JobStatus, Map, MAX_MAP_SIZE};
+
+// Poll finished() until every job in ids has shown up, panics after a while
+fn wait_for(pool: &JobPool, ids: &[u64]) -> Vec<(u64, JobStatus)> {
+    let started = Instant::now();
+    let mut finished = Vec::new();
+    while ids.iter().any(|id| !finished.iter().any(|(done, _)| done == id)) {
+        assert!(started.elapsed() < Duration::from_secs(60), "jobs never finished");
+        finished.extend(pool.finished());
+        thread::sleep(Duration::from_millis(5));
+    }
+    finished
+}
+
+#[test]
+fn job_maps_match_new_biome() {
+    let biomes = BiomeRegistry::builtin();
+    let mut pool = JobPool::new(2);
+    let jobs: Vec<(u64, String, u64)> = biomes.names().iter().enumerate().map(|(seed, name)| {
+        (pool.generate(40, 30, biomes.get_or_fallback(name), seed as u64).unwrap(), name.clone(), seed as u64)
+    }).collect();
+    let ids: Vec<u64> = jobs.iter().map(|(id, _, _)| *id).collect();
+    let finished = wait_for(&pool, &ids);
+    assert_eq!(finished.len(), ids.len());
+
+    for (id, name, seed) in jobs {
+        assert_eq!(pool.status(id), Some(JobStatus::Done));
+        assert_eq!(pool.progress(id), Some(1.0));
+        assert_eq!(pool.biome_name(id).as_ref(), Some(&name));
+        let map = pool.take_map(id).expect("finished job has a map");
+        let expected = Map::new_biome(40, 30, &biomes.get_or_fallback(&name), seed);
+        assert_eq!(map.header, expected.header);
+        assert_eq!(map.tileset.to_ids(), expected.tileset.to_ids());
+        // Taken maps are forgotten
+        assert_eq!(pool.status(id), None);
+        assert!(pool.take_map(id).is_none());
+    }
+    assert!(pool.is_empty());
+}
+
+#[test]
+fn cancelled_jobs_have_no_map() {
+    let biomes = BiomeRegistry::builtin();
+    let biome = biomes.get_or_fallback("Cave");
+    // One worker, so the later jobs are still queued when they're cancelled
+    let mut pool = JobPool::new(1);
+    let ids: Vec<u64> = (0..4).map(|seed| pool.generate(200, 200, biome.clone(), seed).unwrap()).collect();
+    for id in ids.iter().skip(1) {
+        assert!(pool.cancel(*id));
+    }
+    let finished = wait_for(&pool, &ids);
+    assert_eq!(finished.len(), ids.len());
+    for (id, status) in finished {
+        let expected = if id == ids[0] { JobStatus::Done } else { JobStatus::Cancelled };
+        assert_eq!(status, expected);
+    }
+    // Finished jobs can't be cancelled
+    assert!(!pool.cancel(ids[0]));
+    assert!(pool.take_map(ids[0]).is_some());
+    for id in ids.iter().skip(1) {
+        assert!(pool.take_map(*id).is_none());
+        assert_eq!(pool.status(*id), None);
+    }
+    assert!(!pool.cancel(12345));
+    assert!(pool.is_empty());
+}
+
+#[test]
+fn job_table_drains() {
+    let biome = BiomeRegistry::builtin().get_or_fallback("Cave");
+    // One worker finishes the jobs in order, only the last two maps are kept
+    let mut pool = JobPool::with_unclaimed_maps(1, 2);
+    let ids: Vec<u64> = (0..4).map(|seed| pool.generate(20, 20, biome.clone(), seed).unwrap()).collect();
+    let finished = wait_for(&pool, &ids);
+    assert!(finished.iter().all(|(_, status)| *status == JobStatus::Done));
+    assert_eq!(pool.len(), 2);
+    for id in ids.iter().take(2) {
+        assert_eq!(pool.status(*id), None);
+        assert!(pool.take_map(*id).is_none());
+    }
+    for id in ids.iter().skip(2) {
+        assert!(pool.take_map(*id).is_some());
+    }
+    assert!(pool.is_empty());
+
+    // Cancelled jobs leave the table as soon as they stop
+    let running = pool.generate(300, 300, biome.clone(), 9).unwrap();
+    let queued = pool.generate(300, 300, biome, 10).unwrap();
+    assert!(pool.cancel(queued));
+    assert_eq!(pool.status(queued), None);
+    assert!(pool.cancel(running));
+    assert_eq!(wait_for(&pool, &[running, queued]).len(), 2);
+    assert!(pool.is_empty());
+}
+
+#[test]
+fn bad_sizes_are_never_queued() {
+    let biome = BiomeRegistry::builtin().get_or_fallback("Cave");
+    let mut pool = JobPool::new(1);
+    for &(size_x, size_y) in [(0, 10), (10, -3), (-1, -1), (MAX_MAP_SIZE + 1, 1)].iter() {
+        assert_eq!(pool.generate(size_x, size_y, biome.clone(), 1).unwrap_err().code(), 11);
+    }
+    assert!(pool.finished().is_empty());
+    // The worker is still there for the next job
+    let id = pool.generate(1, 1, biome, 1).unwrap();
+    assert_eq!(wait_for(&pool, &[id]), vec![(id, JobStatus::Done)]);
+}
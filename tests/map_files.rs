@@ -0,0 +1,44 @@
+// Map files, saved maps load back unchanged and legacy files (metadata in '$' sentinel tiles) are upgraded to a MapHeader
+
+use maps::{BiomeRegistry, Map, MapExit, MapHeader};
+
+#[test]
+fn legacy_map_files_are_upgraded() {
+    let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/legacy.map");
+    let map = Map::load_map(fixture, false).unwrap();
+    assert_eq!(map.tileset.to_rows(), vec!["######", "#..~.#", "#.t..#", "#..,.#", "##/###"]);
+    assert_eq!(map.header, MapHeader {
+        generator_version: 0, size_x: 6, size_y: 5, biome: String::new(),
+        seed: (1 << 32) | 7, // Split into the seed tile's x (high bits) and y (low bits)
+        world_x: 2, world_y: -1, world_z: 3, default_floor: '.', default_wall: '#',
+        spawn_x: 3, spawn_y: 2,
+        exits: vec![MapExit {side: String::from("south"), x: 2, y: 4, target_map: String::new(), arrival_x: 0, arrival_y: 0}],
+        reachable_area: 0.0
+    });
+    // Saving writes the new layout, which loads back the same
+    let file = std::env::temp_dir().join("maps_legacy_upgrade_test.map").to_string_lossy().to_string();
+    Map::save_map(&file, &map, false).unwrap();
+    assert!(std::fs::read_to_string(&file).unwrap().starts_with("{\"header\""));
+    let upgraded = Map::load_map(&file, false).unwrap();
+    assert_eq!(upgraded.header, map.header);
+    assert_eq!(upgraded.tileset, map.tileset);
+    std::fs::remove_file(&file).unwrap();
+}
+
+#[test]
+fn saved_maps_load_back() {
+    let biomes = BiomeRegistry::builtin();
+    for &compression in [false, true].iter() {
+        let file = std::env::temp_dir().join(format!("maps_save_load_test_{}.map", compression)).to_string_lossy().to_string();
+        for name in biomes.names() {
+            let map = Map::new_biome(45, 30, &biomes.get_or_fallback(&name), 11);
+            Map::save_map(&file, &map, compression).unwrap();
+            let loaded = Map::load_map(&file, compression).unwrap();
+            assert_eq!(loaded.header, map.header, "{}", name);
+            assert_eq!(loaded.tileset, map.tileset, "{}", name);
+            // Only readable with the compression it was saved with
+            assert!(Map::load_map(&file, !compression).is_err(), "{}", name);
+        }
+        std::fs::remove_file(&file).unwrap();
+    }
+}
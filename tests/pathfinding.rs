@@ -0,0 +1,396 @@
+// A* against brute force searches on generated maps, every search uses PathMap::move_cost so costs always match
+// HPA* is checked against A* (valid, never cheaper, not much more expensive), world routes on a small hand made world
+// Flow fields are checked against A* to the closest goal, and goal updates against a field made from scratch
+// Each test runs with every built-in cost profile and movement mode
+
+use std::collections::{BTreeMap, VecDeque};
+
+use maps::hpa::ClusterGraph;
+use maps::{find_world_path, BiomeRegistry, ClimateModel, CostProfile, FlowField, Map, MapExit, MapHeader, Movement, MAX_TILE_COST, PathMap, TileGrid, World, WorldStep};
+use rand::Rng;
+
+// Dijkstra over every tile (no heuristic), cheapest cost from start to every tile
+fn dijkstra(path_map: &PathMap, tileset: &TileGrid, profile: &CostProfile, movement: Movement, start: usize) -> Vec<Option<i32>> {
+    let mut costs: Vec<Option<i32>> = vec![None; tileset.len()];
+    let mut done = vec![false; tileset.len()];
+    costs[start] = Some(0);
+    loop {
+        // Slow on purpose: scan for the cheapest unfinished tile
+        let mut current = None;
+        for tile in 0..tileset.len() {
+            if let (false, Some(cost)) = (done[tile], costs[tile]) {
+                if current.map_or(true, |(_, best)| cost < best) {
+                    current = Some((tile, cost));
+                }
+            }
+        }
+        let (tile, cost) = match current {
+            Some(current) => current,
+            None => break,
+        };
+        done[tile] = true;
+        for &neighbor in path_map.path_tiles[tile].neighbors.iter() {
+            if let Some(step_cost) = PathMap::move_cost(tileset, profile, movement, tile, neighbor) {
+                if costs[neighbor].map_or(true, |old| cost + step_cost < old) {
+                    costs[neighbor] = Some(cost + step_cost);
+                }
+            }
+        }
+    }
+    costs
+}
+
+// Breadth first search, number of steps from start to every tile
+fn bfs(path_map: &PathMap, tileset: &TileGrid, profile: &CostProfile, movement: Movement, start: usize) -> Vec<Option<i32>> {
+    let mut steps: Vec<Option<i32>> = vec![None; tileset.len()];
+    let mut queue = VecDeque::new();
+    steps[start] = Some(0);
+    queue.push_back(start);
+    while let Some(tile) = queue.pop_front() {
+        for &neighbor in path_map.path_tiles[tile].neighbors.iter() {
+            if steps[neighbor].is_none() && PathMap::move_cost(tileset, profile, movement, tile, neighbor).is_some() {
+                steps[neighbor] = Some(steps[tile].unwrap() + 1);
+                queue.push_back(neighbor);
+            }
+        }
+    }
+    steps
+}
+
+fn generated_maps() -> Vec<Map> {
+    let biomes = BiomeRegistry::builtin();
+    biomes.names().iter().enumerate().map(|(seed, name)| {
+        Map::new_biome(40, 30, &biomes.get_or_fallback(name), seed as u64)
+    }).collect()
+}
+
+fn profiles() -> Vec<CostProfile> {
+    CostProfile::builtin_names().iter().filter_map(|name| CostProfile::builtin(name)).collect()
+}
+
+static MOVEMENTS: [Movement; 3] = [Movement::Sides, Movement::Diagonal, Movement::NoCornerCutting];
+
+#[test]
+fn path_cost_matches_dijkstra() {
+    let mut rng = Map::seeded_rng(11);
+    for map in generated_maps() {
+        let tileset = &map.tileset;
+        let mut path_map = PathMap::new(tileset);
+        for profile in profiles() {
+            for &movement in MOVEMENTS.iter() {
+                for _ in 0..2 {
+                    let start = rng.gen_range(0, tileset.len());
+                    let costs = dijkstra(&path_map, tileset, &profile, movement, start);
+                    for _ in 0..10 {
+                        let end = rng.gen_range(0, tileset.len());
+                        let path = path_map.find_path(start, end, tileset, &profile, movement);
+                        assert_eq!(path.as_ref().map(|p| p.cost), costs[end], "{} {} {:?} {} -> {}",
+                                   map.header.biome, profile.name, movement, start, end);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn unit_cost_path_length_matches_bfs() {
+    // Walker side steps all cost the same, so the cheapest path is also the one with fewest steps
+    let profile = CostProfile::walker();
+    let movement = Movement::Sides;
+    let mut rng = Map::seeded_rng(12);
+    for map in generated_maps() {
+        let tileset = &map.tileset;
+        let mut path_map = PathMap::new(tileset);
+        let start = rng.gen_range(0, tileset.len());
+        let steps = bfs(&path_map, tileset, &profile, movement, start);
+        for _ in 0..20 {
+            let end = rng.gen_range(0, tileset.len());
+            let path = path_map.find_path(start, end, tileset, &profile, movement);
+            assert_eq!(path.map(|p| p.tiles.len() as i32 - 1), steps[end]);
+        }
+    }
+}
+
+#[test]
+fn path_is_connected_and_cost_adds_up() {
+    let mut rng = Map::seeded_rng(13);
+    for map in generated_maps() {
+        let tileset = &map.tileset;
+        let mut path_map = PathMap::new(tileset);
+        for profile in profiles() {
+            for &movement in MOVEMENTS.iter() {
+                for _ in 0..10 {
+                    let start = rng.gen_range(0, tileset.len());
+                    let end = rng.gen_range(0, tileset.len());
+                    let path = match path_map.find_path(start, end, tileset, &profile, movement) {
+                        Some(path) => path,
+                        None => continue,
+                    };
+                    assert_eq!(path.tiles.first(), Some(&start));
+                    assert_eq!(path.tiles.last(), Some(&end));
+                    let mut cost = 0;
+                    for step in path.tiles.windows(2) {
+                        assert!(path_map.path_tiles[step[0]].neighbors.contains(&step[1]));
+                        cost += PathMap::move_cost(tileset, &profile, movement, step[0], step[1]).expect("path takes a blocked step");
+                    }
+                    assert_eq!(cost, path.cost);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn walker_never_enters_walls_or_water() {
+    let profile = CostProfile::walker();
+    for map in generated_maps() {
+        let tileset = &map.tileset;
+        let mut path_map = PathMap::new(tileset);
+        let spawn = tileset.index(map.header.spawn_x, map.header.spawn_y);
+        for end in (0..tileset.len()).step_by(37) {
+            if let Some(path) = path_map.find_path(spawn, end, tileset, &profile, Movement::Diagonal) {
+                for tile in path.tiles.iter().skip(1) {
+                    assert!(profile.is_passable(tileset.tiles()[*tile]));
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn diagonal_costs_and_corner_cutting() {
+    let profile = CostProfile::walker();
+    // Open map, cost is the octile distance (10 per side step, 14 per diagonal)
+    let open = TileGrid::new(20, 20, '.');
+    let mut path_map = PathMap::new(&open);
+    let path = path_map.find_path(open.index(0, 0), open.index(19, 5), &open, &profile, Movement::Diagonal).unwrap();
+    assert_eq!(path.cost, 5 * 14 + 14 * 10);
+    assert_eq!(path.tiles.len(), 20);
+    let path = path_map.find_path(open.index(0, 0), open.index(19, 5), &open, &profile, Movement::Sides).unwrap();
+    assert_eq!(path.cost, 24 * 10);
+    // Edge tiles are used like any other tile
+    let path = path_map.find_path(open.index(0, 0), open.index(0, 19), &open, &profile, Movement::Sides).unwrap();
+    assert!(path.tiles.iter().all(|tile| open.position(*tile).0 == 0));
+    // Start boxed in by walls on both sides, only a diagonal squeeze gets out
+    let mut boxed = TileGrid::new(3, 3, '.');
+    boxed.set(1, 0, '#');
+    boxed.set(0, 1, '#');
+    let mut path_map = PathMap::new(&boxed);
+    let (start, end) = (boxed.index(0, 0), boxed.index(2, 2));
+    assert_eq!(path_map.find_path(start, end, &boxed, &profile, Movement::Diagonal).map(|p| p.cost), Some(28));
+    assert_eq!(path_map.find_path(start, end, &boxed, &profile, Movement::NoCornerCutting), None);
+    assert_eq!(path_map.find_path(start, end, &boxed, &profile, Movement::Sides), None);
+}
+
+#[test]
+fn cost_profile_definitions() {
+    let climber = CostProfile::from_json(r##"{"name": "climber", "costs": {"floor": 1, "#": 5, "water": null}}"##).unwrap();
+    assert_eq!(climber.cost('#'), Some(5));
+    assert_eq!(climber.cost('~'), None);
+    assert_eq!(climber.cost('t'), None);
+    assert!(CostProfile::from_json(r#"{"name": "bad", "costs": {"lava": 1}}"#).is_err());
+    assert!(CostProfile::from_json(r#"{"name": "free", "costs": {"floor": 0}}"#).is_err());
+    // Costs are capped so path costs stay far from i32 overflow
+    let heavy = format!(r#"{{"name": "heavy", "costs": {{"floor": {}}}}}"#, MAX_TILE_COST);
+    assert_eq!(CostProfile::from_json(&heavy).unwrap().cost('.'), Some(MAX_TILE_COST));
+    let too_heavy = format!(r#"{{"name": "too_heavy", "costs": {{"floor": 1}}, "other": {}}}"#, MAX_TILE_COST + 1);
+    assert!(CostProfile::from_json(&too_heavy).is_err());
+}
+
+#[test]
+fn hierarchical_path_is_valid_and_near_optimal() {
+    let mut rng = Map::seeded_rng(14);
+    let (mut total_cost, mut total_optimal) = (0i64, 0i64);
+    for map in generated_maps() {
+        let tileset = &map.tileset;
+        let mut path_map = PathMap::new(tileset);
+        for profile in profiles() {
+            for &movement in MOVEMENTS.iter() {
+                let graph = ClusterGraph::build(tileset, &profile, movement, 8);
+                for _ in 0..20 {
+                    let start = rng.gen_range(0, tileset.len());
+                    let end = rng.gen_range(0, tileset.len());
+                    let optimal = path_map.find_path(start, end, tileset, &profile, movement);
+                    let path = graph.find_path(tileset, &profile, movement, start, end);
+                    // Diagonal squeezes across a cluster corner aren't entrances, every other route crosses at an entrance
+                    if movement != Movement::Diagonal {
+                        assert_eq!(path.is_some(), optimal.is_some(), "{} {} {:?} {} -> {}",
+                                   map.header.biome, profile.name, movement, start, end);
+                    }
+                    let (path, optimal) = match (path, optimal) {
+                        (Some(path), Some(optimal)) => (path, optimal),
+                        _ => continue,
+                    };
+                    assert_eq!(path.tiles.first(), Some(&start));
+                    assert_eq!(path.tiles.last(), Some(&end));
+                    let mut cost = 0;
+                    for step in path.tiles.windows(2) {
+                        cost += PathMap::move_cost(tileset, &profile, movement, step[0], step[1]).expect("path takes a blocked step");
+                    }
+                    assert_eq!(cost, path.cost);
+                    assert!(path.cost >= optimal.cost);
+                    total_cost += path.cost as i64;
+                    total_optimal += optimal.cost as i64;
+                }
+            }
+        }
+    }
+    // Short paths next to a cluster border can take a detour to an entrance, on average it stays close
+    assert!(total_cost * 10 <= total_optimal * 11, "{} vs optimal {}", total_cost, total_optimal);
+}
+
+#[test]
+fn long_border_runs_get_an_entrance_in_the_middle() {
+    // Two 10x10 clusters, the wall at (9, 2) splits their border into a short run (rows 0-1) and a long one (rows 3-9)
+    let rows: Vec<String> = (0..10).map(|y| if y == 2 { ".........#.........." } else { "...................." }.to_string()).collect();
+    let tileset = TileGrid::from_rows(20, 10, &rows, '#');
+    let profile = CostProfile::walker();
+    let graph = ClusterGraph::build(&tileset, &profile, Movement::Sides, 10);
+    let mut entrances: Vec<(i32, i32)> = graph.nodes.iter().map(|&node| tileset.position(node)).collect();
+    entrances.sort_by_key(|&(x, y)| (y, x));
+    // Short runs only get the middle, long runs get both ends and the middle
+    assert_eq!(entrances, vec![(9, 0), (10, 0), (9, 3), (10, 3), (9, 6), (10, 6), (9, 9), (10, 9)]);
+    // Going straight across the long run doesn't detour to one of its ends
+    let (start, end) = (tileset.index(0, 6), tileset.index(19, 6));
+    let optimal = PathMap::new(&tileset).find_path(start, end, &tileset, &profile, Movement::Sides).unwrap();
+    assert_eq!(graph.find_path(&tileset, &profile, Movement::Sides, start, end).unwrap().cost, optimal.cost);
+}
+
+#[test]
+fn hierarchical_cache_is_reused_until_tiles_change() {
+    let biomes = BiomeRegistry::builtin();
+    let mut map = Map::new_biome(40, 30, &biomes.get_or_fallback("Cave"), 3);
+    let profile = CostProfile::walker();
+    let movement = Movement::NoCornerCutting;
+    let map_file = std::env::temp_dir().join("maps_hpa_cache_test.json").to_string_lossy().to_string();
+    Map::save_map(&map_file, &map, false).unwrap();
+    let built = ClusterGraph::load_or_build(&map_file, &map.tileset, &profile, movement, 8).unwrap();
+    let cache_file = ClusterGraph::cache_file(&map_file, &profile, movement);
+    let loaded = ClusterGraph::load(&cache_file, &map.tileset).unwrap();
+    assert_eq!(loaded, built);
+    assert!(loaded.is_current(&map.tileset, &profile, movement, 8));
+    // Other cluster sizes, profiles and tile changes all need a new graph
+    assert!(!loaded.is_current(&map.tileset, &profile, movement, 10));
+    assert!(!loaded.is_current(&map.tileset, &CostProfile::swimmer(), movement, 8));
+    let flipped = if map.tileset.at(5, 5) == '#' { '.' } else { '#' };
+    map.tileset.set(5, 5, flipped);
+    assert!(!loaded.is_current(&map.tileset, &profile, movement, 8));
+    let rebuilt = ClusterGraph::load_or_build(&map_file, &map.tileset, &profile, movement, 8).unwrap();
+    assert!(rebuilt.is_current(&map.tileset, &profile, movement, 8));
+    std::fs::remove_file(&map_file).unwrap();
+    std::fs::remove_file(&cache_file).unwrap();
+}
+
+// Open floor map at a world position, exits are (x, y, side, target map, arrival x, arrival y)
+fn world_map(world: &World, name: &str, exits: &[(i32, i32, &str, &str, i32, i32)]) {
+    let (world_x, world_y, world_z) = World::parse_map_name(name).unwrap();
+    let header = MapHeader {
+        generator_version: 0, size_x: 20, size_y: 20, biome: String::new(), seed: 0,
+        world_x: world_x, world_y: world_y, world_z: world_z, default_floor: '.', default_wall: '#',
+        spawn_x: 10, spawn_y: 10, reachable_area: 100.0,
+        exits: exits.iter().map(|(x, y, side, target, arrival_x, arrival_y)| MapExit {
+            side: side.to_string(), x: *x, y: *y, target_map: target.to_string(), arrival_x: *arrival_x, arrival_y: *arrival_y
+        }).collect()
+    };
+    let map = Map {header: header, tileset: TileGrid::new(20, 20, '.')};
+    Map::save_map(&world.get_map_path(name.to_string()), &map, false).unwrap();
+}
+
+#[test]
+fn world_path_crosses_maps_and_levels() {
+    let directory = std::env::temp_dir().join("maps_world_path_test");
+    std::fs::create_dir_all(directory.join("maps")).unwrap();
+    let world = World {
+        world_name: String::from("route"), size_x: 2, size_y: 2, size_z: 1, seed: 0, map_size_x: 20, map_size_y: 20, climate: ClimateModel::builtin(),
+        directory: directory.to_string_lossy().to_string(), compress_maps: false, maps: BTreeMap::new()
+    };
+    // Three maps west to east, a level above the last one, and a map north of the start that is off the route
+    world_map(&world, "x0y0z0", &[(19, 10, "east", "x1y0z0", 0, 10), (10, 0, "north", "x0y1z0", 10, 19)]);
+    world_map(&world, "x1y0z0", &[(0, 10, "west", "x0y0z0", 19, 10), (19, 10, "east", "x2y0z0", 0, 10)]);
+    world_map(&world, "x2y0z0", &[(0, 10, "west", "x1y0z0", 19, 10), (15, 15, "above", "x2y0z1", 15, 15)]);
+    world_map(&world, "x2y0z1", &[(15, 15, "below", "x2y0z0", 15, 15)]);
+    world_map(&world, "x0y1z0", &[(10, 19, "south", "x0y0z0", 10, 0)]);
+    let profile = CostProfile::walker();
+
+    let path = find_world_path(&world, "x0y0z0", "5x10", "x2y0z1", "15x15", &profile, Movement::Sides).unwrap().unwrap();
+    // Best case is 14 steps to the east exit, 19 across the middle map, 20 to the stairs, plus a step for each of the 3 exits
+    // HPA* can take a small detour inside a map, every step on floor still costs 10
+    let shortest = (14 + 19 + 20 + 3) * 10;
+    assert!(path.cost >= shortest && path.cost <= shortest * 13 / 10, "{} vs {}", path.cost, shortest);
+    assert_eq!(path.cost, (path.steps.len() as i32 - 1) * 10);
+    assert_eq!(path.steps.first(), Some(&WorldStep {map: String::from("x0y0z0"), x: 5, y: 10}));
+    assert_eq!(path.steps.last(), Some(&WorldStep {map: String::from("x2y0z1"), x: 15, y: 15}));
+    let mut maps: Vec<&str> = path.steps.iter().map(|step| step.map.as_str()).collect();
+    maps.dedup();
+    assert_eq!(maps, vec!["x0y0z0", "x1y0z0", "x2y0z0", "x2y0z1"]);
+    assert!(!path.maps_loaded.contains(&String::from("x0y1z0")));
+
+    // Back down and west through the same exits, a route inside one map, and a tile off the map
+    let back = find_world_path(&world, "x2y0z1", "15x15", "x0y0z0", "5x10", &profile, Movement::Sides).unwrap().unwrap();
+    assert!(back.cost >= shortest && back.cost <= shortest * 13 / 10);
+    assert_eq!(back.steps.last(), Some(&WorldStep {map: String::from("x0y0z0"), x: 5, y: 10}));
+    let same_map = find_world_path(&world, "x1y0z0", "0x0", "x1y0z0", "3x4", &profile, Movement::Sides).unwrap().unwrap();
+    assert_eq!(same_map.cost, 70);
+    assert!(find_world_path(&world, "x0y0z0", "5x10", "x0y0z0", "25x10", &profile, Movement::Sides).is_err());
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn flow_field_matches_astar_to_closest_goal() {
+    let mut rng = Map::seeded_rng(15);
+    for map in generated_maps() {
+        let tileset = &map.tileset;
+        let mut path_map = PathMap::new(tileset);
+        for profile in profiles() {
+            for &movement in MOVEMENTS.iter() {
+                let goals: Vec<usize> = (0..3).map(|_| rng.gen_range(0, tileset.len())).collect();
+                let field = FlowField::new(tileset, &profile, movement, &goals);
+                for _ in 0..10 {
+                    let tile = rng.gen_range(0, tileset.len());
+                    let closest = goals.iter().filter_map(|goal| path_map.find_path(tile, *goal, tileset, &profile, movement).map(|p| p.cost)).min();
+                    assert_eq!(field.cost(tile), closest, "{} {} {:?} {}", map.header.biome, profile.name, movement, tile);
+                    // Following next_step adds up to the same cost and ends on a goal
+                    let mut current = tile;
+                    let mut cost = 0;
+                    while let Some(next) = field.next_step(current) {
+                        cost += PathMap::move_cost(tileset, &profile, movement, current, next).unwrap();
+                        current = next;
+                    }
+                    if closest.is_some() {
+                        assert!(goals.contains(&current));
+                        assert_eq!(Some(cost), closest);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn flow_field_goal_updates_match_a_new_field() {
+    let mut rng = Map::seeded_rng(16);
+    for map in generated_maps() {
+        let tileset = &map.tileset;
+        for profile in profiles() {
+            for &movement in MOVEMENTS.iter() {
+                let mut goals: Vec<usize> = (0..2).map(|_| rng.gen_range(0, tileset.len())).collect();
+                let mut field = FlowField::new(tileset, &profile, movement, &goals);
+                for _ in 0..5 {
+                    // Goal walks a couple of tiles, sometimes a goal is added or dropped
+                    let (x, y) = tileset.position(goals[0]);
+                    let (x, y) = ((x + rng.gen_range(-2, 3)).max(0).min(tileset.size_x() - 1), (y + rng.gen_range(-2, 3)).max(0).min(tileset.size_y() - 1));
+                    goals[0] = tileset.index(x, y);
+                    match rng.gen_range(0, 3) {
+                        0 => goals.push(rng.gen_range(0, tileset.len())),
+                        1 if goals.len() > 1 => { goals.pop(); },
+                        _ => (),
+                    }
+                    field.set_goals(tileset, &goals);
+                    let fresh = FlowField::new(tileset, &profile, movement, &goals);
+                    assert_eq!(field.costs(), fresh.costs(), "{} {} {:?}", map.header.biome, profile.name, movement);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,61 @@
+// Map reports on a fixed map, every count, group and problem is known ahead of time
+
+use maps::{Biome, Map, MapHeader, MapReport, TileGrid};
+
+fn report_map() -> Map {
+    let rows: Vec<String> = ["#####", "#..~#", "#~#,.", "##t/#"].iter().map(|row| row.to_string()).collect();
+    let header = MapHeader {
+        generator_version: 0, size_x: 5, size_y: 4, biome: String::from("Report"), seed: 0,
+        world_x: 0, world_y: 0, world_z: 0, default_floor: '.', default_wall: '#',
+        spawn_x: 1, spawn_y: 1, reachable_area: 0.0, exits: Vec::new()
+    };
+    Map {header: header, tileset: TileGrid::from_rows(5, 4, &rows, '#')}
+}
+
+#[test]
+fn report_counts_a_fixed_map() {
+    let map = report_map();
+    // Fallback biome: a third each of floor, wall and water, with an outer wall
+    let biome = Biome::fallback(String::from("Report"));
+    let report = MapReport::new(&map, &biome);
+    assert_eq!((report.biome.as_str(), report.size_x, report.size_y), ("Report", 5, 4));
+
+    // (tile, count, side-connected groups), in TILE_TYPE order, road and bridge are missing and not requested
+    let tiles: Vec<(char, usize, usize)> = report.tiles.iter().map(|tile| (tile.tile_type, tile.count, tile.components)).collect();
+    assert_eq!(tiles, vec![('.', 3, 2), ('#', 12, 3), ('~', 2, 2), (',', 1, 1), ('t', 1, 1), ('/', 1, 1)]);
+    let percents: Vec<(f32, f32)> = report.tiles.iter().map(|tile| (tile.percent, tile.requested_percent)).collect();
+    for (percent, expected) in percents.iter().zip([(15.0, 33.0), (60.0, 33.0), (10.0, 33.0), (5.0, 0.0), (5.0, 0.0), (5.0, 0.0)].iter()) {
+        assert!((percent.0 - expected.0).abs() < 0.01 && (percent.1 - expected.1).abs() < 0.01, "{:?} {:?}", percent, expected);
+    }
+
+    // Walkable: the spawn room (2 tiles) and the sand/floor/exit group on the right (3 tiles)
+    assert_eq!((report.walkable_tiles, report.walkable_components, report.largest_walkable_area), (5, 2, 3));
+    assert!(report.spawn_walkable);
+    assert_eq!(report.spawn_reachable_tiles, 2);
+    assert!((report.reachable_area - 40.0).abs() < 0.01);
+    // Exits are allowed in the outer wall, floor and trees aren't
+    assert!(report.border_checked);
+    assert_eq!(report.border_breaks, vec![(4, 2), (2, 3)]);
+
+    assert_eq!(report.problems(50.0, 30.0), vec![
+        String::from("only 40.0% of walkable tiles are reachable from spawn"),
+        String::from("outer wall has 2 gaps"),
+    ]);
+    // Walls are 27% and water 23% off their requested percent, spawn reaches exactly the minimum
+    assert_eq!(report.problems(40.0, 20.0), vec![
+        String::from("outer wall has 2 gaps"),
+        String::from("'#' covers 60.0% of the map, requested 33.0%"),
+        String::from("'~' covers 10.0% of the map, requested 33.0%"),
+    ]);
+
+    // Border isn't checked without an outer wall, a spawn on a wall reaches nothing
+    let mut biome = biome;
+    biome.biome_control.outer_wall = false;
+    let mut map = map;
+    map.header.spawn_x = 0;
+    let report = MapReport::new(&map, &biome);
+    assert!(!report.border_checked && report.border_breaks.is_empty());
+    assert!(!report.spawn_walkable);
+    assert_eq!((report.spawn_reachable_tiles, report.reachable_area), (0, 0.0));
+    assert_eq!(report.problems(0.0, 100.0), vec![String::from("spawn is not walkable")]);
+}
@@ -0,0 +1,31 @@
+// TileMap layers, trees go on the overlay with the map's default floor under them on the ground, everything else is ground only
+// Chars without a TileSet index leave the cell empty
+
+mod common;
+
+use std::collections::HashMap;
+
+use common::grid;
+use maps::{Map, MapHeader, TileMapLayers};
+
+fn map(rows: &[&str], default_floor: char) -> Map {
+    let tileset = grid(rows);
+    let header = MapHeader {
+        generator_version: 0, size_x: tileset.size_x(), size_y: tileset.size_y(), biome: String::new(), seed: 0,
+        world_x: 0, world_y: 0, world_z: 0, default_floor: default_floor, default_wall: '#',
+        spawn_x: 1, spawn_y: 1, reachable_area: 100.0, exits: Vec::new()
+    };
+    Map {header: header, tileset: tileset}
+}
+
+#[test]
+fn trees_go_on_the_overlay() {
+    let tile_indexes: HashMap<char, i64> = [('.', 0), ('#', 1), ('~', 2), (',', 3), ('t', 4)].iter().cloned().collect();
+    let layers = TileMapLayers::new(&map(&["#t#", ".~=", ",t#"], ','), &tile_indexes);
+    assert_eq!(layers.ground, vec![1, 3, 1, 0, 2, -1, 3, 3, 1]);
+    assert_eq!(layers.overlay, vec![-1, 4, -1, -1, -1, -1, -1, 4, -1]);
+
+    // Default floor decides what's under the trees
+    let layers = TileMapLayers::new(&map(&["t."], '.'), &tile_indexes);
+    assert_eq!((layers.ground, layers.overlay), (vec![0, 0], vec![4, -1]));
+}
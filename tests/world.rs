@@ -0,0 +1,422 @@
+// Worlds made of generated maps, exits have to line up between neighbors and be reachable from each map's spawn
+// Roads have to join every exit (and the spawn) and only bridge water when going around is much longer, off-map endpoints draw nothing
+// World files have to load back exactly, legacy world files are upgraded
+// Maps generated on demand only depend on the world seed and their position, whatever order they're asked for in
+// Their biomes come from the world climate, so neighbors mostly share a biome and underground levels get underground biomes
+// Positions from Godot outside the world (or past i32) are errors
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+use maps::connectivity::reachable_from;
+use maps::{find_world_path, BiomeRegistry, ClimateModel, CostProfile, LazyWorld, Map, MapExit, MapHeader, MapStatus, Movement, PathMap, TileGrid, World, WorldMap, UNBOUNDED};
+
+// World in a temp directory, maps are (name, biome) pairs generated with a seed from their position in the list
+fn generated_world(directory_name: &str, maps: &[(&str, &str)]) -> (World, PathBuf) {
+    generated_world_with(directory_name, maps, false)
+}
+fn generated_world_with(directory_name: &str, maps: &[(&str, &str)], compress_maps: bool) -> (World, PathBuf) {
+    let directory = std::env::temp_dir().join(directory_name);
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(directory.join("maps")).unwrap();
+    let mut world = World {
+        world_name: String::from(directory_name), size_x: 1, size_y: 1, size_z: 1, seed: 0, map_size_x: 40, map_size_y: 30, climate: ClimateModel::builtin(),
+        directory: directory.to_string_lossy().to_string(), compress_maps: compress_maps, maps: BTreeMap::new()
+    };
+    let biomes = BiomeRegistry::builtin();
+    for (seed, (name, biome_name)) in maps.iter().enumerate() {
+        let (world_x, world_y, world_z) = World::parse_map_name(name).unwrap();
+        let mut map = Map::new_biome(40, 30, &biomes.get_or_fallback(biome_name), seed as u64);
+        map.header.world_x = world_x;
+        map.header.world_y = world_y;
+        map.header.world_z = world_z;
+        assert_eq!(world.add_map(&map).unwrap(), name.to_string());
+    }
+    (world, directory)
+}
+
+#[test]
+fn exits_line_up_between_neighbors() {
+    // Plus shape around x0y0z0 with a level above it, the map to the west has a biome without exits
+    let (mut world, directory) = generated_world("maps_world_exits_test", &[
+        ("x0y0z0", "Cave"), ("x1y0z0", "Forest"), ("x0y1z0", "Desert"), ("x0y-1z0", "Ocean"),
+        ("x0y0z1", "Underlake"), ("x-1y0z0", "No Exits")
+    ]);
+    world.add_map_exits(&BiomeRegistry::builtin()).unwrap();
+    let maps: HashMap<String, Map> = world.maps.keys().map(|name| (name.clone(), world.load_map(name).unwrap())).collect();
+
+    let sides = |name: &str| maps[name].header.exits.iter().map(|exit| exit.side.clone()).collect::<Vec<String>>();
+    assert_eq!(sides("x0y0z0"), vec!["above", "east", "north", "south"]);
+    assert_eq!(sides("x1y0z0"), vec!["west"]);
+    assert_eq!(sides("x0y1z0"), vec!["south"]);
+    assert_eq!(sides("x0y-1z0"), vec!["north"]);
+    assert_eq!(sides("x0y0z1"), vec!["below"]);
+    assert!(sides("x-1y0z0").is_empty());
+
+    let profile = CostProfile::walker();
+    for (name, map) in maps.iter() {
+        let tileset = &map.tileset;
+        let mut path_map = PathMap::new(tileset);
+        let spawn = tileset.index(map.header.spawn_x, map.header.spawn_y);
+        for exit in map.header.exits.iter() {
+            let on_side = match exit.side.as_str() {
+                "north" => exit.y == 0,
+                "south" => exit.y == tileset.size_y() - 1,
+                "east" => exit.x == tileset.size_x() - 1,
+                "west" => exit.x == 0,
+                _ => !tileset.is_border(exit.x, exit.y),
+            };
+            assert!(on_side, "{} {:?}", name, exit);
+            assert_eq!(tileset.at(exit.x, exit.y), '/');
+            let exit_tile = tileset.index(exit.x, exit.y);
+            assert!(path_map.find_path(spawn, exit_tile, tileset, &profile, Movement::Sides).is_some(), "{} {:?}", name, exit);
+            // The neighbor's exit leads back, arriving on this exit
+            let back = maps[&exit.target_map].header.exits.iter().find(|back| back.target_map == *name).unwrap();
+            assert_eq!((back.x, back.y), (exit.arrival_x, exit.arrival_y));
+            assert_eq!((back.arrival_x, back.arrival_y), (exit.x, exit.y));
+            match exit.side.as_str() {
+                "north" | "south" => assert_eq!(exit.x, back.x),
+                "east" | "west" => assert_eq!(exit.y, back.y),
+                _ => assert_eq!((exit.x, exit.y), (back.x, back.y)),
+            }
+        }
+    }
+
+    // Forest and Desert have roads from spawn to their exits, walking on nothing but road
+    for name in ["x1y0z0", "x0y1z0"].iter() {
+        let map = &maps[*name];
+        assert!(map.tileset.tiles().contains(&'='), "{}", name);
+        let spawn = map.tileset.index(map.header.spawn_x, map.header.spawn_y);
+        let on_road = reachable_from(&map.tileset, spawn, |c| c == '=' || c == '+' || c == '/');
+        for exit in map.header.exits.iter() {
+            assert!(on_road[map.tileset.index(exit.x, exit.y)], "{} {:?}", name, exit);
+        }
+    }
+
+    // Exits don't depend on load order, adding them again gives the same maps
+    world.add_map_exits(&BiomeRegistry::builtin()).unwrap();
+    for (name, map) in maps.iter() {
+        let again = world.load_map(name).unwrap();
+        assert_eq!(again.header, map.header);
+        assert_eq!(again.tileset.to_ids(), map.tileset.to_ids());
+    }
+
+    // Every connected map can be walked to from every other through the exits
+    for (from, to) in [("x1y0z0", "x0y0z1"), ("x0y1z0", "x0y-1z0")].iter() {
+        let start = format!("{}x{}", maps[*from].header.spawn_x, maps[*from].header.spawn_y);
+        let end = format!("{}x{}", maps[*to].header.spawn_x, maps[*to].header.spawn_y);
+        let path = find_world_path(&world, from, &start, to, &end, &profile, Movement::Sides).unwrap();
+        assert!(path.is_some(), "{} -> {}", from, to);
+    }
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+// Floor map with exits on the west and east edges halfway down, water tiles from the list
+#[test]
+fn routes_far_from_the_middle_of_the_world() {
+    // World tile positions here are past i32 (100000000 maps of 40 tiles)
+    let (mut world, directory) = generated_world("maps_world_far_route_test", &[("x100000000y-100000000z0", "Cave"), ("x100000001y-100000000z0", "Forest")]);
+    world.size_x = UNBOUNDED;
+    world.size_y = UNBOUNDED;
+    world.add_map_exits(&BiomeRegistry::builtin()).unwrap();
+    let (from, to) = ("x100000000y-100000000z0", "x100000001y-100000000z0");
+    let (start_map, end_map) = (world.load_map(from).unwrap(), world.load_map(to).unwrap());
+    let start = format!("{}x{}", start_map.header.spawn_x, start_map.header.spawn_y);
+    let end = format!("{}x{}", end_map.header.spawn_x, end_map.header.spawn_y);
+    let path = find_world_path(&world, from, &start, to, &end, &CostProfile::walker(), Movement::Sides).unwrap().unwrap();
+    assert_eq!(path.steps.first().unwrap().map, from);
+    assert_eq!(path.steps.last().unwrap().map, to);
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+fn road_map(water: &[(i32, i32)]) -> Map {
+    let header = MapHeader {
+        generator_version: 0, size_x: 30, size_y: 21, biome: String::from("Forest"), seed: 0,
+        world_x: 0, world_y: 0, world_z: 0, default_floor: '.', default_wall: '#',
+        spawn_x: 15, spawn_y: 3, reachable_area: 100.0, exits: Vec::new()
+    };
+    let mut map = Map {header: header, tileset: TileGrid::new(30, 21, '.')};
+    for (x, y) in water.iter() {
+        map.tileset.set(*x, *y, '~');
+    }
+    // Not add_map_exit, its tunnel from spawn would fill in the river
+    for (side, x) in [("east", 29), ("west", 0)].iter() {
+        map.tileset.set(*x, 10, '/');
+        map.header.exits.push(MapExit {side: side.to_string(), x: *x, y: 10, target_map: String::new(), arrival_x: 0, arrival_y: 0});
+    }
+    map
+}
+
+#[test]
+fn roads_bridge_water_only_when_needed() {
+    let biome = BiomeRegistry::builtin().get_or_fallback("Forest");
+    // A river across the whole map has to be bridged, once
+    let river: Vec<(i32, i32)> = (0..21).map(|y| (20, y)).collect();
+    let mut map = road_map(&river);
+    map.add_roads(&biome);
+    assert_eq!(map.tileset.tiles().iter().filter(|c| **c == '+').count(), 1);
+    let west = map.tileset.index(0, 10);
+    let on_road = reachable_from(&map.tileset, west, |c| c == '=' || c == '+' || c == '/');
+    assert!(on_road[map.tileset.index(29, 10)]);
+    assert!(on_road[map.tileset.index(15, 3)]);
+
+    // A small pond is cheaper to walk around
+    let pond: Vec<(i32, i32)> = (9..12).flat_map(|y| (6..9).map(move |x| (x, y))).collect();
+    let mut map = road_map(&pond);
+    map.add_roads(&biome);
+    assert_eq!(map.tileset.tiles().iter().filter(|c| **c == '+').count(), 0);
+    assert_eq!(map.tileset.tiles().iter().filter(|c| **c == '~').count(), pond.len());
+    let on_road = reachable_from(&map.tileset, west, |c| c == '=' || c == '+' || c == '/');
+    assert!(on_road[map.tileset.index(29, 10)]);
+
+    // Endpoints off the map draw nothing (instead of indexing past the grid)
+    let mut tileset = TileGrid::new(30, 21, '.');
+    for &(start, end) in [((-1, 10), (29, 10)), ((0, 10), (30, 10)), ((5, 21), (5, 0)), ((0, 0), (0, -1))].iter() {
+        Map::draw_road(start, end, &mut tileset);
+    }
+    assert!(tileset.tiles().iter().all(|c| *c == '.'));
+    Map::draw_road((0, 10), (29, 10), &mut tileset);
+    assert_eq!(tileset.tiles().iter().filter(|c| **c == '=').count(), 30);
+}
+
+#[test]
+fn world_file_round_trips() {
+    for &compress_maps in [false, true].iter() {
+        let directory_name = format!("maps_world_file_test_{}", compress_maps);
+        let (mut world, directory) = generated_world_with(&directory_name, &[("x0y0z0", "Forest"), ("x1y0z0", "Desert")], compress_maps);
+        world.add_map_exits(&BiomeRegistry::builtin()).unwrap();
+        // A map that is planned but not generated yet
+        world.maps.insert(String::from("x0y1z0"), WorldMap {
+            world_x: 0, world_y: 1, world_z: 0, biome: String::from("Cave"), seed: 77,
+            file: world.get_map_path(String::from("x0y1z0")), exits: Vec::new(), status: MapStatus::Pending
+        });
+        World::save_world(&world).unwrap();
+
+        let loaded = World::load_world_file(&world.world_path()).unwrap();
+        assert_eq!(loaded, world);
+        assert_eq!(World::open(&world.world_path()).unwrap(), world);
+        let entry = &loaded.maps["x1y0z0"];
+        assert_eq!((entry.biome.as_str(), entry.seed, entry.status), ("Desert", 1, MapStatus::Generated));
+        let map = loaded.load_map("x1y0z0").unwrap();
+        assert_eq!(map.header.exits, entry.exits);
+        assert_eq!(map.header.exits.len(), 1);
+        assert!(loaded.load_map("x0y1z0").is_err());
+        // Map files are only readable with the world's compression setting
+        assert!(Map::load_map(&entry.file, !compress_maps).is_err());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
+
+#[test]
+fn legacy_world_files_are_upgraded() {
+    let (world, directory) = generated_world("maps_world_legacy_test", &[("x0y0z0", "Cave")]);
+    let mut legacy = HashMap::new();
+    for (field, value) in [("world_name", world.world_name.clone()), ("size_x", String::from("1")), ("size_y", String::from("1")),
+                           ("size_z", String::from("1")), ("directory", world.directory.clone())].iter() {
+        legacy.insert(field.to_string(), value.clone());
+    }
+    legacy.insert(String::from("x0y0z0"), world.get_map_path(String::from("x0y0z0")));
+    legacy.insert(String::from("x1y0z0"), world.get_map_path(String::from("x1y0z0"))); // Never saved
+    std::fs::write(world.world_path(), serde_json::to_string(&legacy).unwrap()).unwrap();
+
+    let upgraded = World::load_world_file(&world.world_path()).unwrap();
+    assert_eq!(upgraded.maps["x0y0z0"], world.maps["x0y0z0"]);
+    assert_eq!(upgraded.maps["x1y0z0"].status, MapStatus::Pending);
+    assert_eq!(upgraded.maps.len(), 2);
+    assert_eq!((upgraded.size_x, upgraded.size_y, upgraded.size_z), (1, 1, 1));
+    // Saving writes the new layout
+    World::save_world(&upgraded).unwrap();
+    assert_eq!(World::load_world_file(&world.world_path()).unwrap(), upgraded);
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+// Unbounded world in a temp directory, nothing generated yet
+fn lazy_world(directory_name: &str, seed: u64, capacity: usize) -> (LazyWorld, PathBuf) {
+    let directory = std::env::temp_dir().join(directory_name);
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(directory.join("maps")).unwrap();
+    let world = World {
+        world_name: String::from(directory_name), size_x: UNBOUNDED, size_y: UNBOUNDED, size_z: UNBOUNDED, seed: seed,
+        map_size_x: 40, map_size_y: 30, climate: ClimateModel::builtin(), directory: directory.to_string_lossy().to_string(), compress_maps: false, maps: BTreeMap::new()
+    };
+    (LazyWorld::new(world, BiomeRegistry::builtin(), capacity), directory)
+}
+
+#[test]
+fn lazy_maps_only_depend_on_seed_and_position() {
+    let names = ["x0y0z0", "x1y0z0", "x0y1z0", "x0y0z-1", "x-1y0z0", "x1000000y-5z3"];
+    let (mut forward, forward_directory) = lazy_world("maps_lazy_world_forward_test", 42, names.len());
+    let (mut backward, backward_directory) = lazy_world("maps_lazy_world_backward_test", 42, names.len());
+    for name in names.iter() {
+        forward.map(name).unwrap();
+    }
+    for name in names.iter().rev() {
+        backward.map(name).unwrap();
+    }
+    let biomes = BiomeRegistry::builtin();
+    for name in names.iter() {
+        let map = forward.map(name).unwrap().clone();
+        let other = backward.map(name).unwrap();
+        assert_eq!(map.header, other.header, "{}", name);
+        assert_eq!(map.tileset.to_ids(), other.tileset.to_ids(), "{}", name);
+        assert_eq!(map.header.seed, forward.world.map_seed(map.header.world_x, map.header.world_y, map.header.world_z));
+
+        // Unbounded, so a biome with exits has one on every side
+        let has_exits = biomes.get_or_fallback(&map.header.biome).biome_control.exits;
+        let neighbors_with_exits = ["east", "west", "north", "south", "above", "below"].iter()
+            .filter(|side| {
+                let exit = map.header.exits.iter().find(|exit| exit.side == **side);
+                exit.map_or(false, |exit| biomes.get_or_fallback(&forward.world.plan_map(&exit.target_map).unwrap().biome).biome_control.exits)
+            }).count();
+        assert_eq!(neighbors_with_exits, if has_exits { map.header.exits.len() } else { 0 }, "{}", name);
+        // Exits to generated neighbors line up with the neighbor's exit back
+        for exit in map.header.exits.iter() {
+            if !names.contains(&exit.target_map.as_str()) {
+                continue;
+            }
+            let neighbor = forward.map(&exit.target_map).unwrap();
+            let back = neighbor.header.exits.iter().find(|back| back.target_map == *name).unwrap();
+            assert_eq!((back.x, back.y), (exit.arrival_x, exit.arrival_y));
+            assert_eq!((back.arrival_x, back.arrival_y), (exit.x, exit.y));
+            assert_eq!(neighbor.tileset.at(back.x, back.y), '/');
+        }
+    }
+    // A different seed gives a different world
+    let (mut other_seed, other_directory) = lazy_world("maps_lazy_world_other_seed_test", 43, 1);
+    assert_ne!(other_seed.map("x0y0z0").unwrap().header.seed, forward.map("x0y0z0").unwrap().header.seed);
+
+    // Generated maps are in the world file and load back the same
+    let saved = World::load_world_file(&forward.world.world_path()).unwrap();
+    assert_eq!(saved, forward.world);
+    assert_eq!(saved.maps.len(), names.len());
+    assert!(saved.maps.values().all(|entry| entry.status == MapStatus::Generated));
+    for directory in [forward_directory, backward_directory, other_directory].iter() {
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
+
+#[test]
+fn lazy_world_generates_pending_maps() {
+    // Planned map already listed in the world file, but never generated
+    let (mut world, directory) = lazy_world("maps_lazy_world_pending_test", 11, 2);
+    let entry = world.world.plan_map("x0y0z0").unwrap();
+    assert_eq!(entry.status, MapStatus::Pending);
+    world.world.maps.insert(String::from("x0y0z0"), entry.clone());
+    World::save_world(&world.world).unwrap();
+
+    let map = world.map("x0y0z0").unwrap().clone();
+    assert_eq!((map.header.biome.as_str(), map.header.seed), (entry.biome.as_str(), entry.seed));
+    let saved = World::load_world_file(&world.world.world_path()).unwrap();
+    assert_eq!(saved.maps["x0y0z0"].status, MapStatus::Generated);
+    assert_eq!(saved, world.world);
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn world_positions_are_range_checked() {
+    let (world, directory) = generated_world("maps_world_position_test", &[]);
+    assert_eq!(world.position(-1, 1, 0).unwrap(), (-1, 1, 0));
+    // Outside a bounded world, or past i32 (which used to wrap around to 0, 0, 0)
+    for &(x, y, z) in [(2, 0, 0), (0, 0, -2), (1 << 32, 0, 0), (0, i64::min_value(), 0), (0, 0, 1 << 32)].iter() {
+        assert_eq!(world.position(x, y, z).unwrap_err().code(), 9, "{} {} {}", x, y, z);
+    }
+    let unbounded = World {size_x: UNBOUNDED, size_y: UNBOUNDED, size_z: UNBOUNDED, ..world.clone()};
+    assert_eq!(unbounded.position(i64::from(i32::max_value()), -7, 3).unwrap(), (i32::max_value(), -7, 3));
+    assert!(unbounded.position(i64::from(i32::max_value()) + 1, 0, 0).is_err());
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn lazy_world_keeps_recently_used_maps() {
+    let (mut world, directory) = lazy_world("maps_lazy_world_lru_test", 7, 2);
+    let first = world.map("x0y0z0").unwrap().clone();
+    world.map("x1y0z0").unwrap();
+    assert_eq!(world.resident(), vec!["x0y0z0", "x1y0z0"]);
+    // Using a map makes it the most recent one, so x1y0z0 is dropped next
+    world.map("x0y0z0").unwrap();
+    world.map_at(2, 0, 0).unwrap();
+    assert_eq!(world.resident(), vec!["x0y0z0", "x2y0z0"]);
+    assert!(!world.is_resident("x1y0z0"));
+    world.map("x2y0z0").unwrap();
+    world.map("x1y0z0").unwrap();
+    assert_eq!(world.resident(), vec!["x2y0z0", "x1y0z0"]);
+    // Dropped maps come back from their file unchanged
+    let again = world.map("x0y0z0").unwrap();
+    assert_eq!(again.header, first.header);
+    assert_eq!(again.tileset.to_ids(), first.tileset.to_ids());
+    assert_eq!(world.world.maps.len(), 3);
+    // Map names that aren't world positions, or are outside a bounded world, have no map
+    assert!(world.map("spawn").is_err());
+    world.world.size_z = 1;
+    assert!(world.map("x0y0z2").is_err());
+    // Bad map sizes in the world file are an error, not a panic, and nothing is generated
+    world.world.map_size_x = 0;
+    assert_eq!(world.map("x5y5z0").err().map(|e| e.code()), Some(11));
+    assert_ne!(world.world.maps.get("x5y5z0").map(|entry| entry.status), Some(MapStatus::Generated));
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn biomes_follow_the_world_climate() {
+    let climate = ClimateModel::builtin();
+    let (mut same, mut total) = (0, 0);
+    for x in -20..20 {
+        for y in -20..20 {
+            let here = climate.climate_at(9, x, y, 0);
+            let east = climate.climate_at(9, x + 1, y, 0);
+            // Fields change a little between neighbors
+            assert!((here.elevation - east.elevation).abs() < 0.35, "{} {}", x, y);
+            assert!((here.moisture - east.moisture).abs() < 0.35, "{} {}", x, y);
+            let biome = climate.biome_for(&here);
+            assert!(["Ocean", "Cave", "Desert", "Forest"].contains(&biome.as_str()), "{}", biome);
+            if here.elevation <= 0.35 {
+                assert_eq!(biome, "Ocean");
+            }
+            same += (biome == climate.biome_for(&east)) as i32;
+            total += 1;
+            // Underground levels only get underground biomes, lakes under wet ground near the surface
+            let below = climate.climate_at(9, x, y, -1);
+            assert_eq!((below.elevation, below.moisture, below.depth), (here.elevation, here.moisture, 1));
+            let expected = if below.moisture >= 0.55 { "Underlake" } else { "Cave" };
+            assert_eq!(climate.biome_for(&below), expected);
+            assert_eq!(climate.biome_at(9, x, y, -5), "Cave");
+        }
+    }
+    // A random biome per map would share its neighbor's biome about a quarter of the time
+    assert!(same * 10 > total * 6, "{}/{}", same, total);
+    assert_eq!(climate.climate_at(9, 3, 4, 0), climate.climate_at(9, 3, 4, 0));
+    assert_ne!(climate.climate_at(9, 3, 4, 0), climate.climate_at(10, 3, 4, 0));
+
+    // Custom rules, first match wins and anything unmatched gets the fallback
+    let custom = ClimateModel::from_definition("custom.toml", r#"
+        scale = 4.0
+        octaves = 2
+        sea_level = 0.5
+        lapse_rate = 0.0
+        fallback = "Forest"
+        [[rules]]
+        biome = "Desert"
+        elevation = [0.5, 1.0]
+        [[rules]]
+        biome = "Underlake"
+        depth = [1, 1]
+    "#).unwrap();
+    let (mut world, directory) = lazy_world("maps_climate_world_test", 9, 4);
+    world.world.climate = custom.clone();
+    for x in -3..3 {
+        let name = World::get_map_name(x, 0, 0);
+        let high = custom.climate_at(9, x, 0, 0).elevation >= 0.5;
+        assert_eq!(world.world.plan_map(&name).unwrap().biome, if high { "Desert" } else { "Forest" });
+    }
+    assert_eq!(world.world.plan_map("x0y0z-1").unwrap().biome, "Underlake");
+    assert_eq!(world.world.plan_map("x0y0z-2").unwrap().biome, "Forest");
+    assert_eq!(world.map("x0y0z-1").unwrap().header.biome, "Underlake");
+    // The climate is part of the world file
+    assert_eq!(World::load_world_file(&world.world.world_path()).unwrap().climate, custom);
+
+    let bad_range = r#"{"scale": 4.0, "octaves": 2, "sea_level": 0.5, "lapse_rate": 0.0, "fallback": "Cave",
+                        "rules": [{"biome": "Ocean", "moisture": [0.8, 0.2]}]}"#;
+    assert!(ClimateModel::from_definition("bad.json", bad_range).is_err());
+    assert!(ClimateModel::from_definition("bad.json", &bad_range.replace("\"scale\": 4.0", "\"scale\": 0.0")).is_err());
+    std::fs::remove_dir_all(&directory).unwrap();
+}